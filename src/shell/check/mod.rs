@@ -0,0 +1,424 @@
+use super::syntax;
+use super::syntax::ast::{Argument, Expr};
+use super::syntax::tokens::{StringLiteralComponent, Token};
+use super::jobs::{resolve_command, BuiltinHandler, JobManager};
+use nom;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::prelude::*;
+use std::os::unix::io::RawFd;
+
+mod report;
+pub use self::report::Format;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+}
+
+struct Assignment {
+    line: usize,
+    column: usize,
+}
+
+struct Checker<'h, B: BuiltinHandler + 'h> {
+    handler: &'h mut B,
+    diagnostics: Vec<Diagnostic>,
+    assignments: HashMap<String, Assignment>,
+    used_variables: HashSet<String>,
+    aliases: HashSet<String>,
+    /// File descriptors the shell itself has open, changed by `exec` with only redirections.
+    shell_fds: HashSet<RawFd>,
+    exit_line: Option<usize>,
+    reported_unreachable: bool,
+}
+
+/// Position of a lexed line within the script, used to turn token slices back into columns.
+struct Line<'a> {
+    number: usize,
+    source: &'a str,
+}
+
+impl<'a> Line<'a> {
+    fn offset_of(&self, s: &str) -> Option<usize> {
+        let start = self.source.as_ptr() as usize;
+        let ptr = s.as_ptr() as usize;
+        if ptr >= start && ptr <= start + self.source.len() {
+            Some(ptr - start)
+        } else {
+            None
+        }
+    }
+
+    fn column(&self, offset: usize) -> usize {
+        self.source[..offset].chars().count() + 1
+    }
+
+    // the lexer only recognizes double quotes, and they cannot be escaped inside a quoted string
+    fn in_double_quotes(&self, offset: usize) -> bool {
+        let mut inside = false;
+        let mut escaped = false;
+        for c in self.source[..offset].chars() {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' && !inside {
+                escaped = true;
+            } else if c == '"' {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+}
+
+fn components_offset(line: &Line, components: &[StringLiteralComponent]) -> Option<usize> {
+    for component in components {
+        let offset = match component {
+            &StringLiteralComponent::Literal(s) => line.offset_of(s),
+            // the slice starts at the variable name, skip back over `${`
+            &StringLiteralComponent::EnvVar(s) => line.offset_of(s).map(|o| o.saturating_sub(2)),
+            &StringLiteralComponent::Brace(ref parts) => parts.first().and_then(|s| line.offset_of(s)).map(|o| o.saturating_sub(1)),
+        };
+        if offset.is_some() {
+            return offset;
+        }
+    }
+    None
+}
+
+fn expr_offset(line: &Line, expr: &Expr) -> Option<usize> {
+    match expr {
+        &Expr::Command(binary, _) => components_offset(line, binary),
+        &Expr::Pipeline(ref first, _) => expr_offset(line, first),
+    }
+}
+
+fn literal_string(components: &[StringLiteralComponent]) -> Option<String> {
+    let mut result = String::new();
+    for component in components {
+        match component {
+            &StringLiteralComponent::Literal(s) => result.push_str(s),
+            _ => return None,
+        }
+    }
+    Some(result)
+}
+
+impl<'h, B: BuiltinHandler + 'h> Checker<'h, B> {
+    fn new(handler: &'h mut B) -> Checker<'h, B> {
+        Checker {
+            handler: handler,
+            diagnostics: Vec::new(),
+            assignments: HashMap::new(),
+            used_variables: HashSet::new(),
+            aliases: HashSet::new(),
+            shell_fds: [0, 1, 2].iter().cloned().collect(),
+            exit_line: None,
+            reported_unreachable: false,
+        }
+    }
+
+    fn report(&mut self, line: &Line, offset: Option<usize>, severity: Severity, code: &'static str, message: String) {
+        self.diagnostics.push(Diagnostic {
+            line: line.number,
+            column: offset.map(|o| line.column(o)).unwrap_or(1),
+            severity: severity,
+            code: code,
+            message: message,
+        });
+    }
+
+    fn check_line(&mut self, number: usize, source: &str) {
+        let trimmed = source.trim();
+        if trimmed.len() == 0 || trimmed.starts_with("#") {
+            return;
+        }
+        let line = Line {
+            number: number,
+            source: source,
+        };
+        if let Some(exit_line) = self.exit_line {
+            if !self.reported_unreachable {
+                self.reported_unreachable = true;
+                let offset = source.find(trimmed);
+                self.report(&line, offset, Severity::Warning, "unreachable", format!("unreachable code after `exit` on line {}", exit_line));
+            }
+        }
+        match syntax::lexer::lex(source) {
+            nom::IResult::Done(remaining, tokens) => {
+                if remaining.len() == 0 {
+                    if let Some(condition) = syntax::parser::split_conditional(&tokens) {
+                        // words inside `[[ ]]` are not split, so only their use is recorded
+                        for token in condition {
+                            if let &Token::StringLiteral(ref components) = token {
+                                for component in components {
+                                    if let &StringLiteralComponent::EnvVar(name) = component {
+                                        self.used_variables.insert(name.to_owned());
+                                    }
+                                }
+                            }
+                        }
+                        return;
+                    }
+                    match syntax::parser::parse(&tokens) {
+                        Ok(expr) => self.check_toplevel(&line, &expr),
+                        Err(error) => {
+                            self.report(&line, None, Severity::Error, "syntax", format!("syntax error: {:?}", error));
+                        }
+                    }
+                } else {
+                    let offset = line.offset_of(remaining);
+                    self.report(&line, offset, Severity::Error, "syntax", format!("extraneous characters `{}`", remaining.trim()));
+                }
+            }
+            _ => {
+                self.report(&line, None, Severity::Error, "syntax", String::from("failed to lex line"));
+            }
+        }
+    }
+
+    fn check_toplevel(&mut self, line: &Line, expr: &Expr) {
+        self.check_expr(line, expr);
+        if let &Expr::Command(binary, ref arguments) = expr {
+            let background = arguments.iter().any(|a| a == &Argument::Background);
+            if !background && literal_string(binary).map(|b| b == "exit").unwrap_or(false) && self.exit_line.is_none() {
+                self.exit_line = Some(line.number);
+            }
+        }
+    }
+
+    fn check_expr(&mut self, line: &Line, expr: &Expr) {
+        match expr {
+            &Expr::Command(binary, ref arguments) => {
+                self.check_expansions(line, binary);
+                self.check_command_name(line, binary);
+                if literal_string(binary).map(|b| b == "set").unwrap_or(false) && arguments.len() >= 2 {
+                    if let &Argument::Literal(name) = &arguments[0] {
+                        self.record_assignment(line, name);
+                    }
+                }
+                if literal_string(binary).map(|b| b == "alias").unwrap_or(false) {
+                    for arg in arguments {
+                        if let &Argument::Literal(components) = arg {
+                            if let Some(definition) = literal_string(components) {
+                                if let Some(index) = definition.find('=') {
+                                    self.aliases.insert(definition[..index].to_owned());
+                                }
+                            }
+                        }
+                    }
+                }
+                let mut open_fds = self.shell_fds.clone();
+                for arg in arguments {
+                    match arg {
+                        &Argument::Literal(components) => {
+                            self.check_expansions(line, components);
+                        }
+                        &Argument::Redirect(fd, target) | &Argument::Append(fd, target) | &Argument::Input(fd, target) => {
+                            self.check_expansions(line, target);
+                            open_fds.insert(fd);
+                        }
+                        &Argument::RedirectFD(fd, target) => {
+                            if !open_fds.contains(&target) {
+                                let offset = line.source.find(&format!("{}>&{}", fd, target)).or_else(|| line.source.find(&format!(">&{}", target)));
+                                self.report(
+                                    line,
+                                    offset,
+                                    Severity::Error,
+                                    "bad-fd",
+                                    format!("redirection of fd {} refers to fd {}, which is not open", fd, target),
+                                );
+                            }
+                            open_fds.insert(fd);
+                        }
+                        &Argument::Close(fd) => {
+                            open_fds.remove(&fd);
+                        }
+                        &Argument::Subshell(ref subexpr) => {
+                            let offset = expr_offset(line, subexpr).and_then(|o| line.source[..o].rfind('`'));
+                            self.report(line, offset, Severity::Warning, "backtick", String::from("use of legacy backtick subshell"));
+                            self.check_expr(line, subexpr);
+                        }
+                        &Argument::Background => {}
+                    }
+                }
+                let literal_arguments = arguments.iter().any(|a| if let &Argument::Literal(_) = a { true } else { false });
+                if literal_string(binary).map(|b| b == "exec").unwrap_or(false) && !literal_arguments {
+                    self.shell_fds = open_fds;
+                }
+            }
+            &Expr::Pipeline(ref first, ref second) => {
+                self.check_expr(line, first);
+                self.check_expr(line, second);
+            }
+        }
+    }
+
+    fn check_expansions(&mut self, line: &Line, components: &[StringLiteralComponent]) {
+        for component in components {
+            if let &StringLiteralComponent::EnvVar(name) = component {
+                self.used_variables.insert(name.to_owned());
+                // `~` expands to a static `HOME` slice that is not part of the line
+                if let Some(offset) = line.offset_of(name).map(|o| o.saturating_sub(2)) {
+                    if !line.in_double_quotes(offset) {
+                        self.report(
+                            line,
+                            Some(offset),
+                            Severity::Warning,
+                            "word-split",
+                            format!("unquoted expansion `${{{}}}` may be split or globbed; quote it", name),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_command_name(&mut self, line: &Line, binary: &[StringLiteralComponent]) {
+        if let Some(name) = literal_string(binary) {
+            if !self.aliases.contains(&name) && !self.handler.is_builtin(&name) && resolve_command(&name).is_err() {
+                let offset = components_offset(line, binary);
+                self.report(line, offset, Severity::Error, "unknown-command", format!("`{}` is not an alias or builtin and was not found in PATH", name));
+            }
+        }
+    }
+
+    fn record_assignment(&mut self, line: &Line, name: &[StringLiteralComponent]) {
+        if let Some(var) = literal_string(name) {
+            let offset = components_offset(line, name);
+            self.assignments.insert(
+                var,
+                Assignment {
+                    line: line.number,
+                    column: offset.map(|o| line.column(o)).unwrap_or(1),
+                },
+            );
+        }
+    }
+
+    fn finish(mut self) -> Vec<Diagnostic> {
+        let mut unused: Vec<(&String, &Assignment)> = self.assignments
+            .iter()
+            .filter(|&(name, _)| !self.used_variables.contains(name))
+            .collect();
+        unused.sort_by_key(|&(_, a)| (a.line, a.column));
+        for (name, assignment) in unused {
+            self.diagnostics.push(Diagnostic {
+                line: assignment.line,
+                column: assignment.column,
+                severity: Severity::Warning,
+                code: "unused-variable",
+                message: format!("variable `{}` is assigned but never used", name),
+            });
+        }
+        self.diagnostics.sort_by_key(|d| (d.line, d.column));
+        self.diagnostics
+    }
+}
+
+pub fn check_source<B: BuiltinHandler>(source: &str, handler: &mut B) -> Vec<Diagnostic> {
+    let mut checker = Checker::new(handler);
+    for (index, line) in source.lines().enumerate() {
+        checker.check_line(index + 1, line);
+    }
+    checker.finish()
+}
+
+/// Entry point for `radish --check`: lints each file and prints the diagnostics.
+/// Returns 1 if any diagnostics were reported, 2 if a file could not be read.
+pub fn run(files: &[String], format: Format) -> i8 {
+    let mut job_manager = JobManager::new();
+    let mut results = Vec::new();
+    let mut status = 0;
+    for file in files {
+        let mut contents = String::new();
+        match File::open(file).and_then(|mut f| f.read_to_string(&mut contents)) {
+            Ok(_) => {
+                let diagnostics = check_source(&contents, &mut job_manager);
+                if diagnostics.len() > 0 && status == 0 {
+                    status = 1;
+                }
+                results.push((file.as_ref(), diagnostics));
+            }
+            Err(e) => {
+                eprintln!("radish: {}: {}", file, e);
+                status = 2;
+            }
+        }
+    }
+    report::print(&results, format);
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The position and code of every diagnostic for `source`.
+    fn check(source: &str) -> Vec<(usize, usize, &'static str)> {
+        check_source(source, &mut JobManager::new()).into_iter().map(|d| (d.line, d.column, d.code)).collect()
+    }
+
+    #[test]
+    fn test_clean_script() {
+        assert_eq!(check("set name world\necho \"hello ${name}\"\n# a comment\n\n"), vec![]);
+    }
+
+    #[test]
+    fn test_diagnostics_and_columns() {
+        let source = "echo ${HOME}\n  no-such-command-for-radish arg\necho `pwd`\nset unused 1\n";
+        assert_eq!(
+            check(source),
+            vec![(1, 6, "word-split"), (2, 3, "unknown-command"), (3, 6, "backtick"), (4, 5, "unused-variable")]
+        );
+    }
+
+    #[test]
+    fn test_columns_count_characters() {
+        assert_eq!(check("echo \"é\" ${HOME}"), vec![(1, 10, "word-split")]);
+    }
+
+    #[test]
+    fn test_aliases_are_known_commands() {
+        assert_eq!(check("alias greet=echo\ngreet hi"), vec![]);
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        assert_eq!(check("echo ${"), vec![(1, 6, "syntax")]);
+    }
+
+    #[test]
+    fn test_unreachable_after_exit() {
+        assert_eq!(check("echo a\nexit 0\n\n# done\necho b\necho c"), vec![(5, 1, "unreachable")]);
+        assert_eq!(check("exit\n    echo b"), vec![(2, 5, "unreachable")]);
+        // an `exit` in the background does not end the script
+        assert_eq!(check("exit &\necho b"), vec![]);
+    }
+
+    #[test]
+    fn test_bad_fd_redirections() {
+        assert_eq!(check("echo hi 2>&1"), vec![]);
+        assert_eq!(check("echo hi 2>&3"), vec![(1, 9, "bad-fd")]);
+        assert_eq!(check("echo hi 3>log 2>&3"), vec![]);
+        assert_eq!(check("echo hi 1>&- 2>&1"), vec![(1, 14, "bad-fd")]);
+    }
+
+    #[test]
+    fn test_exec_opens_fds_for_later_lines() {
+        assert_eq!(check("exec 3>log\necho hi 2>&3"), vec![]);
+        assert_eq!(check("exec 3>log\nexec 3>&-\necho hi 2>&3"), vec![(3, 9, "bad-fd")]);
+        // `exec` with a command does not change the shell's descriptors
+        assert_eq!(check("exec 3>log echo hi\necho hi 2>&3"), vec![(2, 9, "bad-fd")]);
+    }
+}
@@ -0,0 +1,121 @@
+use super::{Diagnostic, Severity};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+impl Severity {
+    fn name(&self) -> &'static str {
+        match *self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+fn json_escape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+/// One diagnostic per line, as `file:line:column: severity: message [code]`.
+fn format_text(results: &[(&str, Vec<Diagnostic>)]) -> Vec<String> {
+    let mut lines = Vec::new();
+    for &(file, ref diagnostics) in results {
+        for d in diagnostics {
+            lines.push(format!("{}:{}:{}: {}: {} [{}]", file, d.line, d.column, d.severity.name(), d.message, d.code));
+        }
+    }
+    lines
+}
+
+/// All diagnostics as a single JSON array.
+fn format_json(results: &[(&str, Vec<Diagnostic>)]) -> String {
+    let mut entries = Vec::new();
+    for &(file, ref diagnostics) in results {
+        for d in diagnostics {
+            entries.push(format!(
+                "{{\"file\":{},\"line\":{},\"column\":{},\"severity\":\"{}\",\"code\":\"{}\",\"message\":{}}}",
+                json_escape(file),
+                d.line,
+                d.column,
+                d.severity.name(),
+                d.code,
+                json_escape(&d.message)
+            ));
+        }
+    }
+    format!("[{}]", entries.join(","))
+}
+
+pub fn print(results: &[(&str, Vec<Diagnostic>)], format: Format) {
+    match format {
+        Format::Text => for line in format_text(results) {
+            println!("{}", line);
+        },
+        Format::Json => println!("{}", format_json(results)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(line: usize, column: usize, severity: Severity, code: &'static str, message: &str) -> Diagnostic {
+        Diagnostic {
+            line: line,
+            column: column,
+            severity: severity,
+            code: code,
+            message: String::from(message),
+        }
+    }
+
+    #[test]
+    fn test_json_escape() {
+        assert_eq!(json_escape("plain"), "\"plain\"");
+        assert_eq!(json_escape("say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(json_escape("a\\b"), "\"a\\\\b\"");
+        assert_eq!(json_escape("tab\tnew\nline\r"), "\"tab\\tnew\\nline\\r\"");
+        assert_eq!(json_escape("bell\u{7}"), "\"bell\\u0007\"");
+        assert_eq!(json_escape("é"), "\"é\"");
+    }
+
+    #[test]
+    fn test_text_format() {
+        let results = vec![("a.rsh", vec![diagnostic(3, 6, Severity::Warning, "word-split", "quote it")])];
+        assert_eq!(format_text(&results), vec!["a.rsh:3:6: warning: quote it [word-split]"]);
+    }
+
+    #[test]
+    fn test_json_format() {
+        let results = vec![
+            ("a.rsh", vec![diagnostic(1, 1, Severity::Error, "syntax", "bad `\"`")]),
+            ("b.rsh", Vec::new()),
+            ("c\".rsh", vec![diagnostic(2, 4, Severity::Warning, "backtick", "legacy")]),
+        ];
+        assert_eq!(
+            format_json(&results),
+            concat!(
+                "[{\"file\":\"a.rsh\",\"line\":1,\"column\":1,\"severity\":\"error\",\"code\":\"syntax\",\"message\":\"bad `\\\"`\"},",
+                "{\"file\":\"c\\\".rsh\",\"line\":2,\"column\":4,\"severity\":\"warning\",\"code\":\"backtick\",\"message\":\"legacy\"}]"
+            )
+        );
+        assert_eq!(format_json(&[]), "[]");
+    }
+}
@@ -0,0 +1,54 @@
+use super::Engine;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+pub struct AliasCompletion {
+    aliases: Arc<RwLock<HashMap<String, String>>>,
+}
+
+impl AliasCompletion {
+    pub fn new(aliases: Arc<RwLock<HashMap<String, String>>>) -> AliasCompletion {
+        AliasCompletion { aliases: aliases }
+    }
+}
+
+/// Returns true if `start` is the word in command position, i.e. it is the first word on the
+/// line or directly follows a pipe or an opening backtick.
+pub fn in_command_position(start: &str, line: &str) -> bool {
+    match line.rfind(start) {
+        Some(index) => {
+            let before = line[..index].trim_end();
+            before.is_empty() || before.ends_with('|') || before.ends_with('`')
+        }
+        None => false,
+    }
+}
+
+impl Engine for AliasCompletion {
+    fn completions<'a>(&'a mut self, start: &str, line: &str) -> Option<Vec<(Cow<'a, str>, Cow<'a, str>)>> {
+        if !in_command_position(start, line) {
+            return None;
+        }
+        let aliases = self.aliases.read().unwrap();
+        let mut matches: Vec<(Cow<str>, Cow<str>)> = aliases
+            .iter()
+            .filter(|&(name, _)| name.starts_with(start))
+            .map(|(name, value)| (Cow::Owned(name.clone()), Cow::Owned(value.clone())))
+            .collect();
+        if matches.is_empty() {
+            None
+        } else {
+            matches.sort();
+            Some(matches)
+        }
+    }
+
+    fn category<'a>(&'a self) -> &'a str {
+        "Aliases"
+    }
+
+    fn is_command(&self, word: &str) -> bool {
+        self.aliases.read().unwrap().contains_key(word)
+    }
+}
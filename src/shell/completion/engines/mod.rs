@@ -4,9 +4,16 @@ pub use self::path_completion::*;
 pub mod user_completion;
 pub use self::user_completion::*;
 
+pub mod alias_completion;
+pub use self::alias_completion::*;
+
 use std::borrow::Cow;
 
 pub trait Engine {
     fn completions<'a>(&'a mut self, start: &str, line: &str) -> Option<Vec<(Cow<'a, str>, Cow<'a, str>)>>;
     fn category<'a>(&'a self) -> &'a str;
+    /// Whether `word` in command position names something this engine knows can be run.
+    fn is_command(&self, _word: &str) -> bool {
+        false
+    }
 }
@@ -1,15 +1,19 @@
 use super::Engine;
+use super::in_command_position;
+use super::super::super::jobs;
+use std::env;
 use std::fs;
 use std::collections::BTreeSet;
 use std::borrow::Cow;
-use std::path::{self, Path};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{self, Path, PathBuf};
 
 // from https://github.com/kkawakam/rustyline/blob/master/src/completion.rs, MIT license
 
 impl Engine for PathCompletion {
     fn completions<'a>(&'a mut self, start: &str, line: &str) -> Option<Vec<(Cow<'a, str>, Cow<'a, str>)>> {
         let path = unescape(start, ESCAPE_CHAR);
-        let matches = filename_complete(&path, ESCAPE_CHAR, &self.break_chars);
+        let matches = filename_complete(&path, ESCAPE_CHAR, &self.break_chars, in_command_position(start, line));
         if matches.is_empty() {
             None
         } else {
@@ -20,6 +24,34 @@ impl Engine for PathCompletion {
     fn category<'a>(&'a self) -> &'a str {
         "Files"
     }
+
+    fn is_command(&self, word: &str) -> bool {
+        let path = expand_home(word);
+        if path.is_dir() {
+            // run with `cd` when `autocd` is set
+            true
+        } else if word.contains(path::MAIN_SEPARATOR) {
+            is_executable(&path)
+        } else {
+            env::var_os("PATH").and_then(|paths| jobs::search_path(word, &paths)).is_some()
+        }
+    }
+}
+
+/// Replaces a leading `~` with the home directory, as the lexer does for the command word.
+fn expand_home(word: &str) -> PathBuf {
+    let path = Path::new(word);
+    match (path.strip_prefix("~"), env::var_os("HOME")) {
+        (Ok(rel_path), Some(home)) => Path::new(&home).join(rel_path),
+        _ => path.to_path_buf(),
+    }
+}
+
+fn is_executable(path: &Path) -> bool {
+    match fs::metadata(path) {
+        Ok(metadata) => metadata.is_file() && metadata.permissions().mode() & 0o111 != 0,
+        Err(_) => false,
+    }
 }
 
 pub struct PathCompletion {
@@ -88,7 +120,8 @@ pub fn escape(input: String, esc_char: Option<char>, break_chars: &BTreeSet<char
 
 fn filename_complete(path: &str,
                      esc_char: Option<char>,
-                     break_chars: &BTreeSet<char>)
+                     break_chars: &BTreeSet<char>,
+                     command: bool)
                      -> Vec<(Cow<'static, str>, Cow<'static, str>)> {
     use std::env::{current_dir, home_dir};
 
@@ -125,6 +158,11 @@ fn filename_complete(path: &str,
             if let Ok(entry) = entry {
                 if let Some(s) = entry.file_name().to_str() {
                     if s.starts_with(file_name) || file_name.is_empty() {
+                        // a command can be a directory or, given with its directory, an
+                        // executable; other files are left out
+                        if command && !entry.path().is_dir() && (dir_name.is_empty() || !is_executable(&entry.path())) {
+                            continue;
+                        }
                         let mut path = String::from(dir_name) + s;
                         let metadata = fs::symlink_metadata(entry.path());
                         if let Ok(metadata) = metadata {
@@ -8,6 +8,7 @@ use self::fuzzy::fuzzy_search;
 
 use self::engines::Engine;
 use super::history::History;
+use super::jobs;
 
 #[derive(Debug)]
 pub struct Completion {
@@ -106,6 +107,12 @@ impl<'a> Completer<'a> {
     pub fn new(engines: Vec<Box<Engine + 'a>>) -> Completer<'a> {
         Completer { engines: engines }
     }
+    /// Whether `word` can be run as a command: a builtin, a keyword, or anything one of the
+    /// engines recognises, such as an alias, an executable or a directory to change into.
+    pub fn is_command(&self, word: &str) -> bool {
+        jobs::is_builtin_or_keyword(word) || self.engines.iter().any(|engine| engine.is_command(word))
+    }
+
     pub fn completions(&mut self, start: &str, line: &[char]) -> Completions {
         let line_string = line.iter().cloned().collect::<String>();
         let mut sets = HashMap::new();
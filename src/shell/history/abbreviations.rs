@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+use super::diesel;
+use super::diesel::prelude::*;
+use super::model::{Abbreviation, NewAbbreviation};
+use super::schema::abbreviations;
+use super::schema::abbreviations::dsl::*;
+
+/// Fish-style abbreviations, persisted in the history database.
+pub struct Abbreviations {
+    connection: SqliteConnection,
+    entries: HashMap<String, String>,
+}
+
+impl Abbreviations {
+    pub fn new(database_url: &str) -> Result<Abbreviations, ConnectionError> {
+        let connection = SqliteConnection::establish(database_url)?;
+        diesel::sql_query(
+            "CREATE TABLE IF NOT EXISTS abbreviations (
+                           name text PRIMARY KEY,
+                           expansion text
+                          );",
+        ).execute(&connection)
+            .map_err(|e| {
+                use std::error::Error;
+                ConnectionError::BadConnection(e.description().to_owned())
+            })?;
+        let entries = Abbreviations::load_entries(&connection);
+        Ok(Abbreviations {
+            connection: connection,
+            entries: entries,
+        })
+    }
+
+    fn load_entries(connection: &SqliteConnection) -> HashMap<String, String> {
+        match abbreviations.load::<Abbreviation>(connection) {
+            Ok(rows) => rows.into_iter().map(|a| (a.name, a.expansion)).collect(),
+            Err(_) => HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, abbreviation: &str) -> Option<&String> {
+        self.entries.get(abbreviation)
+    }
+
+    /// Returns the expansion of `word` if it is an abbreviation in command position, that is the
+    /// first word on the line or one following a pipe. `previous` is the word before it.
+    pub fn expand(&self, word: &str, previous: Option<&str>) -> Option<&String> {
+        match previous {
+            Some(previous) if !previous.ends_with('|') => None,
+            _ => self.entries.get(word),
+        }
+    }
+
+    pub fn entries(&self) -> &HashMap<String, String> {
+        &self.entries
+    }
+
+    pub fn insert(&mut self, abbreviation: &str, value: &str) -> Result<(), ()> {
+        match diesel::replace_into(abbreviations::table)
+            .values(&NewAbbreviation {
+                name: abbreviation,
+                expansion: value,
+            })
+            .execute(&self.connection)
+        {
+            Ok(_) => {
+                self.entries.insert(abbreviation.to_owned(), value.to_owned());
+                Ok(())
+            }
+            Err(e) => {
+                eprintln!("error: {:}", e);
+                Err(())
+            }
+        }
+    }
+
+    /// Returns `Ok(false)` if there was no such abbreviation.
+    pub fn remove(&mut self, abbreviation: &str) -> Result<bool, ()> {
+        match diesel::delete(abbreviations.filter(name.eq(abbreviation))).execute(&self.connection) {
+            Ok(_) => Ok(self.entries.remove(abbreviation).is_some()),
+            Err(e) => {
+                eprintln!("error: {:}", e);
+                Err(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn abbreviations(entries: &[(&str, &str)]) -> Abbreviations {
+        let mut abbreviations = Abbreviations::new(":memory:").unwrap();
+        for &(abbreviation, value) in entries {
+            abbreviations.insert(abbreviation, value).unwrap();
+        }
+        abbreviations
+    }
+
+    #[test]
+    fn test_expand_in_command_position() {
+        let abbreviations = abbreviations(&[("gco", "git checkout")]);
+        assert_eq!(abbreviations.expand("gco", None).map(|e| e.as_str()), Some("git checkout"));
+        assert_eq!(abbreviations.expand("gco", Some("|")).map(|e| e.as_str()), Some("git checkout"));
+        assert_eq!(abbreviations.expand("gco", Some("log|")).map(|e| e.as_str()), Some("git checkout"));
+        assert_eq!(abbreviations.expand("gco", Some("echo")), None);
+        assert_eq!(abbreviations.expand("gc", None), None);
+    }
+
+    #[test]
+    fn test_insert_replaces() {
+        let mut abbreviations = abbreviations(&[("l", "ls")]);
+        abbreviations.insert("l", "ls -l").unwrap();
+        assert_eq!(abbreviations.get("l").map(|e| e.as_str()), Some("ls -l"));
+        assert_eq!(abbreviations.entries().len(), 1);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut abbreviations = abbreviations(&[("l", "ls"), ("g", "git")]);
+        assert_eq!(abbreviations.remove("l"), Ok(true));
+        assert_eq!(abbreviations.remove("l"), Ok(false));
+        assert_eq!(abbreviations.get("l"), None);
+        assert_eq!(abbreviations.get("g").map(|e| e.as_str()), Some("git"));
+    }
+
+    #[test]
+    fn test_entries_persist() {
+        let abbreviations = abbreviations(&[("l", "ls"), ("g", "git")]);
+        let entries = Abbreviations::load_entries(&abbreviations.connection);
+        assert_eq!(&entries, abbreviations.entries());
+    }
+}
@@ -0,0 +1,465 @@
+use super::History;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    EventNotFound(String),
+    BadWordSpecifier(String),
+    BadModifier(String),
+    SubstitutionFailed,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Error::EventNotFound(ref event) => write!(f, "{}: event not found", event),
+            &Error::BadWordSpecifier(ref event) => write!(f, "{}: bad word specifier", event),
+            &Error::BadModifier(ref modifier) => write!(f, "{}: unrecognized history modifier", modifier),
+            &Error::SubstitutionFailed => write!(f, "substitution failed"),
+        }
+    }
+}
+
+/// Performs csh-style history expansion (`!!`, `!$`, `!n`, `!-n`, `!prefix`, `!?string?`,
+/// `^old^new`) with optional word designators and modifiers.
+///
+/// Nothing inside single quotes or after a backslash is expanded.
+pub fn expand_history(line: &str, history: &History) -> Result<String, Error> {
+    let commands: Vec<&str> = match history.entries() {
+        &Some(ref entries) => entries.iter().map(|e| e.command.as_ref()).collect(),
+        &None => Vec::new(),
+    };
+    expand_with(line, &commands)
+}
+
+/// Expands history references in `line` against `commands`, oldest first.
+fn expand_with(line: &str, commands: &[&str]) -> Result<String, Error> {
+    if line.starts_with('^') {
+        return quick_substitution(line, commands);
+    }
+    let chars: Vec<char> = line.chars().collect();
+    let mut result = String::new();
+    let mut index = 0;
+    let mut in_single_quote = false;
+    let mut in_double_quote = false;
+    while index < chars.len() {
+        let c = chars[index];
+        if in_single_quote {
+            if c == '\'' {
+                in_single_quote = false;
+            }
+            result.push(c);
+            index += 1;
+        } else if c == '\\' {
+            result.push(c);
+            if let Some(&next) = chars.get(index + 1) {
+                result.push(next);
+            }
+            index += 2;
+        } else if c == '"' {
+            in_double_quote = !in_double_quote;
+            result.push(c);
+            index += 1;
+        } else if c == '\'' && !in_double_quote {
+            in_single_quote = true;
+            result.push(c);
+            index += 1;
+        } else if c == '!' && starts_designator(chars.get(index + 1)) {
+            let (expansion, end) = expand_one(&chars, index, commands)?;
+            result.push_str(&expansion);
+            index = end;
+        } else {
+            result.push(c);
+            index += 1;
+        }
+    }
+    Ok(result)
+}
+
+fn starts_designator(next: Option<&char>) -> bool {
+    match next {
+        None => false,
+        Some(&c) => !(c.is_whitespace() || c == '=' || c == '(' || c == '"'),
+    }
+}
+
+fn event_not_found(chars: &[char], start: usize, end: usize) -> Error {
+    Error::EventNotFound(chars[start..end].iter().collect())
+}
+
+/// Expands the history reference starting at `chars[start]` (which is a `!`), returning the
+/// replacement text and the index just past the reference.
+fn expand_one(chars: &[char], start: usize, commands: &[&str]) -> Result<(String, usize), Error> {
+    let mut index = start + 1;
+    // `!$`, `!^`, `!*` and `!:n` are shorthand for the corresponding word of `!!`
+    let mut implicit_words = false;
+    let event: Option<&str> = match chars[index] {
+        '!' => {
+            index += 1;
+            commands.last().cloned()
+        }
+        '$' | '^' | '*' | ':' => {
+            implicit_words = true;
+            commands.last().cloned()
+        }
+        c if c == '-' || c.is_digit(10) => {
+            let negative = chars[index] == '-';
+            if negative {
+                index += 1;
+            }
+            let digits_start = index;
+            while index < chars.len() && chars[index].is_digit(10) {
+                index += 1;
+            }
+            let number: usize = chars[digits_start..index]
+                .iter()
+                .collect::<String>()
+                .parse()
+                .map_err(|_| event_not_found(chars, start, index))?;
+            if negative {
+                if number > 0 && number <= commands.len() {
+                    Some(commands[commands.len() - number])
+                } else {
+                    None
+                }
+            } else if number > 0 && number <= commands.len() {
+                Some(commands[number - 1])
+            } else {
+                None
+            }
+        }
+        '?' => {
+            index += 1;
+            let search_start = index;
+            while index < chars.len() && chars[index] != '?' {
+                index += 1;
+            }
+            let needle: String = chars[search_start..index].iter().collect();
+            if index < chars.len() {
+                index += 1;
+            }
+            commands.iter().rev().find(|c| c.contains(needle.as_str())).cloned()
+        }
+        _ => {
+            let prefix_start = index;
+            while index < chars.len() && !chars[index].is_whitespace() && chars[index] != ':' {
+                index += 1;
+            }
+            let prefix: String = chars[prefix_start..index].iter().collect();
+            commands.iter().rev().find(|c| c.starts_with(prefix.as_str())).cloned()
+        }
+    };
+    let event = match event {
+        Some(e) => e,
+        None => return Err(event_not_found(chars, start, index)),
+    };
+    let words = split_words(event);
+    let mut text = String::from(event);
+
+    // word designator
+    let designator_start = index;
+    if implicit_words && chars[index] != ':' {
+        let (selected, end) = parse_word_designator(chars, index, &words).ok_or_else(|| Error::BadWordSpecifier(chars[start..index + 1].iter().collect()))?;
+        text = selected;
+        index = end;
+    } else if index < chars.len() && chars[index] == ':' && chars.get(index + 1).map(|c| is_word_designator_start(*c)).unwrap_or(false) {
+        let (selected, end) = parse_word_designator(chars, index + 1, &words).ok_or_else(|| Error::BadWordSpecifier(chars[start..designator_start + 2].iter().collect()))?;
+        text = selected;
+        index = end;
+    }
+
+    // modifiers
+    while index + 1 < chars.len() && chars[index] == ':' {
+        let (modified, end) = apply_modifier(chars, index + 1, &text)?;
+        text = modified;
+        index = end;
+    }
+    Ok((text, index))
+}
+
+fn is_word_designator_start(c: char) -> bool {
+    c.is_digit(10) || c == '^' || c == '$' || c == '*' || c == '-'
+}
+
+fn parse_number(chars: &[char], mut index: usize) -> (Option<usize>, usize) {
+    let start = index;
+    while index < chars.len() && chars[index].is_digit(10) {
+        index += 1;
+    }
+    if start == index {
+        (None, index)
+    } else {
+        (chars[start..index].iter().collect::<String>().parse().ok(), index)
+    }
+}
+
+/// Parses a word designator (`0`, `n`, `^`, `$`, `*`, `n-m`, `n-$`, `n*`, `n-`, `-m`) and
+/// returns the selected words joined by spaces.
+fn parse_word_designator(chars: &[char], index: usize, words: &[String]) -> Option<(String, usize)> {
+    if words.len() == 0 {
+        return None;
+    }
+    let last = words.len() - 1;
+    let select = |from: usize, to: usize| -> Option<String> {
+        if from <= to && to <= last {
+            Some(words[from..to + 1].join(" "))
+        } else if from == to + 1 {
+            // an empty range such as `!*` on a command without arguments
+            Some(String::new())
+        } else {
+            None
+        }
+    };
+    match chars.get(index) {
+        Some(&'$') => select(last, last).map(|s| (s, index + 1)),
+        Some(&'^') => select(1, 1).map(|s| (s, index + 1)),
+        Some(&'*') => select(1, last).map(|s| (s, index + 1)),
+        Some(&'-') => match parse_number(chars, index + 1) {
+            (Some(to), end) => select(0, to).map(|s| (s, end)),
+            _ => None,
+        },
+        _ => {
+            let (from, end) = parse_number(chars, index);
+            let from = from?;
+            match chars.get(end) {
+                Some(&'*') => select(from, last).map(|s| (s, end + 1)),
+                Some(&'-') => match chars.get(end + 1) {
+                    Some(&'$') => select(from, last).map(|s| (s, end + 2)),
+                    _ => match parse_number(chars, end + 1) {
+                        (Some(to), to_end) => select(from, to).map(|s| (s, to_end)),
+                        // `n-` means from n to the second-to-last word
+                        (None, _) => if last == 0 {
+                            None
+                        } else {
+                            select(from, last - 1).map(|s| (s, end + 1))
+                        },
+                    },
+                },
+                _ => select(from, from).map(|s| (s, end)),
+            }
+        }
+    }
+}
+
+/// Applies the modifier starting at `chars[index]` (just after the `:`).
+fn apply_modifier(chars: &[char], index: usize, text: &str) -> Result<(String, usize), Error> {
+    match chars[index] {
+        'h' => Ok((
+            match text.rfind('/') {
+                Some(0) => String::from("/"),
+                Some(i) => String::from(&text[..i]),
+                None => String::from(text),
+            },
+            index + 1,
+        )),
+        't' => Ok((
+            match text.rfind('/') {
+                Some(i) => String::from(&text[i + 1..]),
+                None => String::from(text),
+            },
+            index + 1,
+        )),
+        'r' => Ok((
+            match extension_start(text) {
+                Some(i) => String::from(&text[..i]),
+                None => String::from(text),
+            },
+            index + 1,
+        )),
+        'e' => Ok((
+            match extension_start(text) {
+                Some(i) => String::from(&text[i..]),
+                None => String::new(),
+            },
+            index + 1,
+        )),
+        's' => substitute(chars, index + 1, text, false),
+        'g' if chars.get(index + 1) == Some(&'s') => substitute(chars, index + 2, text, true),
+        c => Err(Error::BadModifier(format!(":{}", c))),
+    }
+}
+
+fn extension_start(text: &str) -> Option<usize> {
+    let name_start = text.rfind('/').map(|i| i + 1).unwrap_or(0);
+    match text[name_start..].rfind('.') {
+        Some(0) | None => None,
+        Some(i) => Some(name_start + i),
+    }
+}
+
+/// Parses `/old/new/` (any delimiter) starting at `chars[index]` and applies it to `text`.
+/// The final delimiter may be omitted at the end of the line, and `&` in `new` stands for `old`.
+fn substitute(chars: &[char], index: usize, text: &str, global: bool) -> Result<(String, usize), Error> {
+    let delimiter = match chars.get(index) {
+        Some(&d) => d,
+        None => return Err(Error::SubstitutionFailed),
+    };
+    let mut parts = vec![String::new(), String::new()];
+    let mut part = 0;
+    let mut i = index + 1;
+    while i < chars.len() && part < 2 {
+        if chars[i] == '\\' && chars.get(i + 1) == Some(&delimiter) {
+            parts[part].push(delimiter);
+            i += 2;
+        } else if chars[i] == delimiter {
+            part += 1;
+            i += 1;
+        } else {
+            parts[part].push(chars[i]);
+            i += 1;
+        }
+    }
+    let old = &parts[0];
+    let new = parts[1].replace("&", old);
+    if old.is_empty() || !text.contains(old.as_str()) {
+        return Err(Error::SubstitutionFailed);
+    }
+    if global {
+        Ok((text.replace(old.as_str(), &new), i))
+    } else {
+        Ok((text.replacen(old.as_str(), &new, 1), i))
+    }
+}
+
+/// `^old^new^` is equivalent to `!!:s/old/new/`.
+fn quick_substitution(line: &str, commands: &[&str]) -> Result<String, Error> {
+    let last = match commands.last() {
+        Some(c) => c,
+        None => return Err(Error::EventNotFound(String::from("^"))),
+    };
+    let chars: Vec<char> = line.chars().collect();
+    let (result, end) = substitute(&chars, 0, last, false)?;
+    let rest: String = chars[end..].iter().collect();
+    Ok(result + &rest)
+}
+
+/// Splits a command into words on whitespace, keeping quoted strings together.
+fn split_words(command: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for c in command.chars() {
+        if escaped {
+            current.push(c);
+            escaped = false;
+        } else if c == '\\' {
+            current.push(c);
+            escaped = true;
+        } else if let Some(q) = quote {
+            current.push(c);
+            if c == q {
+                quote = None;
+            }
+        } else if c == '"' || c == '\'' {
+            current.push(c);
+            quote = Some(c);
+        } else if c.is_whitespace() {
+            if !current.is_empty() {
+                words.push(current);
+                current = String::new();
+            }
+        } else {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMANDS: &[&str] = &["ls -l /tmp", "git commit -m 'first change'", "cat src/main.rs notes.txt.bak", "echo one two three"];
+
+    fn expand(line: &str) -> String {
+        expand_with(line, COMMANDS).unwrap()
+    }
+
+    fn error(line: &str) -> String {
+        expand_with(line, COMMANDS).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_events() {
+        assert_eq!(expand("!!"), "echo one two three");
+        assert_eq!(expand("sudo !!"), "sudo echo one two three");
+        assert_eq!(expand("!1"), "ls -l /tmp");
+        assert_eq!(expand("!-1"), "echo one two three");
+        assert_eq!(expand("!-4"), "ls -l /tmp");
+        assert_eq!(expand("!git"), "git commit -m 'first change'");
+        assert_eq!(expand("!c"), "cat src/main.rs notes.txt.bak");
+        assert_eq!(expand("!?commit?"), "git commit -m 'first change'");
+        assert_eq!(expand("!?main"), "cat src/main.rs notes.txt.bak");
+    }
+
+    #[test]
+    fn test_missing_events() {
+        assert_eq!(error("!0"), "!0: event not found");
+        assert_eq!(error("!5"), "!5: event not found");
+        assert_eq!(error("!-5"), "!-5: event not found");
+        assert_eq!(error("!make"), "!make: event not found");
+        assert_eq!(expand_with("!!", &[]).unwrap_err().to_string(), "!!: event not found");
+    }
+
+    #[test]
+    fn test_word_designators() {
+        assert_eq!(expand("!$"), "three");
+        assert_eq!(expand("!^"), "one");
+        assert_eq!(expand("!*"), "one two three");
+        assert_eq!(expand("!:0"), "echo");
+        assert_eq!(expand("!!:2"), "two");
+        assert_eq!(expand("!!:1-2"), "one two");
+        assert_eq!(expand("!!:2-$"), "two three");
+        assert_eq!(expand("!!:1*"), "one two three");
+        assert_eq!(expand("!!:1-"), "one two");
+        assert_eq!(expand("!!:-2"), "echo one two");
+        assert_eq!(expand("!git:$"), "'first change'");
+        assert_eq!(expand("vi !c:1"), "vi src/main.rs");
+        assert_eq!(error("!!:7"), "!!:7: bad word specifier");
+    }
+
+    #[test]
+    fn test_modifiers() {
+        assert_eq!(expand("!c:1:h"), "src");
+        assert_eq!(expand("!c:1:t"), "main.rs");
+        assert_eq!(expand("!c:1:r"), "src/main");
+        assert_eq!(expand("!c:1:e"), ".rs");
+        assert_eq!(expand("!c:2:r:r"), "notes");
+        assert_eq!(expand("!1:$:h"), "/");
+        assert_eq!(expand("!!:s/one/1/"), "echo 1 two three");
+        assert_eq!(expand("!!:gs/o/0/"), "ech0 0ne tw0 three");
+        assert_eq!(expand("!!:s/two/[&]"), "echo one [two] three");
+        assert_eq!(error("!!:x"), ":x: unrecognized history modifier");
+        assert_eq!(error("!!:s/four/4/"), "substitution failed");
+    }
+
+    #[test]
+    fn test_quick_substitution() {
+        assert_eq!(expand("^two^2^"), "echo one 2 three");
+        assert_eq!(expand("^two^2"), "echo one 2 three");
+        assert_eq!(expand("^two^2^ four"), "echo one 2 three four");
+        assert_eq!(error("^four^4"), "substitution failed");
+    }
+
+    #[test]
+    fn test_quoting() {
+        assert_eq!(expand("echo '!!'"), "echo '!!'");
+        assert_eq!(expand("echo 'a' !$"), "echo 'a' three");
+        assert_eq!(expand("echo \\!!"), "echo \\!!");
+        assert_eq!(expand("echo \"!$\""), "echo \"three\"");
+        assert_eq!(expand("echo \"it's !$\""), "echo \"it's three\"");
+    }
+
+    #[test]
+    fn test_literal_bangs() {
+        assert_eq!(expand("echo hi!"), "echo hi!");
+        assert_eq!(expand("echo ! x"), "echo ! x");
+        assert_eq!(expand("[ ! -e x ]"), "[ ! -e x ]");
+        assert_eq!(expand("a!=b"), "a!=b");
+        assert_eq!(expand("echo \"wow!\""), "echo \"wow!\"");
+    }
+}
@@ -1,4 +1,5 @@
 use std::cell::Cell;
+use std::sync::{Arc, RwLock};
 
 extern crate diesel;
 use self::diesel::prelude::*;
@@ -6,7 +7,10 @@ extern crate chrono;
 
 mod schema;
 mod model;
+mod abbreviations;
+pub mod expansion;
 pub use self::model::Entry;
+pub use self::abbreviations::Abbreviations;
 use self::model::NewHistoryEntry;
 use self::schema::history::dsl::*;
 use self::schema::history::columns::timestamp;
@@ -15,6 +19,7 @@ use self::schema::history;
 pub struct History {
     connection: SqliteConnection,
     entries: Option<Vec<Entry>>,
+    abbreviations: Arc<RwLock<Abbreviations>>,
 }
 
 impl History {
@@ -34,9 +39,11 @@ impl History {
                         ConnectionError::BadConnection(e.description().to_owned())
                     })?;
                 let entries = History::load_entries(&con);
+                let abbreviations = Abbreviations::new(database_url)?;
                 Ok(History {
                     connection: con,
                     entries: entries,
+                    abbreviations: Arc::new(RwLock::new(abbreviations)),
                 })
             }
             Err(e) => Err(e),
@@ -54,6 +61,10 @@ impl History {
         &self.entries
     }
 
+    pub fn abbreviations(&self) -> Arc<RwLock<Abbreviations>> {
+        self.abbreviations.clone()
+    }
+
     fn load_entries(connection: &SqliteConnection) -> Option<Vec<Entry>> {
         match history.order(timestamp.asc()).load(connection) {
             Ok(entries) => Some(entries),
@@ -14,3 +14,16 @@ pub struct NewHistoryEntry<'a> {
     pub timestamp: NaiveDateTime,
     pub command: &'a str,
 }
+
+#[derive(Queryable)]
+pub struct Abbreviation {
+    pub name: String,
+    pub expansion: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "abbreviations"]
+pub struct NewAbbreviation<'a> {
+    pub name: &'a str,
+    pub expansion: &'a str,
+}
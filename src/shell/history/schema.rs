@@ -6,3 +6,10 @@ table! {
         command -> VarChar,
     }
 }
+
+table! {
+    abbreviations (name) {
+        name -> VarChar,
+        expansion -> VarChar,
+    }
+}
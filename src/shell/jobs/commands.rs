@@ -0,0 +1,431 @@
+use super::JobManager;
+use super::job::{self, resolve_command, search_path, BuiltinHandler};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Words the shell handles itself before looking for a command.
+pub const KEYWORDS: &[&str] = &["time", "[[", "]]"];
+
+/// The `PATH` used by `command -p`, which finds the standard utilities whatever `PATH` says.
+const DEFAULT_PATH: &str = "/usr/local/bin:/usr/bin:/bin:/usr/sbin:/sbin";
+
+struct Entry {
+    path: PathBuf,
+    hits: usize,
+}
+
+/// Remembers where commands were found in `PATH`, so that directories are only searched the
+/// first time a command is run. The table is emptied whenever `PATH` changes.
+pub struct CommandTable {
+    path: Option<OsString>,
+    entries: HashMap<String, Entry>,
+    /// The names of the files in the directories of `PATH`, once something asked for them.
+    names: Option<HashSet<String>>,
+}
+
+impl CommandTable {
+    pub fn new() -> Self {
+        CommandTable {
+            path: env::var_os("PATH"),
+            entries: HashMap::new(),
+            names: None,
+        }
+    }
+
+    fn check_path(&mut self) {
+        let current = env::var_os("PATH");
+        if current != self.path {
+            self.clear();
+            self.path = current;
+        }
+    }
+
+    /// Forgets everything, as `hash -r` asks.
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.names = None;
+    }
+
+    /// Every name that can be found in `PATH`, for suggestions when a command is not found. The
+    /// directories are only read the first time, like the locations of commands.
+    pub fn names(&mut self) -> &HashSet<String> {
+        self.check_path();
+        if self.names.is_none() {
+            let mut names: HashSet<String> = self.entries.keys().cloned().collect();
+            if let Some(ref path) = self.path {
+                for dir in env::split_paths(path) {
+                    if let Ok(entries) = fs::read_dir(dir) {
+                        names.extend(entries.filter_map(|entry| entry.ok()).filter_map(|entry| entry.file_name().into_string().ok()));
+                    }
+                }
+            }
+            self.names = Some(names);
+        }
+        self.names.as_ref().unwrap()
+    }
+
+    /// The remembered location of a command, if it is still there.
+    fn cached(&mut self, name: &str) -> Option<PathBuf> {
+        self.check_path();
+        let stale = match self.entries.get(name) {
+            Some(entry) if entry.path.is_file() => return Some(entry.path.clone()),
+            Some(_) => true,
+            None => false,
+        };
+        if stale {
+            self.entries.remove(name);
+        }
+        None
+    }
+
+    fn insert(&mut self, name: &str, path: PathBuf, hits: usize) {
+        self.check_path();
+        self.entries.insert(name.to_owned(), Entry { path: path, hits: hits });
+    }
+
+    /// Finds the executable for `name`. Names with a slash are taken as paths and never kept.
+    pub fn find(&mut self, name: &str) -> Result<PathBuf, job::Error> {
+        if name.contains('/') {
+            return resolve_command(name);
+        }
+        if self.cached(name).is_some() {
+            let entry = self.entries.get_mut(name).unwrap();
+            entry.hits += 1;
+            return Ok(entry.path.clone());
+        }
+        let found = match self.path {
+            Some(ref path) => search_path(name, path),
+            None => None,
+        };
+        match found {
+            Some(path) => {
+                self.insert(name, path.clone(), 1);
+                Ok(path)
+            }
+            None => resolve_command(name),
+        }
+    }
+}
+
+/// Splits the options off `command [-p] name args`. `None` means `-v` or `-V` was given, so the
+/// builtin has to describe the command instead of running it.
+pub fn split_prefix(args: &[String]) -> Option<(bool, &[String])> {
+    let mut default_path = false;
+    for (index, arg) in args.iter().enumerate() {
+        if arg == "--" {
+            return Some((default_path, &args[index + 1..]));
+        }
+        if !arg.starts_with('-') || arg.len() == 1 {
+            return Some((default_path, &args[index..]));
+        }
+        for option in arg[1..].chars() {
+            match option {
+                'p' => default_path = true,
+                _ => return None,
+            }
+        }
+    }
+    Some((default_path, &args[args.len()..]))
+}
+
+pub fn find_in_default_path(name: &str) -> Result<PathBuf, job::Error> {
+    if name.contains('/') {
+        return resolve_command(name);
+    }
+    search_path(name, OsStr::new(DEFAULT_PATH)).ok_or(job::Error::CommandNotFound(PathBuf::from(name), Vec::new()))
+}
+
+/// What a command name stands for, in the order the shell looks.
+enum Found {
+    Alias(String),
+    Keyword,
+    Builtin,
+    Hashed(PathBuf),
+    File(PathBuf),
+}
+
+impl Found {
+    fn kind(&self) -> &'static str {
+        match *self {
+            Found::Alias(_) => "alias",
+            Found::Keyword => "keyword",
+            Found::Builtin => "builtin",
+            Found::Hashed(_) | Found::File(_) => "file",
+        }
+    }
+
+    fn path(&self) -> Option<&Path> {
+        match *self {
+            Found::Hashed(ref path) | Found::File(ref path) => Some(path),
+            _ => None,
+        }
+    }
+
+    fn describe(&self, name: &str) -> String {
+        match *self {
+            Found::Alias(ref value) => format!("{} is aliased to `{}'", name, value),
+            Found::Keyword => format!("{} is a shell keyword", name),
+            Found::Builtin => format!("{} is a shell builtin", name),
+            Found::Hashed(ref path) => format!("{} is hashed ({})", name, path.display()),
+            Found::File(ref path) => format!("{} is {}", name, path.display()),
+        }
+    }
+}
+
+/// Splits leading single-letter options off `args`, failing on any not in `allowed`.
+fn parse_flags<'a>(name: &str, args: &'a [String], allowed: &str) -> Result<(Vec<char>, &'a [String]), i8> {
+    let mut flags = Vec::new();
+    let mut index = 0;
+    while let Some(arg) = args.get(index) {
+        if arg == "--" {
+            index += 1;
+            break;
+        }
+        if !arg.starts_with('-') || arg.len() == 1 {
+            break;
+        }
+        for flag in arg[1..].chars() {
+            if !allowed.contains(flag) {
+                eprintln!("{}: -{}: invalid option", name, flag);
+                return Err(2);
+            }
+            flags.push(flag);
+        }
+        index += 1;
+    }
+    Ok((flags, &args[index..]))
+}
+
+impl JobManager {
+    /// Looks a name up the way the shell would run it. Only the first match is returned unless
+    /// `all` is set, in which case every file of that name in `PATH` is listed as well.
+    fn lookup(&mut self, name: &str, all: bool) -> Vec<Found> {
+        let mut found = Vec::new();
+        if let Some(value) = self.aliases.read().unwrap().get(name) {
+            found.push(Found::Alias(value.clone()));
+        }
+        if KEYWORDS.contains(&name) {
+            found.push(Found::Keyword);
+        }
+        if self.is_builtin(name) {
+            found.push(Found::Builtin);
+        }
+        if !all && found.len() > 0 {
+            found.truncate(1);
+            return found;
+        }
+        if name.contains('/') {
+            if Path::new(name).is_file() {
+                found.push(Found::File(PathBuf::from(name)));
+            }
+        } else if all {
+            if let Some(path) = env::var_os("PATH") {
+                found.extend(env::split_paths(&path).map(|dir| dir.join(name)).filter(|p| p.is_file()).map(Found::File));
+            }
+        } else if let Some(path) = self.commands.cached(name) {
+            found.push(Found::Hashed(path));
+        } else if let Some(path) = env::var_os("PATH").and_then(|path| search_path(name, &path)) {
+            found.push(Found::File(path));
+        }
+        found
+    }
+
+    pub fn hash_builtin(&mut self, args: &[String]) -> i8 {
+        let (flags, names) = match parse_flags("hash", args, "rdtp") {
+            Ok(parsed) => parsed,
+            Err(status) => {
+                eprintln!("hash: usage: hash [-r] [-p pathname] [-dt] [name ...]");
+                return status;
+            }
+        };
+        if flags.contains(&'r') {
+            self.commands.clear();
+        }
+        if flags.contains(&'p') {
+            return match names.split_first() {
+                Some((path, names)) if names.len() > 0 => {
+                    for name in names {
+                        self.commands.insert(name, PathBuf::from(path), 0);
+                    }
+                    0
+                }
+                _ => {
+                    eprintln!("hash: -p: option requires a path and a name");
+                    2
+                }
+            };
+        }
+        if names.is_empty() {
+            if !flags.contains(&'r') {
+                self.commands.check_path();
+                if self.commands.entries.is_empty() {
+                    eprintln!("hash: hash table empty");
+                } else {
+                    let mut entries: Vec<(&String, &Entry)> = self.commands.entries.iter().collect();
+                    entries.sort_by(|a, b| a.0.cmp(b.0));
+                    println!("hits\tcommand");
+                    for (_, entry) in entries {
+                        println!("{:>4}\t{}", entry.hits, entry.path.display());
+                    }
+                }
+            }
+            return 0;
+        }
+        let mut result = 0;
+        for name in names {
+            if flags.contains(&'d') {
+                if self.commands.entries.remove(name.as_str()).is_none() {
+                    eprintln!("hash: {}: not found", name);
+                    result = 1;
+                }
+            } else if flags.contains(&'t') {
+                match self.commands.cached(name) {
+                    Some(path) if names.len() > 1 => println!("{}\t{}", name, path.display()),
+                    Some(path) => println!("{}", path.display()),
+                    None => {
+                        eprintln!("hash: {}: not found", name);
+                        result = 1;
+                    }
+                }
+            } else if self.is_builtin(name) {
+                // builtins are never looked up in PATH
+                continue;
+            } else {
+                match self.commands.cached(name).or_else(|| env::var_os("PATH").and_then(|path| search_path(name, &path))) {
+                    Some(path) => self.commands.insert(name, path, 0),
+                    None => {
+                        eprintln!("hash: {}: not found", name);
+                        result = 1;
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    pub fn type_builtin(&mut self, args: &[String]) -> i8 {
+        let (flags, names) = match parse_flags("type", args, "atpP") {
+            Ok(parsed) => parsed,
+            Err(status) => {
+                eprintln!("type: usage: type [-afptP] name [name ...]");
+                return status;
+            }
+        };
+        let all = flags.contains(&'a');
+        let mut result = 0;
+        for name in names {
+            let found = if flags.contains(&'P') {
+                self.lookup(name, all).into_iter().filter(|f| f.path().is_some()).collect()
+            } else {
+                self.lookup(name, all)
+            };
+            if found.is_empty() {
+                if !flags.contains(&'t') && !flags.contains(&'p') && !flags.contains(&'P') {
+                    eprintln!("type: {}: not found", name);
+                }
+                result = 1;
+                continue;
+            }
+            for item in &found {
+                if flags.contains(&'t') {
+                    println!("{}", item.kind());
+                } else if flags.contains(&'p') || flags.contains(&'P') {
+                    // `-p` prints nothing for names that are not files
+                    if let Some(path) = item.path() {
+                        println!("{}", path.display());
+                    }
+                } else {
+                    println!("{}", item.describe(name));
+                }
+            }
+        }
+        result
+    }
+
+    /// `which`, which only looks for files in `PATH`, unlike `type`.
+    pub fn which_builtin(&mut self, args: &[String]) -> i8 {
+        let (flags, names) = match parse_flags("which", args, "a") {
+            Ok(parsed) => parsed,
+            Err(status) => {
+                eprintln!("which: usage: which [-a] name [name ...]");
+                return status;
+            }
+        };
+        let path = env::var_os("PATH").unwrap_or(OsString::new());
+        let mut result = 0;
+        for name in names {
+            let found: Vec<PathBuf> = if name.contains('/') {
+                Some(PathBuf::from(name.as_str())).into_iter().filter(|p| p.is_file()).collect()
+            } else if flags.contains(&'a') {
+                env::split_paths(&path).map(|dir| dir.join(name)).filter(|p| p.is_file()).collect()
+            } else {
+                search_path(name, &path).into_iter().collect()
+            };
+            if found.is_empty() {
+                result = 1;
+            }
+            for path in found {
+                println!("{}", path.display());
+            }
+        }
+        result
+    }
+
+    /// `command -v` and `command -V`; running a command through `command` is handled when the
+    /// job is built.
+    pub fn command_builtin(&mut self, args: &[String]) -> i8 {
+        let (flags, names) = match parse_flags("command", args, "pvV") {
+            Ok(parsed) => parsed,
+            Err(status) => {
+                eprintln!("command: usage: command [-pVv] command [arg ...]");
+                return status;
+            }
+        };
+        if !flags.contains(&'v') && !flags.contains(&'V') {
+            // only reached without a command to run
+            return 0;
+        }
+        let mut result = 0;
+        for name in names {
+            let found = if flags.contains(&'p') && !self.is_builtin(name) && !KEYWORDS.contains(&name.as_str()) {
+                find_in_default_path(name).ok().map(Found::File)
+            } else {
+                self.lookup(name, false).into_iter().next()
+            };
+            match found {
+                Some(item) => if flags.contains(&'V') {
+                    println!("{}", item.describe(name));
+                } else {
+                    match item {
+                        Found::Alias(value) => println!("alias {}='{}'", name, value),
+                        Found::Keyword | Found::Builtin => println!("{}", name),
+                        Found::Hashed(path) | Found::File(path) => println!("{}", path.display()),
+                    }
+                },
+                None => {
+                    if flags.contains(&'V') {
+                        eprintln!("command: {}: not found", name);
+                    }
+                    result = 1;
+                }
+            }
+        }
+        result
+    }
+
+    /// Runs a builtin even where something else would take precedence.
+    pub fn builtin_builtin(&mut self, args: &[String]) -> i8 {
+        match args.split_first() {
+            None => 0,
+            Some((name, args)) => if self.is_builtin(name) {
+                self.handle_builtin(name, args)
+            } else {
+                eprintln!("builtin: {}: not a shell builtin", name);
+                1
+            },
+        }
+    }
+}
@@ -0,0 +1,454 @@
+use super::JobManager;
+use super::regex::Regex;
+use super::super::syntax::tokens::{StringLiteralComponent, Token};
+extern crate glob;
+use nix;
+use nix::libc;
+use std::env;
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+/// Whether an expression was given to `test`/`[` or written inside `[[ ]]`. Both share the
+/// primaries, but `[[ ]]` combines them with `&&` and `||`, matches `==` as a glob pattern and
+/// has `=~`.
+#[derive(Clone, Copy, PartialEq)]
+enum Syntax {
+    Test,
+    Conditional,
+}
+
+impl Syntax {
+    fn and(&self) -> &'static str {
+        match *self {
+            Syntax::Test => "-a",
+            Syntax::Conditional => "&&",
+        }
+    }
+
+    fn or(&self) -> &'static str {
+        match *self {
+            Syntax::Test => "-o",
+            Syntax::Conditional => "||",
+        }
+    }
+
+    fn is_binary(&self, operator: &str) -> bool {
+        match operator {
+            "=" | "==" | "!=" | "<" | ">" | "-eq" | "-ne" | "-lt" | "-le" | "-gt" | "-ge" | "-nt" | "-ot" | "-ef" => true,
+            "=~" => *self == Syntax::Conditional,
+            _ => false,
+        }
+    }
+}
+
+fn is_unary(operator: &str) -> bool {
+    match operator {
+        "-e" | "-f" | "-d" | "-x" | "-s" | "-r" | "-w" | "-h" | "-L" | "-b" | "-c" | "-p" | "-S" | "-t" | "-z" | "-n" => true,
+        _ => false,
+    }
+}
+
+enum Condition {
+    /// A lone word, true if it is not empty.
+    Word(String),
+    Unary(String, String),
+    Binary(String, String, String),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+struct Parser<'a> {
+    words: &'a [String],
+    position: usize,
+    syntax: Syntax,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.words.get(self.position).map(|w| w.as_str())
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let word = self.peek();
+        self.position += 1;
+        word
+    }
+
+    fn parse(mut self) -> Result<Condition, String> {
+        let condition = self.or()?;
+        match self.peek() {
+            Some(word) => Err(format!("{}: unexpected argument", word)),
+            None => Ok(condition),
+        }
+    }
+
+    fn or(&mut self) -> Result<Condition, String> {
+        let mut left = self.and()?;
+        while self.peek() == Some(self.syntax.or()) {
+            self.position += 1;
+            let right = self.and()?;
+            left = Condition::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Condition, String> {
+        let mut left = self.not()?;
+        while self.peek() == Some(self.syntax.and()) {
+            self.position += 1;
+            let right = self.not()?;
+            left = Condition::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Condition, String> {
+        // a `!` with nothing after it is just a word
+        if self.peek() == Some("!") && self.position + 1 < self.words.len() {
+            self.position += 1;
+            return Ok(Condition::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Condition, String> {
+        let word = self.next().ok_or(String::from("argument expected"))?;
+        if let Some(operator) = self.peek() {
+            if self.syntax.is_binary(operator) {
+                self.position += 1;
+                let right = self.next().ok_or(format!("{}: argument expected", operator))?;
+                return Ok(Condition::Binary(word.to_owned(), operator.to_owned(), right.to_owned()));
+            }
+        }
+        if word == "(" {
+            let inner = self.or()?;
+            return match self.next() {
+                Some(")") => Ok(inner),
+                _ => Err(String::from("`)' expected")),
+            };
+        }
+        if is_unary(word) {
+            if let Some(operand) = self.next() {
+                return Ok(Condition::Unary(word.to_owned(), operand.to_owned()));
+            }
+        }
+        Ok(Condition::Word(word.to_owned()))
+    }
+}
+
+/// Parses the arguments of `test` by their number first, as POSIX asks, so that for example
+/// `[ -n ]` and `[ ! = x ]` mean what they say; only longer expressions go to the parser.
+fn parse_test(args: &[String]) -> Result<Condition, String> {
+    let word = |index: usize| Condition::Word(args[index].clone());
+    match args.len() {
+        0 => Ok(Condition::Word(String::new())),
+        1 => Ok(word(0)),
+        2 if args[0] == "!" => Ok(Condition::Not(Box::new(word(1)))),
+        2 if is_unary(&args[0]) => Ok(Condition::Unary(args[0].clone(), args[1].clone())),
+        2 => Err(format!("{}: unary operator expected", args[0])),
+        3 if args[1] == "-a" => Ok(Condition::And(Box::new(word(0)), Box::new(word(2)))),
+        3 if args[1] == "-o" => Ok(Condition::Or(Box::new(word(0)), Box::new(word(2)))),
+        3 if Syntax::Test.is_binary(&args[1]) => Ok(Condition::Binary(args[0].clone(), args[1].clone(), args[2].clone())),
+        3 if args[0] == "!" => Ok(Condition::Not(Box::new(parse_test(&args[1..])?))),
+        3 if args[0] == "(" && args[2] == ")" => Ok(word(1)),
+        3 => Err(format!("{}: binary operator expected", args[1])),
+        4 if args[0] == "!" => Ok(Condition::Not(Box::new(parse_test(&args[1..])?))),
+        4 if args[0] == "(" && args[3] == ")" => parse_test(&args[1..3]),
+        _ => Parser {
+            words: args,
+            position: 0,
+            syntax: Syntax::Test,
+        }.parse(),
+    }
+}
+
+fn accessible(path: &str, mode: libc::c_int) -> bool {
+    match CString::new(path) {
+        Ok(path) => unsafe { libc::access(path.as_ptr(), mode) == 0 },
+        Err(_) => false,
+    }
+}
+
+fn file_test(operator: &str, path: &str) -> bool {
+    if operator == "-h" || operator == "-L" {
+        return fs::symlink_metadata(path).map(|m| m.file_type().is_symlink()).unwrap_or(false);
+    }
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return false,
+    };
+    let file_type = metadata.file_type();
+    match operator {
+        "-f" => file_type.is_file(),
+        "-d" => file_type.is_dir(),
+        "-s" => metadata.len() > 0,
+        "-b" => file_type.is_block_device(),
+        "-c" => file_type.is_char_device(),
+        "-p" => file_type.is_fifo(),
+        "-S" => file_type.is_socket(),
+        "-r" => accessible(path, libc::R_OK),
+        "-w" => accessible(path, libc::W_OK),
+        "-x" => accessible(path, libc::X_OK),
+        _ => true,
+    }
+}
+
+fn integer(word: &str) -> Result<i64, String> {
+    word.trim().parse::<i64>().map_err(|_| format!("{}: integer expression expected", word))
+}
+
+/// Compares modification times for `-nt`; a file that exists is newer than one that does not.
+fn newer(left: &str, right: &str) -> bool {
+    let modified = |path: &str| fs::metadata(path).and_then(|m| m.modified()).ok();
+    match (modified(left), modified(right)) {
+        (Some(left), Some(right)) => left > right,
+        (Some(_), None) => true,
+        _ => false,
+    }
+}
+
+fn same_file(left: &str, right: &str) -> bool {
+    match (fs::metadata(left), fs::metadata(right)) {
+        (Ok(left), Ok(right)) => left.dev() == right.dev() && left.ino() == right.ino(),
+        _ => false,
+    }
+}
+
+/// Evaluates a condition. `rematch` receives the groups of the last `=~` that was evaluated,
+/// or an empty list if it did not match.
+fn evaluate(condition: &Condition, syntax: Syntax, rematch: &mut Option<Vec<String>>) -> Result<bool, String> {
+    Ok(match *condition {
+        Condition::Word(ref word) => !word.is_empty(),
+        Condition::Not(ref inner) => !evaluate(inner, syntax, rematch)?,
+        // `[[ ]]` stops as soon as the result is known; `test` has always looked at everything
+        Condition::And(ref left, ref right) => if syntax == Syntax::Conditional {
+            evaluate(left, syntax, rematch)? && evaluate(right, syntax, rematch)?
+        } else {
+            let left = evaluate(left, syntax, rematch)?;
+            evaluate(right, syntax, rematch)? && left
+        },
+        Condition::Or(ref left, ref right) => if syntax == Syntax::Conditional {
+            evaluate(left, syntax, rematch)? || evaluate(right, syntax, rematch)?
+        } else {
+            let left = evaluate(left, syntax, rematch)?;
+            evaluate(right, syntax, rematch)? || left
+        },
+        Condition::Unary(ref operator, ref operand) => match operator.as_str() {
+            "-z" => operand.is_empty(),
+            "-n" => !operand.is_empty(),
+            "-t" => nix::unistd::isatty(integer(operand)? as libc::c_int).unwrap_or(false),
+            operator => file_test(operator, operand),
+        },
+        Condition::Binary(ref left, ref operator, ref right) => match operator.as_str() {
+            "=" | "==" | "!=" => {
+                let equal = if syntax == Syntax::Conditional {
+                    glob::Pattern::new(right).map(|p| p.matches(left)).unwrap_or(left == right)
+                } else {
+                    left == right
+                };
+                equal == (operator != "!=")
+            }
+            "=~" => {
+                let regex = Regex::new(right).map_err(|e| format!("{}: {}", right, e))?;
+                let captures = regex.captures(left);
+                let matched = captures.is_some();
+                *rematch = Some(captures.unwrap_or(Vec::new()));
+                matched
+            }
+            "<" => left < right,
+            ">" => left > right,
+            "-eq" => integer(left)? == integer(right)?,
+            "-ne" => integer(left)? != integer(right)?,
+            "-lt" => integer(left)? < integer(right)?,
+            "-le" => integer(left)? <= integer(right)?,
+            "-gt" => integer(left)? > integer(right)?,
+            "-ge" => integer(left)? >= integer(right)?,
+            "-nt" => newer(left, right),
+            "-ot" => newer(right, left),
+            _ => same_file(left, right),
+        },
+    })
+}
+
+impl JobManager {
+    /// `test` and `[`, which only differ in that `[` wants a closing `]`.
+    pub fn test_builtin(&mut self, name: &str, args: &[String]) -> i8 {
+        let args = if name == "[" {
+            match args.split_last() {
+                Some((last, rest)) if last == "]" => rest,
+                _ => {
+                    eprintln!("[: missing `]'");
+                    return 2;
+                }
+            }
+        } else {
+            args
+        };
+        match parse_test(args).and_then(|condition| evaluate(&condition, Syntax::Test, &mut None)) {
+            Ok(true) => 0,
+            Ok(false) => 1,
+            Err(e) => {
+                eprintln!("{}: {}", name, e);
+                2
+            }
+        }
+    }
+
+    /// Expands a word inside `[[ ]]`. Unlike command arguments the result is neither globbed
+    /// nor dropped when empty, and braces are kept for `=~`.
+    fn conditional_word(&self, components: &[StringLiteralComponent]) -> String {
+        components
+            .iter()
+            .map(|component| match *component {
+                StringLiteralComponent::Literal(s) => String::from(s),
+                StringLiteralComponent::EnvVar(name) => self.variables
+                    .get(name)
+                    .unwrap_or_else(|| env::var(name).unwrap_or(String::new())),
+                StringLiteralComponent::Brace(ref parts) => format!("{{{}}}", parts.join(",")),
+            })
+            .collect()
+    }
+
+    /// Runs the expression between `[[` and `]]`. `&&` and `||` reach here as pairs of
+    /// background and pipe tokens, and `<` and `>` as redirections.
+    pub fn conditional_command(&mut self, tokens: &[Token]) -> i8 {
+        let mut words = Vec::new();
+        let mut index = 0;
+        while index < tokens.len() {
+            let word = match (&tokens[index], tokens.get(index + 1)) {
+                (&Token::StringLiteral(ref components), _) => self.conditional_word(components),
+                (&Token::Background, Some(&Token::Background)) => {
+                    index += 1;
+                    String::from("&&")
+                }
+                (&Token::Pipe, Some(&Token::Pipe)) => {
+                    index += 1;
+                    String::from("||")
+                }
+                (&Token::Input(0), _) => String::from("<"),
+                (&Token::Redirect(1), _) => String::from(">"),
+                (token, _) => {
+                    eprintln!("[[: syntax error near {:?}", token);
+                    return 2;
+                }
+            };
+            words.push(word);
+            index += 1;
+        }
+        let parser = Parser {
+            words: &words,
+            position: 0,
+            syntax: Syntax::Conditional,
+        };
+        let mut rematch = None;
+        let result = parser.parse().and_then(|condition| evaluate(&condition, Syntax::Conditional, &mut rematch));
+        if let Some(groups) = rematch {
+            self.variables.set_array("BASH_REMATCH", groups);
+        }
+        match result {
+            Ok(true) => 0,
+            Ok(false) => 1,
+            Err(e) => {
+                eprintln!("[[: {}", e);
+                2
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| String::from(*word)).collect()
+    }
+
+    fn test(args: &[&str]) -> Result<bool, String> {
+        parse_test(&strings(args)).and_then(|condition| evaluate(&condition, Syntax::Test, &mut None))
+    }
+
+    fn conditional(words: &[&str]) -> (Result<bool, String>, Option<Vec<String>>) {
+        let words = strings(words);
+        let parser = Parser {
+            words: &words,
+            position: 0,
+            syntax: Syntax::Conditional,
+        };
+        let mut rematch = None;
+        let result = parser.parse().and_then(|condition| evaluate(&condition, Syntax::Conditional, &mut rematch));
+        (result, rematch)
+    }
+
+    #[test]
+    fn test_by_argument_count() {
+        assert_eq!(test(&[]), Ok(false));
+        assert_eq!(test(&["word"]), Ok(true));
+        assert_eq!(test(&[""]), Ok(false));
+        // a lone operator is just a word
+        assert_eq!(test(&["-n"]), Ok(true));
+        assert_eq!(test(&["-z", ""]), Ok(true));
+        assert_eq!(test(&["!", ""]), Ok(true));
+        assert_eq!(test(&["!", "=", "x"]), Ok(false));
+        assert_eq!(test(&["!", "-z", "x"]), Ok(true));
+        assert_eq!(test(&["(", "", ")"]), Ok(false));
+        assert_eq!(test(&["!", "(", "x", ")"]), Ok(false));
+    }
+
+    #[test]
+    fn test_operators() {
+        assert_eq!(test(&["abc", "=", "abc"]), Ok(true));
+        assert_eq!(test(&["abc", "=", "a*"]), Ok(false));
+        assert_eq!(test(&["abc", "!=", "abd"]), Ok(true));
+        assert_eq!(test(&["a", "<", "b"]), Ok(true));
+        assert_eq!(test(&["10", "-gt", " 9"]), Ok(true));
+        assert_eq!(test(&["-1", "-le", "-2"]), Ok(false));
+        assert_eq!(test(&["-d", "/"]), Ok(true));
+        assert_eq!(test(&["-f", "/"]), Ok(false));
+        assert_eq!(test(&["/", "-ef", "/."]), Ok(true));
+    }
+
+    #[test]
+    fn test_precedence() {
+        // -a binds tighter than -o
+        assert_eq!(test(&["x", "-o", "x", "-a", ""]), Ok(true));
+        assert_eq!(test(&["", "-o", "x", "-a", ""]), Ok(false));
+        assert_eq!(test(&["(", "", "-o", "x", ")", "-a", ""]), Ok(false));
+        assert_eq!(test(&["!", "", "-a", "x"]), Ok(true));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(test(&["a", "b"]), Err(String::from("a: unary operator expected")));
+        assert_eq!(test(&["a", "b", "c"]), Err(String::from("b: binary operator expected")));
+        assert_eq!(test(&["a", "=~", "a"]), Err(String::from("=~: binary operator expected")));
+        assert_eq!(test(&["abc", "-eq", "1"]), Err(String::from("abc: integer expression expected")));
+        assert_eq!(test(&["(", "a", "-a", "b", "c"]), Err(String::from("`)' expected")));
+        assert_eq!(test(&["a", "-a", "b", "c", "d"]), Err(String::from("c: unexpected argument")));
+    }
+
+    #[test]
+    fn test_conditional() {
+        assert_eq!(conditional(&["abc", "==", "a*"]).0, Ok(true));
+        assert_eq!(conditional(&["abc", "!=", "a?c"]).0, Ok(false));
+        assert_eq!(conditional(&["", "||", "x", "&&", "y"]).0, Ok(true));
+        assert_eq!(conditional(&["!", "(", "x", "&&", "", ")"]).0, Ok(true));
+        // unlike test, [[ ]] does not evaluate what cannot change the result
+        assert_eq!(conditional(&["x", "||", "abc", "-eq", "1"]).0, Ok(true));
+        assert!(test(&["x", "-o", "abc", "-eq", "1"]).is_err());
+    }
+
+    #[test]
+    fn test_regex_match() {
+        let (result, rematch) = conditional(&["2026-10-19", "=~", "^([0-9]+)-([0-9]+)"]);
+        assert_eq!(result, Ok(true));
+        assert_eq!(rematch, Some(strings(&["2026-10", "2026", "10"])));
+        let (result, rematch) = conditional(&["October", "=~", "[0-9]"]);
+        assert_eq!(result, Ok(false));
+        assert_eq!(rematch, Some(Vec::new()));
+        assert!(conditional(&["a", "=~", "("]).0.is_err());
+        assert_eq!(conditional(&["a", "&&", "b"]).1, None);
+    }
+}
@@ -0,0 +1,559 @@
+use super::{jobspec, reaper, signals, Condition, Job, JobManager};
+use super::job::exit_code;
+use super::jobspec::{JobRef, Table};
+use nix;
+use nix::sys::signal::{self, SigAction, SigHandler, Signal};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// Set when Ctrl-C interrupts the `wait` builtin.
+static WAIT_INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_wait_interrupt(_: nix::libc::c_int) {
+    WAIT_INTERRUPTED.store(true, Ordering::SeqCst);
+    reaper::wake();
+}
+
+/// Catches SIGINT while `wait` blocks, since an interactive shell otherwise ignores it and
+/// Ctrl-C could not get the user out of waiting for a long job. The previous disposition is
+/// restored when it is dropped.
+struct WaitInterrupt {
+    previous: Option<SigAction>,
+}
+
+impl WaitInterrupt {
+    fn new(catch: bool) -> WaitInterrupt {
+        WAIT_INTERRUPTED.store(false, Ordering::SeqCst);
+        if !catch {
+            return WaitInterrupt { previous: None };
+        }
+        // without SA_RESTART, so that a blocking waitpid returns with EINTR
+        let action = SigAction::new(SigHandler::Handler(handle_wait_interrupt), signal::SaFlags::empty(), signal::SigSet::empty());
+        WaitInterrupt {
+            previous: unsafe { signal::sigaction(Signal::SIGINT, &action) }.ok(),
+        }
+    }
+}
+
+impl Drop for WaitInterrupt {
+    fn drop(&mut self) {
+        if let Some(ref previous) = self.previous {
+            let _ = unsafe { signal::sigaction(Signal::SIGINT, previous) };
+        }
+        WAIT_INTERRUPTED.store(false, Ordering::SeqCst);
+    }
+}
+
+fn wait_interrupted() -> bool {
+    WAIT_INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// The status of `wait` when Ctrl-C interrupts it, as for a command killed by SIGINT.
+const INTERRUPTED_STATUS: i8 = (128 + nix::libc::SIGINT) as i8;
+
+/// Formats a job the way `jobs` prints it, e.g. `[1]+  Running    sleep 10 &`.
+pub fn format_job(job: &Job, marker: char, with_pid: bool) -> String {
+    let id = job.id.map(|i| i.to_string()).unwrap_or(String::from("?"));
+    let suffix = if job.any_stage_running() { " &" } else { "" };
+    if with_pid {
+        let pid = job.pgid().map(|p| p.to_string()).unwrap_or(String::new());
+        format!("[{}]{} {} {:<24}{}{}", id, marker, pid, job.describe(), job.command, suffix)
+    } else {
+        format!("[{}]{}  {:<24}{}{}", id, marker, job.describe(), job.command, suffix)
+    }
+}
+
+impl JobManager {
+    /// Takes a job out of the job table.
+    fn take_job(&self, r: JobRef) -> Job {
+        match r.table {
+            Table::Background => self.background_jobs.write().unwrap().remove(r.index),
+            Table::Stopped => self.stopped_jobs.write().unwrap().remove(r.index),
+        }
+    }
+
+    fn job_id(&self, r: JobRef) -> usize {
+        let background_jobs = self.background_jobs.read().unwrap();
+        let stopped_jobs = self.stopped_jobs.read().unwrap();
+        jobspec::get(r, &background_jobs, &stopped_jobs).id.unwrap_or(0)
+    }
+
+    /// Looks up a jobspec, defaulting to the current job.
+    fn find_job(&self, name: &str, spec: Option<&String>) -> Option<JobRef> {
+        let background_jobs = self.background_jobs.read().unwrap();
+        let stopped_jobs = self.stopped_jobs.read().unwrap();
+        let default = String::from("%+");
+        match jobspec::resolve(spec.unwrap_or(&default), &background_jobs, &stopped_jobs) {
+            Ok(r) => Some(r),
+            Err(e) => {
+                eprintln!("{}: {}", name, e);
+                None
+            }
+        }
+    }
+
+    /// Prints the job table entry of the job with the given number.
+    pub fn print_job(&self, id: usize) {
+        let background_jobs = self.background_jobs.read().unwrap();
+        let stopped_jobs = self.stopped_jobs.read().unwrap();
+        if let Ok(r) = jobspec::resolve(&format!("%{}", id), &background_jobs, &stopped_jobs) {
+            let job = jobspec::get(r, &background_jobs, &stopped_jobs);
+            println!("{}", format_job(job, jobspec::marker(r, &background_jobs, &stopped_jobs), false));
+        }
+    }
+
+    pub fn jobs_builtin(&mut self, args: &[String]) -> i8 {
+        let mut with_pid = false;
+        let mut pids_only = false;
+        let mut specs = Vec::new();
+        for arg in args {
+            match arg.as_ref() {
+                "-l" => with_pid = true,
+                "-p" => pids_only = true,
+                _ => specs.push(arg),
+            }
+        }
+        let background_jobs = self.background_jobs.read().unwrap();
+        let stopped_jobs = self.stopped_jobs.read().unwrap();
+        let mut refs = Vec::new();
+        let mut result = 0;
+        if specs.len() == 0 {
+            refs = jobspec::ordered(&background_jobs, &stopped_jobs);
+            refs.sort_by_key(|r| jobspec::get(*r, &background_jobs, &stopped_jobs).id);
+        } else {
+            for spec in specs {
+                match jobspec::resolve(spec, &background_jobs, &stopped_jobs) {
+                    Ok(r) => refs.push(r),
+                    Err(e) => {
+                        eprintln!("jobs: {}", e);
+                        result = 1;
+                    }
+                }
+            }
+        }
+        for r in refs {
+            let job = jobspec::get(r, &background_jobs, &stopped_jobs);
+            if pids_only {
+                if let Some(pgid) = job.pgid() {
+                    println!("{}", pgid);
+                }
+            } else {
+                println!("{}", format_job(job, jobspec::marker(r, &background_jobs, &stopped_jobs), with_pid));
+            }
+        }
+        result
+    }
+
+    pub fn fg_builtin(&mut self, args: &[String]) -> i8 {
+        let r = match self.find_job("fg", args.first()) {
+            Some(r) => r,
+            None => return 1,
+        };
+        let job = self.take_job(r);
+        println!("{}", job.command);
+        self.foreground_jobs.write().unwrap().push(job);
+        0
+    }
+
+    pub fn bg_builtin(&mut self, args: &[String]) -> i8 {
+        let r = match self.find_job("bg", args.first()) {
+            Some(r) => r,
+            None => return 1,
+        };
+        if r.table == Table::Background {
+            let background_jobs = self.background_jobs.read().unwrap();
+            eprintln!("bg: job {} already in background", background_jobs[r.index].id.unwrap_or(0));
+            return 0;
+        }
+        let mut job = self.take_job(r);
+        if let Err(e) = job.cont(true) {
+            eprintln!("bg: %{}: {}", job.id.unwrap_or(0), e);
+            self.add_job(r.table, job);
+            return 1;
+        }
+        println!("[{}]+ {} &", job.id.unwrap_or(0), job.command);
+        self.add_job(Table::Background, job);
+        0
+    }
+
+    pub fn kill_builtin(&mut self, args: &[String]) -> i8 {
+        let usage = "kill: usage: kill [-s sigspec | -sigspec] pid | jobspec ... or kill -l [sigspec]";
+        let mut signal = Some(nix::sys::signal::Signal::SIGTERM);
+        let mut index = 0;
+        match args.first().map(|a| a.as_ref()) {
+            None => {
+                eprintln!("{}", usage);
+                return 2;
+            }
+            Some("-l") | Some("-L") => return list_signals(&args[1..]),
+            Some("-s") | Some("-n") => {
+                let spec = match args.get(1) {
+                    Some(spec) => spec,
+                    None => {
+                        eprintln!("kill: {}: option requires an argument", args[0]);
+                        eprintln!("{}", usage);
+                        return 2;
+                    }
+                };
+                signal = match parse_signal_spec(spec) {
+                    Ok(signal) => signal,
+                    Err(_) => return 1,
+                };
+                index = 2;
+            }
+            Some("--") => index = 1,
+            Some(flag) if flag.starts_with('-') && flag.len() > 1 => {
+                signal = match parse_signal_spec(&flag[1..]) {
+                    Ok(signal) => signal,
+                    Err(_) => return 1,
+                };
+                index = 1;
+            }
+            Some(_) => {}
+        }
+        if index >= args.len() {
+            eprintln!("{}", usage);
+            return 2;
+        }
+        let mut result = 0;
+        for arg in &args[index..] {
+            let sent = if arg.starts_with('%') {
+                let background_jobs = self.background_jobs.read().unwrap();
+                let stopped_jobs = self.stopped_jobs.read().unwrap();
+                match jobspec::resolve(arg, &background_jobs, &stopped_jobs) {
+                    Ok(r) => {
+                        let job = jobspec::get(r, &background_jobs, &stopped_jobs);
+                        let sent = job.signal(signal);
+                        if sent.is_ok() && r.table == Table::Stopped && (signal == Some(nix::sys::signal::Signal::SIGTERM) || signal == Some(nix::sys::signal::Signal::SIGHUP)) {
+                            // a stopped job would only act on the signal once it is continued
+                            let _ = job.signal(nix::sys::signal::Signal::SIGCONT);
+                        }
+                        Some(sent)
+                    }
+                    Err(e) => {
+                        eprintln!("kill: {}", e);
+                        None
+                    }
+                }
+            } else {
+                match arg.parse::<nix::libc::pid_t>() {
+                    Ok(pid) => Some(nix::sys::signal::kill(nix::unistd::Pid::from_raw(pid), signal)),
+                    Err(_) => {
+                        eprintln!("kill: {}: arguments must be process or job IDs", arg);
+                        None
+                    }
+                }
+            };
+            match sent {
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    eprintln!("kill: {}: {}", arg, e);
+                    result = 1;
+                }
+                None => result = 1,
+            }
+        }
+        result
+    }
+
+    /// Resolves a jobspec or the pid of a job's process group to a job number.
+    fn job_id_of(&self, name: &str, arg: &String) -> Option<usize> {
+        if arg.starts_with('%') {
+            return self.find_job(name, Some(arg)).map(|r| self.job_id(r));
+        }
+        let pid = match arg.parse::<nix::libc::pid_t>() {
+            Ok(pid) => nix::unistd::Pid::from_raw(pid),
+            Err(_) => {
+                eprintln!("{}: {}: not a pid or valid job spec", name, arg);
+                return None;
+            }
+        };
+        let background_jobs = self.background_jobs.read().unwrap();
+        let stopped_jobs = self.stopped_jobs.read().unwrap();
+        match background_jobs.iter().chain(stopped_jobs.iter()).find(|j| j.pgid() == Some(pid)) {
+            Some(job) => job.id,
+            None => {
+                eprintln!("{}: pid {} is not a child of this shell", name, arg);
+                None
+            }
+        }
+    }
+
+    pub fn wait_builtin(&mut self, args: &[String]) -> i8 {
+        let (next, args) = match args.first().map(|a| a.as_ref()) {
+            Some("-n") => (true, &args[1..]),
+            _ => (false, args),
+        };
+        // resolve everything to job numbers up front, since taking jobs out of the table
+        // invalidates the indices of the remaining ones
+        let ids: Vec<usize> = if args.len() == 0 {
+            self.background_jobs.read().unwrap().iter().filter_map(|j| j.id).collect()
+        } else {
+            let mut ids = Vec::new();
+            for arg in args {
+                match self.job_id_of("wait", arg) {
+                    Some(id) => ids.push(id),
+                    None => return 127,
+                }
+            }
+            ids
+        };
+        // with a trap on SIGINT, the trap runs once the command line is done instead
+        let _interrupt = WaitInterrupt::new(self.traps.interactive() && self.traps.command(Condition::Signal(Signal::SIGINT)).is_none());
+        if next {
+            return self.wait_next(&ids);
+        }
+        let mut result = 0;
+        for id in ids {
+            let r = match self.find_job("wait", Some(&format!("%{}", id))) {
+                Some(r) => r,
+                None => continue,
+            };
+            // the job is taken out of the table so the reaper does not report it as well
+            let mut job = self.take_job(r);
+            if r.table == Table::Stopped {
+                eprintln!("wait: job {} is stopped", id);
+                result = exit_code(job.wait_status());
+                self.add_job(Table::Stopped, job);
+                continue;
+            }
+            match self.wait_job(&mut job, nix::sys::wait::WUNTRACED) {
+                Ok(status @ nix::sys::wait::WaitStatus::Stopped(_, _)) => {
+                    result = exit_code(status);
+                    self.add_job(Table::Stopped, job);
+                }
+                // the job keeps running in the background
+                Err(nix::Error::Sys(nix::Errno::EINTR)) if wait_interrupted() => {
+                    self.add_job(Table::Background, job);
+                    return INTERRUPTED_STATUS;
+                }
+                _ => result = job.exit_status(),
+            }
+        }
+        result
+    }
+
+    /// `wait -n`: waits until the first of the given background jobs finishes and returns its
+    /// exit status, or 127 if none of them is running.
+    fn wait_next(&mut self, ids: &[usize]) -> i8 {
+        loop {
+            {
+                let mut background_jobs = self.background_jobs.write().unwrap();
+                let mut waiting = false;
+                for index in 0..background_jobs.len() {
+                    if !background_jobs[index].id.map(|id| ids.contains(&id)).unwrap_or(false) {
+                        continue;
+                    }
+                    waiting = true;
+                    // a pipeline has finished once all of its stages have, not just the last
+                    match background_jobs[index].try_wait(nix::sys::wait::WNOHANG) {
+                        Ok(None) => {}
+                        _ => {
+                            // taken out of the table so the reaper does not report it as well
+                            let job = background_jobs.remove(index);
+                            return job.exit_status();
+                        }
+                    }
+                }
+                if !waiting {
+                    return 127;
+                }
+            }
+            reaper::check_timeouts(&self.background_jobs, &self.stopped_jobs);
+            let timeout = reaper::next_timeout(&self.background_jobs, &self.stopped_jobs);
+            self.wait_for_child(timeout);
+            if wait_interrupted() {
+                return INTERRUPTED_STATUS;
+            }
+        }
+    }
+
+    /// Waits for a job like `Job::wait`, sending the signals of any `timeout` that expires in
+    /// the meantime, whether it belongs to this job or to a background one.
+    pub fn wait_job(&mut self, job: &mut Job, flags: nix::sys::wait::WaitPidFlag) -> nix::Result<nix::sys::wait::WaitStatus> {
+        loop {
+            if self.next_timeout(job).is_none() {
+                return job.wait(Some(flags));
+            }
+            job.check_timeout();
+            reaper::check_timeouts(&self.background_jobs, &self.stopped_jobs);
+            if let Some(status) = job.try_wait(flags)? {
+                return Ok(status);
+            }
+            let timeout = self.next_timeout(job);
+            self.wait_for_child(timeout);
+            if wait_interrupted() {
+                return Err(nix::Error::Sys(nix::Errno::EINTR));
+            }
+        }
+    }
+
+    fn next_timeout(&self, job: &Job) -> Option<Duration> {
+        match (job.timeout_remaining(), reaper::next_timeout(&self.background_jobs, &self.stopped_jobs)) {
+            (Some(own), Some(background)) => Some(own.min(background)),
+            (own, background) => own.or(background),
+        }
+    }
+
+    /// Blocks until a child process changes state or `timeout` has passed.
+    fn wait_for_child(&self, timeout: Option<Duration>) {
+        match self.reaper {
+            Some(ref reaper) => reaper.wait_for_sigchld(timeout),
+            None => thread::sleep(timeout.map(|t| t.min(Duration::from_millis(10))).unwrap_or(Duration::from_millis(10))),
+        }
+    }
+
+    pub fn disown_builtin(&mut self, args: &[String]) -> i8 {
+        let mut keep = false;
+        let mut all = false;
+        let mut running_only = false;
+        let mut specs = Vec::new();
+        for arg in args {
+            match arg.as_ref() {
+                "-h" => keep = true,
+                "-a" => all = true,
+                "-r" => running_only = true,
+                _ => specs.push(arg.clone()),
+            }
+        }
+        let ids: Vec<usize> = if specs.len() == 0 && (all || running_only) {
+            let background_jobs = self.background_jobs.read().unwrap();
+            let stopped_jobs = self.stopped_jobs.read().unwrap();
+            background_jobs
+                .iter()
+                .chain(stopped_jobs.iter())
+                .filter(|j| !running_only || j.any_stage_running())
+                .filter_map(|j| j.id)
+                .collect()
+        } else {
+            if specs.len() == 0 {
+                specs.push(String::from("%+"));
+            }
+            let mut ids = Vec::new();
+            for spec in &specs {
+                match self.job_id_of("disown", spec) {
+                    Some(id) => ids.push(id),
+                    None => return 1,
+                }
+            }
+            ids
+        };
+        for id in ids {
+            let r = match self.find_job("disown", Some(&format!("%{}", id))) {
+                Some(r) => r,
+                None => continue,
+            };
+            if keep {
+                // the job stays in the table, it is just not sent SIGHUP when the shell exits
+                match r.table {
+                    Table::Background => self.background_jobs.write().unwrap()[r.index].no_hangup = true,
+                    Table::Stopped => self.stopped_jobs.write().unwrap()[r.index].no_hangup = true,
+                }
+            } else {
+                let mut job = self.take_job(r);
+                job.disown();
+            }
+        }
+        0
+    }
+
+    /// Warns once when an interactive shell is asked to exit while it still has jobs, returning
+    /// true if the shell should keep running. Asking again right away exits anyway.
+    pub fn warn_about_jobs(&mut self) -> bool {
+        if !self.traps.interactive() || self.exit_warning.map(|line| line + 1) == Some(self.commands_run) {
+            return false;
+        }
+        let stopped = self.stopped_jobs.read().unwrap().len() > 0;
+        let running = self.background_jobs.read().unwrap().len() > 0;
+        if stopped {
+            eprintln!("There are stopped jobs.");
+        } else if running {
+            eprintln!("There are running jobs.");
+        } else {
+            return false;
+        }
+        self.exit_warning = Some(self.commands_run);
+        true
+    }
+
+    /// Sends SIGHUP to every job except those marked with `disown -h`, followed by SIGCONT so
+    /// that stopped jobs act on it, then forgets about all jobs so that dropping the job table
+    /// does not kill the ones that were spared.
+    pub fn hang_up_jobs(&mut self) {
+        let mut background_jobs = self.background_jobs.write().unwrap();
+        let mut stopped_jobs = self.stopped_jobs.write().unwrap();
+        for mut job in background_jobs.drain(..).chain(stopped_jobs.drain(..)) {
+            if !job.no_hangup {
+                let _ = job.signal(nix::sys::signal::Signal::SIGHUP);
+                let _ = job.signal(nix::sys::signal::Signal::SIGCONT);
+            }
+            job.disown();
+        }
+    }
+
+    /// Puts a job in the job table, assigning it a job number if it does not have one yet.
+    pub fn add_job(&self, table: Table, mut job: Job) -> usize {
+        let mut background_jobs = self.background_jobs.write().unwrap();
+        let mut stopped_jobs = self.stopped_jobs.write().unwrap();
+        if job.id.is_none() {
+            job.id = Some(jobspec::next_id(&background_jobs, &stopped_jobs));
+        }
+        job.touch();
+        let id = job.id.unwrap();
+        match table {
+            Table::Background => background_jobs.push(job),
+            Table::Stopped => stopped_jobs.push(job),
+        }
+        id
+    }
+}
+
+/// Parses the signal given to `kill`. Signal 0 sends nothing and only checks that the target
+/// exists.
+fn parse_signal_spec(spec: &str) -> Result<Option<nix::sys::signal::Signal>, ()> {
+    if spec == "0" {
+        return Ok(None);
+    }
+    match signals::parse(spec) {
+        Some(signal) => Ok(Some(signal)),
+        None => {
+            eprintln!("kill: {}: invalid signal specification", spec);
+            Err(())
+        }
+    }
+}
+
+/// `kill -l`: lists all signals, or translates each argument between a signal number (or an
+/// exit status of a job killed by a signal) and a name.
+fn list_signals(args: &[String]) -> i8 {
+    if args.len() == 0 {
+        for line in signals::list() {
+            println!("{}", line);
+        }
+        return 0;
+    }
+    let mut result = 0;
+    for arg in args {
+        match arg.parse::<i32>() {
+            Ok(number) => {
+                let number = if number > 128 { number - 128 } else { number };
+                match nix::sys::signal::Signal::from_c_int(number) {
+                    Ok(signal) => println!("{}", signals::name(signal)),
+                    Err(_) => {
+                        eprintln!("kill: {}: invalid signal specification", arg);
+                        result = 1;
+                    }
+                }
+            }
+            Err(_) => match signals::parse(arg) {
+                Some(signal) => println!("{}", signal as i32),
+                None => {
+                    eprintln!("kill: {}: invalid signal specification", arg);
+                    result = 1;
+                }
+            },
+        }
+    }
+    result
+}
@@ -0,0 +1,481 @@
+use super::JobManager;
+use nix;
+use std::env;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// The `pushd` directory stack and the `prevd`/`nextd` directory history.
+pub struct Directories {
+    /// Directories below the current one on the `pushd` stack, top first.
+    stack: Vec<PathBuf>,
+    /// Directories visited before the current one, most recent last.
+    back: Vec<PathBuf>,
+    /// Directories left with `prevd`, most recent last.
+    forward: Vec<PathBuf>,
+}
+
+impl Directories {
+    pub fn new() -> Self {
+        Directories {
+            stack: Vec::new(),
+            back: Vec::new(),
+            forward: Vec::new(),
+        }
+    }
+
+    /// Changes the working directory and records the old one in the directory history.
+    pub fn change_to(&mut self, target: &Path, physical: bool) -> Result<PathBuf, String> {
+        let old = current_directory();
+        let new = enter(target, physical)?;
+        if new != old {
+            self.back.push(old);
+            self.forward.clear();
+        }
+        Ok(new)
+    }
+
+    /// Goes back `count` entries in the directory history.
+    pub fn previous(&mut self, count: usize) -> Result<PathBuf, String> {
+        let mut current = current_directory();
+        for _ in 0..count {
+            let target = self.back.pop().ok_or(String::from("no previous directory"))?;
+            match enter(&target, false) {
+                Ok(_) => {
+                    self.forward.push(current);
+                    current = target;
+                }
+                Err(e) => {
+                    self.back.push(target);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(current)
+    }
+
+    /// Goes forward `count` entries in the directory history.
+    pub fn next(&mut self, count: usize) -> Result<PathBuf, String> {
+        let mut current = current_directory();
+        for _ in 0..count {
+            let target = self.forward.pop().ok_or(String::from("no next directory"))?;
+            match enter(&target, false) {
+                Ok(_) => {
+                    self.back.push(current);
+                    current = target;
+                }
+                Err(e) => {
+                    self.forward.push(target);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(current)
+    }
+
+    /// The directory stack as `dirs` shows it, starting with the current directory.
+    fn entries(&self) -> Vec<PathBuf> {
+        let mut entries = vec![current_directory()];
+        entries.extend(self.stack.iter().cloned());
+        entries
+    }
+
+    /// Makes `entries[0]` the current directory and the rest the stack below it.
+    fn set_entries(&mut self, mut entries: Vec<PathBuf>) -> Result<PathBuf, String> {
+        let top = entries.remove(0);
+        let new = self.change_to(&top, false)?;
+        self.stack = entries;
+        Ok(new)
+    }
+}
+
+/// The logical working directory: `PWD` if it still refers to the current directory.
+pub fn current_directory() -> PathBuf {
+    let physical = env::current_dir().unwrap_or(PathBuf::from("/"));
+    match env::var_os("PWD").map(PathBuf::from) {
+        Some(pwd) => {
+            if pwd.is_absolute() && pwd.canonicalize().ok() == physical.canonicalize().ok() {
+                pwd
+            } else {
+                physical
+            }
+        }
+        None => physical,
+    }
+}
+
+/// Joins `target` onto `base` and removes `.` and `..` lexically, so that `cd ..` out of a
+/// symlinked directory goes back where it came from.
+fn logical_path(base: &Path, target: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in base.join(target).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                result.pop();
+            }
+            other => result.push(other.as_os_str()),
+        }
+    }
+    result
+}
+
+fn error_message(error: &io::Error) -> String {
+    match error.raw_os_error() {
+        Some(code) => String::from(nix::Errno::from_i32(code).desc()),
+        None => error.to_string(),
+    }
+}
+
+/// Changes the working directory and updates `PWD` and `OLDPWD`. With `physical`, symbolic
+/// links are resolved as `cd -P` does.
+fn enter(target: &Path, physical: bool) -> Result<PathBuf, String> {
+    let old = current_directory();
+    let new = if physical {
+        // `..` has to be taken from the directory the shell is really in, so nothing is
+        // removed lexically before the links are resolved
+        let base = env::current_dir().unwrap_or_else(|_| old.clone());
+        base.join(target).canonicalize().map_err(|e| error_message(&e))?
+    } else {
+        logical_path(&old, target)
+    };
+    env::set_current_dir(&new).map_err(|e| error_message(&e))?;
+    env::set_var("OLDPWD", &old);
+    env::set_var("PWD", &new);
+    Ok(new)
+}
+
+/// Looks `dir` up in `CDPATH` unless it is absolute or starts with `.` or `..`. Also returns
+/// whether it was found through a non-empty `CDPATH` entry, in which case `cd` prints it.
+fn search_cdpath(dir: &str) -> (PathBuf, bool) {
+    let explicit = dir.starts_with('/') || dir == "." || dir == ".." || dir.starts_with("./") || dir.starts_with("../");
+    if !explicit {
+        if let Some(cdpath) = env::var_os("CDPATH") {
+            for entry in env::split_paths(&cdpath) {
+                let candidate = entry.join(dir);
+                if candidate.is_dir() {
+                    return (candidate, !entry.as_os_str().is_empty());
+                }
+            }
+        }
+    }
+    (PathBuf::from(dir), false)
+}
+
+/// Replaces the home directory at the start of a path with `~`.
+fn abbreviate(path: &Path) -> String {
+    let path = path.display().to_string();
+    let home = env::var("HOME").unwrap_or(String::new());
+    let home = home.trim_end_matches('/');
+    if home.len() > 0 && (path == home || path.starts_with(&format!("{}/", home))) {
+        format!("~{}", &path[home.len()..])
+    } else {
+        path
+    }
+}
+
+/// Parses `+N` (counting from the left of the `dirs` listing) or `-N` (from the right).
+fn stack_index(arg: &str, len: usize) -> Option<usize> {
+    if arg.len() < 2 || !(arg.starts_with('+') || arg.starts_with('-')) {
+        return None;
+    }
+    match arg[1..].parse::<usize>() {
+        Ok(n) if n < len => if arg.starts_with('+') {
+            Some(n)
+        } else {
+            Some(len - 1 - n)
+        },
+        _ => None,
+    }
+}
+
+fn is_stack_index(arg: &str) -> bool {
+    arg.len() > 1 && (arg.starts_with('+') || arg.starts_with('-')) && arg[1..].chars().all(|c| c.is_digit(10))
+}
+
+impl JobManager {
+    pub fn cd_builtin(&mut self, args: &[String]) -> i8 {
+        let mut physical = false;
+        let mut index = 0;
+        while index < args.len() && args[index].starts_with('-') && args[index] != "-" {
+            match args[index].as_ref() {
+                "-L" => physical = false,
+                "-P" => physical = true,
+                "--" => {
+                    index += 1;
+                    break;
+                }
+                other => {
+                    eprintln!("cd: {}: invalid option", other);
+                    eprintln!("cd: usage: cd [-L|-P] [dir]");
+                    return 2;
+                }
+            }
+            index += 1;
+        }
+        let operands = &args[index..];
+        if operands.len() > 1 {
+            eprintln!("cd: too many arguments");
+            return 1;
+        }
+        let (target, print) = match operands.first().map(|a| a.as_ref()) {
+            None => match env::var("HOME") {
+                Ok(ref home) if home.len() > 0 => (PathBuf::from(home), false),
+                _ => {
+                    eprintln!("cd: HOME not set");
+                    return 1;
+                }
+            },
+            Some("-") => match env::var("OLDPWD") {
+                Ok(ref oldpwd) if oldpwd.len() > 0 => (PathBuf::from(oldpwd), true),
+                _ => {
+                    eprintln!("cd: OLDPWD not set");
+                    return 1;
+                }
+            },
+            Some(dir) => search_cdpath(dir),
+        };
+        match self.directories.write().unwrap().change_to(&target, physical) {
+            Ok(new) => {
+                if print {
+                    println!("{}", new.display());
+                }
+                0
+            }
+            Err(e) => {
+                eprintln!("cd: {}: {}", operands.first().map(|s| s.clone()).unwrap_or(target.display().to_string()), e);
+                1
+            }
+        }
+    }
+
+    pub fn pushd_builtin(&mut self, args: &[String]) -> i8 {
+        let mut directories = self.directories.write().unwrap();
+        let mut entries = directories.entries();
+        let result = match args.first() {
+            None => {
+                if entries.len() < 2 {
+                    eprintln!("pushd: no other directory");
+                    return 1;
+                }
+                entries.swap(0, 1);
+                directories.set_entries(entries)
+            }
+            Some(arg) if is_stack_index(arg) => match stack_index(arg, entries.len()) {
+                Some(n) => {
+                    let mut rotated = entries.split_off(n);
+                    rotated.append(&mut entries);
+                    directories.set_entries(rotated)
+                }
+                None => {
+                    eprintln!("pushd: {}: directory stack index out of range", arg);
+                    return 1;
+                }
+            },
+            Some(dir) => {
+                let (target, _) = search_cdpath(dir);
+                match directories.change_to(&target, false) {
+                    Ok(new) => {
+                        directories.stack.insert(0, entries.remove(0));
+                        Ok(new)
+                    }
+                    Err(e) => Err(format!("{}: {}", dir, e)),
+                }
+            }
+        };
+        match result {
+            Ok(_) => {
+                print_entries(&directories.entries(), false, false, false);
+                0
+            }
+            Err(e) => {
+                eprintln!("pushd: {}", e);
+                1
+            }
+        }
+    }
+
+    pub fn popd_builtin(&mut self, args: &[String]) -> i8 {
+        let mut directories = self.directories.write().unwrap();
+        let mut entries = directories.entries();
+        if entries.len() < 2 {
+            eprintln!("popd: directory stack empty");
+            return 1;
+        }
+        let index = match args.first() {
+            None => 0,
+            Some(arg) => match stack_index(arg, entries.len()) {
+                Some(n) => n,
+                None => {
+                    eprintln!("popd: {}: invalid argument", arg);
+                    return 1;
+                }
+            },
+        };
+        if index == 0 {
+            entries.remove(0);
+            if let Err(e) = directories.set_entries(entries) {
+                eprintln!("popd: {}", e);
+                return 1;
+            }
+        } else {
+            directories.stack.remove(index - 1);
+        }
+        print_entries(&directories.entries(), false, false, false);
+        0
+    }
+
+    pub fn dirs_builtin(&mut self, args: &[String]) -> i8 {
+        let mut long = false;
+        let mut per_line = false;
+        let mut verbose = false;
+        let mut selected = None;
+        let entries = self.directories.read().unwrap().entries();
+        for arg in args {
+            match arg.as_ref() {
+                "-c" => {
+                    self.directories.write().unwrap().stack.clear();
+                    return 0;
+                }
+                "-l" => long = true,
+                "-p" => per_line = true,
+                "-v" => verbose = true,
+                other if is_stack_index(other) => match stack_index(other, entries.len()) {
+                    Some(n) => selected = Some(n),
+                    None => {
+                        eprintln!("dirs: {}: directory stack index out of range", other);
+                        return 1;
+                    }
+                },
+                other => {
+                    eprintln!("dirs: {}: invalid option", other);
+                    eprintln!("dirs: usage: dirs [-clpv] [+N] [-N]");
+                    return 2;
+                }
+            }
+        }
+        match selected {
+            Some(n) => println!("{}", if long { entries[n].display().to_string() } else { abbreviate(&entries[n]) }),
+            None => print_entries(&entries, long, per_line, verbose),
+        }
+        0
+    }
+
+    pub fn prevd_builtin(&mut self, args: &[String]) -> i8 {
+        self.move_in_history("prevd", args, false)
+    }
+
+    pub fn nextd_builtin(&mut self, args: &[String]) -> i8 {
+        self.move_in_history("nextd", args, true)
+    }
+
+    fn move_in_history(&mut self, name: &str, args: &[String], forward: bool) -> i8 {
+        let count = match args.first() {
+            None => 1,
+            Some(arg) => match arg.parse::<usize>() {
+                Ok(count) => count,
+                Err(_) => {
+                    eprintln!("{}: {}: invalid count", name, arg);
+                    return 2;
+                }
+            },
+        };
+        let mut directories = self.directories.write().unwrap();
+        let result = if forward { directories.next(count) } else { directories.previous(count) };
+        match result {
+            Ok(_) => 0,
+            Err(e) => {
+                eprintln!("{}: {}", name, e);
+                1
+            }
+        }
+    }
+}
+
+fn print_entries(entries: &[PathBuf], long: bool, per_line: bool, verbose: bool) {
+    let names: Vec<String> = entries
+        .iter()
+        .map(|e| if long { e.display().to_string() } else { abbreviate(e) })
+        .collect();
+    if verbose {
+        for (index, name) in names.iter().enumerate() {
+            println!("{:2}  {}", index, name);
+        }
+    } else if per_line {
+        for name in names {
+            println!("{}", name);
+        }
+    } else {
+        println!("{}", names.join(" "));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_logical_path() {
+        assert_eq!(logical_path(Path::new("/usr/lib"), Path::new("share/./doc")), PathBuf::from("/usr/lib/share/doc"));
+        assert_eq!(logical_path(Path::new("/usr/lib"), Path::new("../bin")), PathBuf::from("/usr/bin"));
+        assert_eq!(logical_path(Path::new("/usr/lib"), Path::new("/etc/..")), PathBuf::from("/"));
+        // `..` never goes above the root
+        assert_eq!(logical_path(Path::new("/usr"), Path::new("../../..")), PathBuf::from("/"));
+    }
+
+    #[test]
+    fn test_parent_of_symlinked_directory() {
+        let base = env::temp_dir().join(format!("radish-directories-{}", nix::unistd::getpid()));
+        let real = base.join("real/inner");
+        let link = base.join("link");
+        ::std::fs::create_dir_all(&real).unwrap();
+        ::std::os::unix::fs::symlink(&real, &link).unwrap();
+        // `..` goes back to where the link is, not to the parent of where it points
+        assert_eq!(logical_path(&link, Path::new("..")), base);
+        assert_eq!(logical_path(&link.join("."), Path::new("../link")), link);
+        assert_eq!(link.join("..").canonicalize().unwrap(), base.join("real").canonicalize().unwrap());
+        ::std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_stack_index() {
+        assert_eq!(stack_index("+0", 3), Some(0));
+        assert_eq!(stack_index("+2", 3), Some(2));
+        assert_eq!(stack_index("-0", 3), Some(2));
+        assert_eq!(stack_index("-2", 3), Some(0));
+    }
+
+    #[test]
+    fn test_stack_index_out_of_range() {
+        assert_eq!(stack_index("+3", 3), None);
+        assert_eq!(stack_index("-3", 3), None);
+        assert_eq!(stack_index("-0", 0), None);
+        assert_eq!(stack_index("+", 3), None);
+        assert_eq!(stack_index("1", 3), None);
+        assert_eq!(stack_index("+x", 3), None);
+        assert_eq!(stack_index("+99999999999999999999999", 3), None);
+    }
+
+    #[test]
+    fn test_is_stack_index() {
+        assert!(is_stack_index("+0"));
+        assert!(is_stack_index("-0"));
+        assert!(is_stack_index("+12"));
+        assert!(!is_stack_index("-"));
+        assert!(!is_stack_index("-L"));
+        assert!(!is_stack_index("+1a"));
+        assert!(!is_stack_index("12"));
+    }
+
+    #[test]
+    fn test_abbreviate() {
+        env::set_var("HOME", "/home/user/");
+        assert_eq!(abbreviate(Path::new("/home/user")), "~");
+        assert_eq!(abbreviate(Path::new("/home/user/src")), "~/src");
+        assert_eq!(abbreviate(Path::new("/home/username")), "/home/username");
+        env::set_var("HOME", "/home/user");
+        assert_eq!(abbreviate(Path::new("/home/user")), "~");
+        assert_eq!(abbreviate(Path::new("/home/user/src")), "~/src");
+        assert_eq!(abbreviate(Path::new("/home/username")), "/home/username");
+        assert_eq!(abbreviate(Path::new("/tmp")), "/tmp");
+    }
+}
@@ -1,10 +1,15 @@
 use std::process;
 use nix;
-use std::ffi::CString;
+use std::ffi::{CString, OsStr};
 use super::super::syntax::ast::{Argument, Expr};
 use super::super::syntax::tokens::StringLiteralComponent;
-use std::collections::HashMap;
-use std::path::PathBuf;
+use super::commands;
+use super::limits::{self, Limit};
+use super::timeout::{self, Timeout};
+use super::traps;
+use super::usage::{self, Usage};
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::env;
 use std::os::unix::io::{FromRawFd, RawFd};
 use std::fs;
@@ -14,6 +19,7 @@ use std::io::{stdin, stdout};
 use std::os::unix::io::AsRawFd;
 use std::ops::Deref;
 use std::sync::RwLock;
+use std::time::{Duration, Instant};
 extern crate glob;
 use self::glob::glob;
 
@@ -23,12 +29,16 @@ pub enum FdOption {
     Overwrite(PathBuf),
     Input(PathBuf),
     Fd(RawFd),
+    Close,
 }
 
+/// The redirections of a command, in the order they were written.
+pub type Redirections = Vec<(RawFd, FdOption)>;
+
 #[derive(Debug)]
 pub enum Configuration {
-    Command(PathBuf, Vec<String>, HashMap<RawFd, FdOption>),
-    Builtin(String, Vec<String>, HashMap<RawFd, FdOption>),
+    Command(PathBuf, Vec<String>, Redirections),
+    Builtin(String, Vec<String>, Redirections),
     Pipeline(Box<Job>, Box<Job>),
 }
 
@@ -42,18 +52,42 @@ pub enum Status {
     ),
 }
 
+/// Terminal settings of a job, saved when it is stopped so that `fg` can bring them back.
+#[derive(Clone)]
+pub struct TerminalModes(nix::sys::termios::Termios);
+
+impl fmt::Debug for TerminalModes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TerminalModes")
+    }
+}
+
 #[derive(Debug)]
 pub enum Error {
     Fork,
     StringEncoding,
     Subshell(Box<Error>),
     SubshellExecution,
-    CommandNotFound(PathBuf),
+    /// The command name and the arguments it was given.
+    CommandNotFound(PathBuf, Vec<String>),
     CorruptPath,
     LeftPipe(Box<Error>),
     RightPipe(Box<Error>),
     Pipe,
     Wait,
+    InvalidLimit(String),
+    InvalidTimeout(String),
+}
+
+impl Error {
+    /// The command that could not be found, with its arguments, if that is what went wrong.
+    pub fn missing_command(&self) -> Option<(&Path, &[String])> {
+        match *self {
+            Error::CommandNotFound(ref path, ref args) => Some((path, args)),
+            Error::Subshell(ref error) | Error::LeftPipe(ref error) | Error::RightPipe(ref error) => error.missing_command(),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -62,15 +96,50 @@ pub struct Job {
     pub output: Option<String>,
     configuration: Configuration,
     pub background: bool,
+    /// Job number, assigned when the job is placed in the background or stopped.
+    pub id: Option<usize>,
+    /// The command line the job was created from, as typed by the user.
+    pub command: String,
+    /// When the job was last started, stopped or continued; used to pick the current job.
+    pub last_active: Instant,
+    /// When the job was started and when its last process terminated.
+    pub started: Option<Instant>,
+    pub finished: Option<Instant>,
+    /// CPU time and peak memory of the job's processes, known once they have terminated.
+    pub usage: Usage,
+    disowned: bool,
+    /// Set by `disown -h`: the job stays in the job table but is not sent SIGHUP when the shell
+    /// exits.
+    pub no_hangup: bool,
+    terminal_modes: Option<TerminalModes>,
+    /// Resource limits set with the `limit` prefix, applied only in the forked child.
+    limits: Vec<Limit>,
+    /// Time limit set with the `timeout` prefix; a pipeline keeps it for all of its stages.
+    timeout: Option<Timeout>,
+    /// Whether the job's processes were put in a process group of their own. Without job
+    /// control they stay in the shell's, so they are signalled one by one instead.
+    own_group: bool,
 }
 
 pub trait BuiltinHandler {
     fn handle_builtin(&mut self, name: &str, args: &[String]) -> i8;
     fn is_builtin(&mut self, name: &str) -> bool;
+    /// Finds the executable for a command name, through the shell's table of commands seen before.
+    fn find_command(&mut self, name: &str) -> Result<PathBuf, Error>;
+    /// Whether a directory given as a command on its own should be changed into.
+    fn autocd(&self) -> bool;
+    /// Looks up a shell variable that is not in the environment, such as `?` or an array element.
+    fn variable(&self, name: &str) -> Option<String>;
+    /// Whether jobs get process groups of their own and the terminal, which only an interactive
+    /// shell does.
+    fn job_control(&self) -> bool;
 }
 
 impl Drop for Job {
     fn drop(&mut self) {
+        if self.disowned {
+            return;
+        }
         match self.configuration {
             Configuration::Command(_, _, _) => match self.get_status() {
                 Status::Started(pid, _, _) => match nix::sys::signal::kill(pid, nix::sys::signal::Signal::SIGKILL) {
@@ -87,28 +156,249 @@ impl Drop for Job {
 }
 
 impl Job {
+    fn new(configuration: Configuration, background: bool) -> Job {
+        Job {
+            status: RwLock::new(Status::NotStarted),
+            output: None,
+            configuration: configuration,
+            background: background,
+            id: None,
+            command: String::new(),
+            last_active: Instant::now(),
+            started: None,
+            finished: None,
+            usage: Usage::default(),
+            disowned: false,
+            no_hangup: false,
+            terminal_modes: None,
+            limits: Vec::new(),
+            timeout: None,
+            own_group: false,
+        }
+    }
+
+    /// A builtin job that never runs, standing in for a job in the job table in tests.
+    #[cfg(test)]
+    pub fn placeholder(id: usize, command: &str, last_active: Instant) -> Job {
+        let mut job = Job::new(Configuration::Builtin(String::from("true"), Vec::new(), Vec::new()), true);
+        job.id = Some(id);
+        job.command = command.to_owned();
+        job.last_active = last_active;
+        job
+    }
+
+    fn set_background(&mut self, background: bool) {
+        self.background = background;
+        if let Configuration::Pipeline(ref mut first, ref mut second) = self.configuration {
+            first.set_background(background);
+            second.set_background(background);
+        }
+    }
+
+    /// Exit statuses of the stages of a pipeline from left to right, or of the job itself if it
+    /// is not a pipeline.
+    pub fn stage_statuses(&self) -> Vec<i8> {
+        let mut statuses = match self.configuration {
+            Configuration::Pipeline(ref first, ref second) => {
+                let mut statuses = first.stage_statuses();
+                statuses.append(&mut second.stage_statuses());
+                statuses
+            }
+            _ => vec![exit_code(self.wait_status())],
+        };
+        if self.timed_out() {
+            if let Some(last) = statuses.last_mut() {
+                *last = 124;
+            }
+        }
+        statuses
+    }
+
+    /// The exit status of the job: that of its last stage, or 124 if it ran out of time.
+    pub fn exit_status(&self) -> i8 {
+        self.stage_statuses().last().cloned().unwrap_or(0)
+    }
+
+    /// How long the job took from start to finish, if it has finished.
+    pub fn elapsed(&self) -> Option<Duration> {
+        match (self.started, self.finished) {
+            (Some(started), Some(finished)) => Some(finished.duration_since(started)),
+            _ => None,
+        }
+    }
+
+    pub fn touch(&mut self) {
+        self.last_active = Instant::now();
+    }
+
+    /// Forgets about the job's processes so that dropping the job does not kill them.
+    pub fn disown(&mut self) {
+        self.disowned = true;
+        if let Configuration::Pipeline(ref mut first, ref mut second) = self.configuration {
+            first.disown();
+            second.disown();
+        }
+    }
+
+    pub fn pgid(&self) -> Option<nix::unistd::Pid> {
+        match self.get_status() {
+            Status::Started(_, pgid, _) => Some(pgid),
+            Status::NotStarted => None,
+        }
+    }
+
+    pub fn wait_status(&self) -> nix::sys::wait::WaitStatus {
+        match self.get_status() {
+            Status::Started(_, _, status) => status,
+            Status::NotStarted => nix::sys::wait::WaitStatus::StillAlive,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        match self.wait_status() {
+            nix::sys::wait::WaitStatus::StillAlive | nix::sys::wait::WaitStatus::Continued(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether any process of the job is still running; the status of a pipeline is that of
+    /// its last stage, which may finish before the others.
+    pub fn any_stage_running(&self) -> bool {
+        match self.configuration {
+            Configuration::Pipeline(ref first, ref second) => first.any_stage_running() || second.any_stage_running(),
+            _ => self.is_running(),
+        }
+    }
+
+    /// Sends a signal to every process of the job that has not been reaped yet: to its process
+    /// group when it has one, and otherwise to each process, so that the shell never signals
+    /// its own group. Fails only if no process could be signalled.
+    pub fn signal<T: Into<Option<nix::sys::signal::Signal>>>(&self, signal: T) -> nix::Result<()> {
+        let signal = signal.into();
+        if self.own_group {
+            return match self.pgid() {
+                Some(pgid) => nix::sys::signal::kill(nix::unistd::Pid::from_raw(-nix::libc::pid_t::from(pgid)), signal),
+                None => Ok(()),
+            };
+        }
+        match self.configuration {
+            Configuration::Pipeline(ref first, ref second) => {
+                let first_result = first.signal(signal);
+                let second_result = second.signal(signal);
+                first_result.or(second_result)
+            }
+            _ => match self.get_status() {
+                // a builtin that ran in the shell process has nothing left to signal
+                Status::Started(pid, _, _) if pid == nix::unistd::getpid() => Err(nix::Error::Sys(nix::Errno::ESRCH)),
+                Status::Started(pid, _, _) if !self.finished() => nix::sys::signal::kill(pid, signal),
+                Status::Started(_, _, _) => Err(nix::Error::Sys(nix::Errno::ESRCH)),
+                Status::NotStarted => Ok(()),
+            },
+        }
+    }
+
+    fn finished(&self) -> bool {
+        match self.configuration {
+            Configuration::Pipeline(ref first, ref second) => first.finished() && second.finished(),
+            _ => match self.wait_status() {
+                nix::sys::wait::WaitStatus::Exited(_, _) | nix::sys::wait::WaitStatus::Signaled(_, _, _) => true,
+                _ => false,
+            },
+        }
+    }
+
+    pub fn timed_out(&self) -> bool {
+        self.timeout.map(|timeout| timeout.expired()).unwrap_or(false)
+    }
+
+    /// Time left until the job's timeout sends its next signal.
+    pub fn timeout_remaining(&self) -> Option<Duration> {
+        match self.timeout {
+            Some(ref timeout) if !self.finished() => timeout.remaining(),
+            _ => None,
+        }
+    }
+
+    /// Sends the job SIGTERM or SIGKILL if its timeout calls for it.
+    pub fn check_timeout(&mut self) {
+        if self.finished() {
+            return;
+        }
+        let stopped = !self.is_running();
+        if self.pgid().is_some() {
+            match self.timeout.as_mut().and_then(|timeout| timeout.due()) {
+                Some(nix::sys::signal::Signal::SIGTERM) => {
+                    let _ = self.signal(nix::sys::signal::Signal::SIGTERM);
+                    // a stopped job would not act on SIGTERM until it is continued
+                    let _ = self.signal(nix::sys::signal::Signal::SIGCONT);
+                }
+                Some(signal) => {
+                    let _ = self.signal(signal);
+                }
+                None => {}
+            }
+        }
+        // the job was continued along with SIGTERM so that it can act on it
+        if stopped && self.timed_out() {
+            let _ = self.cont(true);
+        }
+    }
+
+    /// Describes the state of the job the way `jobs` shows it, e.g. `Running` or `Exit 1`.
+    pub fn describe(&self) -> String {
+        match self.wait_status() {
+            nix::sys::wait::WaitStatus::StillAlive | nix::sys::wait::WaitStatus::Continued(_) => String::from("Running"),
+            nix::sys::wait::WaitStatus::Stopped(_, nix::sys::signal::Signal::SIGTTIN) => String::from("Stopped (tty input)"),
+            nix::sys::wait::WaitStatus::Stopped(_, nix::sys::signal::Signal::SIGTTOU) => String::from("Stopped (tty output)"),
+            nix::sys::wait::WaitStatus::Stopped(_, _) => String::from("Stopped"),
+            _ if self.any_stage_running() => String::from("Running"),
+            _ if self.timed_out() => String::from("Timed out"),
+            nix::sys::wait::WaitStatus::Exited(_, 0) => String::from("Done"),
+            nix::sys::wait::WaitStatus::Exited(_, code) => format!("Exit {}", code as u8),
+            nix::sys::wait::WaitStatus::Signaled(_, signal, core_dumped) => {
+                let name = match signal {
+                    nix::sys::signal::Signal::SIGKILL => String::from("Killed"),
+                    nix::sys::signal::Signal::SIGTERM => String::from("Terminated"),
+                    nix::sys::signal::Signal::SIGINT => String::from("Interrupt"),
+                    nix::sys::signal::Signal::SIGHUP => String::from("Hangup"),
+                    nix::sys::signal::Signal::SIGQUIT => String::from("Quit"),
+                    nix::sys::signal::Signal::SIGABRT => String::from("Aborted"),
+                    nix::sys::signal::Signal::SIGSEGV => String::from("Segmentation fault"),
+                    nix::sys::signal::Signal::SIGPIPE => String::from("Broken pipe"),
+                    other => format!("{:?}", other),
+                };
+                if core_dumped {
+                    name + " (core dumped)"
+                } else {
+                    name
+                }
+            }
+            _ => String::from("Unknown"),
+        }
+    }
+
     pub fn from_expr<B: BuiltinHandler>(expr: &Expr, builtin_handler: &mut B) -> Result<Job, Error> {
         match expr {
             &Expr::Command(binary, ref arguments) => {
-                let mut fd_options = HashMap::<RawFd, FdOption>::new();
+                let mut fd_options = Redirections::new();
                 let mut background = false;
                 let mut str_arguments = Vec::<String>::new();
                 for arg in arguments {
                     match arg {
                         &Argument::Redirect(fd, path) => {
-                            fd_options.insert(
-                                fd,
-                                FdOption::Overwrite(PathBuf::from(join_components(path))),
-                            );
+                            fd_options.push((fd, FdOption::Overwrite(PathBuf::from(join_components(path, builtin_handler)))));
                         }
                         &Argument::RedirectFD(fd, target) => {
-                            fd_options.insert(fd, FdOption::Fd(target));
+                            fd_options.push((fd, FdOption::Fd(target)));
+                        }
+                        &Argument::Close(fd) => {
+                            fd_options.push((fd, FdOption::Close));
                         }
                         &Argument::Append(fd, path) => {
-                            fd_options.insert(fd, FdOption::Append(PathBuf::from(join_components(path))));
+                            fd_options.push((fd, FdOption::Append(PathBuf::from(join_components(path, builtin_handler)))));
                         }
                         &Argument::Input(fd, path) => {
-                            fd_options.insert(fd, FdOption::Input(PathBuf::from(join_components(path))));
+                            fd_options.push((fd, FdOption::Input(PathBuf::from(join_components(path, builtin_handler)))));
                         }
                         &Argument::Background => {
                             background = true;
@@ -128,7 +418,7 @@ impl Job {
                             }
                         },
                         &Argument::Literal(s) => {
-                            let joined = join_components(s);
+                            let joined = join_components(s, builtin_handler);
                             if let Ok(it) = glob(&joined) {
                                 let mut glob_components: Vec<String> = Vec::new();
                                 let mut glob_valid = true;
@@ -159,65 +449,79 @@ impl Job {
                         }
                     };
                 }
-                let binary_str = join_components(binary);
-                if builtin_handler.is_builtin(&binary_str) {
-                    Ok(Job {
-                        status: RwLock::new(Status::NotStarted),
-                        output: None,
-                        configuration: Configuration::Builtin(binary_str, str_arguments, fd_options),
-                        background: background,
-                    })
-                } else if let Some(path_os_str) = env::var_os("PATH") {
-                    let binary_path = PathBuf::from(&binary_str);
-                    if let Ok(resolved) = binary_path.canonicalize() {
-                        if resolved.is_file() {
-                            Ok(Job {
-                                status: RwLock::new(Status::NotStarted),
-                                output: None,
-                                configuration: Configuration::Command(resolved, str_arguments, fd_options),
-                                background: background,
-                            })
-                        } else {
-                            Err(Error::CommandNotFound(binary_path))
-                        }
+                let mut binary_str = join_components(binary, builtin_handler);
+                let mut limits = Vec::new();
+                let mut time_limit = None;
+                let mut default_path = false;
+                // `limit mem=2G -- command args` and `timeout 30s command args` run the command
+                // with the limits, and `command [-p] name args` runs it without looking at
+                // functions; without a command the builtins of the same name print their usage
+                loop {
+                    let rest = match binary_str.as_ref() {
+                        "limit" => match limits::split_prefix(&str_arguments) {
+                            Ok((mut parsed, rest)) if rest.len() > 0 => {
+                                limits.append(&mut parsed);
+                                rest.to_vec()
+                            }
+                            Ok(_) => break,
+                            Err(e) => return Err(Error::InvalidLimit(e)),
+                        },
+                        "timeout" if str_arguments.len() > 0 => match timeout::split_prefix(&str_arguments) {
+                            Ok((parsed, rest)) if rest.len() > 0 => {
+                                time_limit = Some(parsed);
+                                rest.to_vec()
+                            }
+                            Ok(_) => break,
+                            Err(e) => return Err(Error::InvalidTimeout(e)),
+                        },
+                        "command" => match commands::split_prefix(&str_arguments) {
+                            Some((use_default_path, rest)) if rest.len() > 0 => {
+                                default_path = default_path || use_default_path;
+                                rest.to_vec()
+                            }
+                            _ => break,
+                        },
+                        _ => break,
+                    };
+                    binary_str = rest[0].clone();
+                    str_arguments = rest[1..].to_vec();
+                }
+                let mut job = if builtin_handler.is_builtin(&binary_str) {
+                    Job::new(Configuration::Builtin(binary_str, str_arguments, fd_options), background)
+                } else {
+                    let resolved = if default_path {
+                        commands::find_in_default_path(&binary_str)
                     } else {
-                        if let Some(binary_appended_path) = env::split_paths(&path_os_str)
-                            .filter_map(|mut f| {
-                                f.push(&binary_path);
-                                let appended_path = f.as_path();
-                                if appended_path.exists() && appended_path.is_file() {
-                                    Some(appended_path.to_path_buf())
-                                } else {
-                                    None
-                                }
-                            })
-                            .next()
-                        {
-                            Ok(Job {
-                                status: RwLock::new(Status::NotStarted),
-                                output: None,
-                                configuration: Configuration::Command(binary_appended_path, str_arguments, fd_options),
-                                background: background,
-                            })
-                        } else {
-                            Err(Error::CommandNotFound(binary_path))
+                        builtin_handler.find_command(&binary_str)
+                    };
+                    match resolved {
+                        Ok(path) => Job::new(Configuration::Command(path, str_arguments, fd_options), background),
+                        // with `autocd`, a directory that is not a command runs `cd` into it
+                        Err(Error::CommandNotFound(_, _)) if str_arguments.is_empty() && builtin_handler.autocd() && Path::new(&binary_str).is_dir() => {
+                            Job::new(Configuration::Builtin("cd".to_owned(), vec![binary_str], fd_options), background)
                         }
+                        Err(Error::CommandNotFound(path, _)) => return Err(Error::CommandNotFound(path, str_arguments)),
+                        Err(e) => return Err(e),
                     }
-                } else {
-                    Err(Error::CorruptPath)
-                }
+                };
+                job.limits = limits;
+                job.timeout = time_limit;
+                Ok(job)
             }
             &Expr::Pipeline(ref first, ref second) => {
                 let first_result = Job::from_expr(&first, builtin_handler);
                 let second_result = Job::from_expr(&second, builtin_handler);
-                if let Ok(f) = first_result {
-                    if let Ok(s) = second_result {
-                        Ok(Job {
-                            status: RwLock::new(Status::NotStarted),
-                            output: None,
-                            configuration: Configuration::Pipeline(Box::new(f), Box::new(s)),
-                            background: false,
-                        })
+                if let Ok(mut f) = first_result {
+                    if let Ok(mut s) = second_result {
+                        // `&` is parsed as part of the last stage, but it applies to the whole pipeline
+                        let background = f.background || s.background;
+                        f.set_background(background);
+                        s.set_background(background);
+                        // a timeout on either stage covers the whole pipeline
+                        let time_limit = f.timeout.take().or_else(|| s.timeout.take());
+                        let mut job = Job::new(Configuration::Pipeline(Box::new(f), Box::new(s)), background);
+                        job.timeout = time_limit;
+                        Ok(job)
                     } else {
                         Err(Error::RightPipe(Box::new(second_result.unwrap_err())))
                     }
@@ -234,11 +538,14 @@ impl Job {
                 Status::Started(pid, pgid, nix::sys::wait::WaitStatus::Stopped(_, _)) => {
                     if !background {
                         assert!(self.in_foreground());
+                        if let Some(TerminalModes(ref modes)) = self.terminal_modes {
+                            // the job already owns the terminal, so the shell is a background process here
+                            without_tty_signals(|| {
+                                let _ = nix::sys::termios::tcsetattr(stdin().as_raw_fd(), nix::sys::termios::SetArg::TCSADRAIN, modes);
+                            });
+                        }
                     }
                     match self.configuration {
-                        Configuration::Builtin(_, _, _) => {
-                            panic!("builtin should never be stopped");
-                        }
                         Configuration::Pipeline(ref mut first, ref mut second) => {
                             let first_result = first.cont(background);
                             let second_result = second.cont(background);
@@ -252,11 +559,8 @@ impl Job {
                                 Err(e) => Err(e),
                             }
                         }
-                        Configuration::Command(_, _, _) => {
-                            let result = nix::sys::signal::kill(
-                                nix::unistd::Pid::from_raw(-nix::libc::pid_t::from(pgid)),
-                                nix::sys::signal::SIGCONT,
-                            );
+                        Configuration::Builtin(_, _, _) | Configuration::Command(_, _, _) => {
+                            let result = self.signal(nix::sys::signal::SIGCONT);
                             match result {
                                 Ok(_) => Ok(Status::Started(
                                     pid,
@@ -281,23 +585,13 @@ impl Job {
     }
 
     fn set_term_group(&self, pgid: nix::unistd::Pid) {
-        let block_sigaction = nix::sys::signal::SigAction::new(
-            nix::sys::signal::SigHandler::SigIgn,
-            nix::sys::signal::SaFlags::empty(),
-            nix::sys::signal::SigSet::empty(),
-        );
-        let default_sigaction = nix::sys::signal::SigAction::new(
-            nix::sys::signal::SigHandler::SigDfl,
-            nix::sys::signal::SaFlags::empty(),
-            nix::sys::signal::SigSet::empty(),
-        );
-        unsafe {
-            nix::sys::signal::sigaction(nix::sys::signal::Signal::SIGTTOU, &block_sigaction).expect("failed to block SIGTTOU");
-            nix::sys::signal::sigaction(nix::sys::signal::Signal::SIGTTIN, &block_sigaction).expect("failed to block SIGTTIN");
-            nix::unistd::tcsetpgrp(stdin().as_raw_fd(), pgid).expect("failed to reset terminal group");
-            nix::sys::signal::sigaction(nix::sys::signal::Signal::SIGTTOU, &default_sigaction).expect("failed to restore SIGTTOU");
-            nix::sys::signal::sigaction(nix::sys::signal::Signal::SIGTTIN, &default_sigaction).expect("failed to restore SIGTTIN");
-        }
+        without_tty_signals(|| {
+            // without a controlling terminal, as when running a script, there is nothing to hand over
+            match nix::unistd::tcsetpgrp(stdin().as_raw_fd(), pgid) {
+                Ok(_) | Err(nix::Error::Sys(nix::Errno::ENOTTY)) => {}
+                Err(e) => panic!("failed to reset terminal group: {:?}", e),
+            }
+        });
     }
 
     pub fn set_foreground(&self) {
@@ -306,7 +600,9 @@ impl Job {
                 panic!("cannot set foreground if job has not started");
             }
             Status::Started(_, pgid, _) => {
-                self.set_term_group(pgid);
+                if self.own_group {
+                    self.set_term_group(pgid);
+                }
             }
         }
     }
@@ -314,42 +610,72 @@ impl Job {
     pub fn in_foreground(&self) -> bool {
         match self.get_status() {
             Status::NotStarted => false,
+            // without job control, jobs share the shell's place on the terminal
+            Status::Started(_, _, _) if !self.own_group => true,
             Status::Started(_, pgid, _) => {
-                let current_pgid = nix::unistd::tcgetpgrp(stdin().as_raw_fd()).expect("failed to tcgetpgrp stdin");
-                pgid == current_pgid
+                match nix::unistd::tcgetpgrp(stdin().as_raw_fd()) {
+                    Ok(current_pgid) => pgid == current_pgid,
+                    // every job is in the foreground when there is no terminal to own
+                    Err(_) => true,
+                }
             }
         }
     }
 
     pub fn wait(&mut self, flags: Option<nix::sys::wait::WaitPidFlag>) -> nix::Result<nix::sys::wait::WaitStatus> {
         let result = self.wait_without_restore(flags);
-        self.set_term_group(nix::unistd::getpgid(None).unwrap());
+        self.take_back_terminal(&result);
         result
     }
 
+    /// Like `wait` with `WNOHANG`, but returns `None` until every process of the job has
+    /// changed state and leaves the terminal to the job until then.
+    pub fn try_wait(&mut self, flags: nix::sys::wait::WaitPidFlag) -> nix::Result<Option<nix::sys::wait::WaitStatus>> {
+        let result = self.wait_without_restore(Some(flags | nix::sys::wait::WNOHANG));
+        match result {
+            Ok(nix::sys::wait::WaitStatus::StillAlive) | Ok(nix::sys::wait::WaitStatus::Continued(_)) => Ok(None),
+            Ok(_) if self.any_stage_running() => Ok(None),
+            _ => {
+                self.take_back_terminal(&result);
+                result.map(Some)
+            }
+        }
+    }
+
+    fn take_back_terminal(&mut self, result: &nix::Result<nix::sys::wait::WaitStatus>) {
+        if !self.own_group {
+            return;
+        }
+        if let &Ok(nix::sys::wait::WaitStatus::Stopped(_, _)) = result {
+            if self.in_foreground() {
+                self.terminal_modes = nix::sys::termios::tcgetattr(stdin().as_raw_fd()).ok().map(TerminalModes);
+            }
+        }
+        self.set_term_group(nix::unistd::getpgid(None).unwrap());
+    }
+
     fn wait_without_restore(&mut self, flags: Option<nix::sys::wait::WaitPidFlag>) -> nix::Result<nix::sys::wait::WaitStatus> {
+        let previous_usage = self.usage;
         let result_status = {
             match self.configuration {
-                Configuration::Builtin(_, _, _) => match self.get_status() {
-                    Status::Started(_, _, s) => Ok((s, self.get_status())),
-                    Status::NotStarted => panic!("builtin should not be running"),
-                },
-                Configuration::Command(_, _, _) => match self.get_status() {
+                // builtins that ran in the shell process have already exited; forked ones are
+                // waited for like commands
+                Configuration::Builtin(_, _, _) | Configuration::Command(_, _, _) => match self.get_status() {
                     Status::Started(pid, pgid, status) => match status {
                         nix::sys::wait::WaitStatus::StillAlive | nix::sys::wait::WaitStatus::Continued(_) => {
-                            let wait_result = nix::sys::wait::waitpid(pid, flags);
+                            let wait_result = usage::wait4(pid, flags);
                             match wait_result {
-                                Ok(result) => Ok((result, Status::Started(pid, pgid, result))),
+                                Ok((result, usage)) => Ok((result, Status::Started(pid, pgid, result), usage)),
                                 Err(e) => Err(e),
                             }
                         }
-                        _ => Ok((status, Status::Started(pid, pgid, status))),
+                        _ => Ok((status, Status::Started(pid, pgid, status), previous_usage)),
                     },
                     Status::NotStarted => Err(nix::Error::from_errno(nix::Errno::ESRCH)),
                 },
                 Configuration::Pipeline(ref mut first, ref mut second) => match first.wait_without_restore(flags) {
                     Ok(_) => match second.wait_without_restore(flags) {
-                        Ok(r) => Ok((r, second.get_status())),
+                        Ok(r) => Ok((r, second.get_status(), first.usage + second.usage)),
                         Err(e) => Err(e),
                     },
                     Err(e) => Err(e),
@@ -357,7 +683,8 @@ impl Job {
             }
         };
         match result_status {
-            Ok((actual_result, new_status)) => {
+            Ok((actual_result, new_status, usage)) => {
+                self.usage = usage;
                 self.set_status(new_status);
                 Ok(actual_result)
             }
@@ -366,6 +693,14 @@ impl Job {
     }
 
     fn set_status(&mut self, status: Status) {
+        match status {
+            Status::Started(_, _, nix::sys::wait::WaitStatus::Exited(_, _)) | Status::Started(_, _, nix::sys::wait::WaitStatus::Signaled(_, _, _)) => {
+                if self.finished.is_none() {
+                    self.finished = Some(Instant::now());
+                }
+            }
+            _ => {}
+        }
         *self.status.write().unwrap() = status;
     }
 
@@ -423,9 +758,13 @@ impl Job {
                 panic!("cannot re-run already running job");
             }
         };
+        self.started = Some(Instant::now());
+        if let Some(ref mut timeout) = self.timeout {
+            timeout.start();
+        }
         use nix::fcntl::*;
         use nix::sys::stat::*;
-        fn apply_fd_changes(input_fd: Option<RawFd>, output_fd: Option<RawFd>, options: &HashMap<RawFd, FdOption>) -> (bool, Vec<(RawFd, RawFd, Option<RawFd>)>) {
+        fn apply_fd_changes(input_fd: Option<RawFd>, output_fd: Option<RawFd>, options: &[(RawFd, FdOption)]) -> (bool, Vec<(RawFd, RawFd, Option<RawFd>)>) {
             let mut log: Vec<(RawFd, RawFd, Option<RawFd>)> = Vec::new();
             if let Some(input) = input_fd {
                 match nix::unistd::dup(stdin().as_raw_fd()) {
@@ -445,7 +784,14 @@ impl Job {
                     return (false, log);
                 }
             }
-            for (src, opt) in options {
+            for &(ref src, ref opt) in options {
+                // otherwise the saved copy of `src` could take the number of a closed target
+                if let &FdOption::Fd(dest) = opt {
+                    if let Err(e) = check_open(dest) {
+                        eprintln!("radish: {}", e);
+                        return (false, log);
+                    }
+                }
                 match nix::unistd::dup(*src) {
                     Ok(saved) => {
                         match opt {
@@ -455,6 +801,12 @@ impl Job {
                                     return (false, log);
                                 }
                             }
+                            &FdOption::Close => {
+                                log.push((*src, saved, None));
+                                if let Err(_) = nix::unistd::close(*src) {
+                                    return (false, log);
+                                }
+                            }
                             &FdOption::Append(ref path) => match nix::fcntl::open(
                                 path,
                                 O_WRONLY | O_CREAT | O_APPEND,
@@ -534,8 +886,119 @@ impl Job {
                 }
             });
         }
+        /// Forks a child that joins process group `pgid` (or a new one), sets up its file
+        /// descriptors, default signal dispositions and resource limits and then runs `child`,
+        /// exiting with the status it returns.
+        fn spawn<F: FnOnce() -> i32>(
+            job_control: bool,
+            background: bool,
+            limits: &[Limit],
+            input_fd: Option<RawFd>,
+            output_fd: Option<RawFd>,
+            options: &[(RawFd, FdOption)],
+            post_fork_close: &[RawFd],
+            pgid: Option<nix::unistd::Pid>,
+            child: F,
+        ) -> Result<Status, Error> {
+            if let Ok(fork_result) = nix::unistd::fork() {
+                match fork_result {
+                    nix::unistd::ForkResult::Parent { child } => {
+                        // without job control this is only the pid the job is known by, since
+                        // its processes stay in the shell's group and the terminal stays with it
+                        let child_pgid = pgid.unwrap_or(child);
+                        if job_control {
+                            match nix::unistd::setpgid(child, child_pgid) {
+                                // the child also joins its group itself; if it has already exec'd, it is in it
+                                Ok(_) | Err(nix::Error::Sys(nix::Errno::EACCES)) => {}
+                                Err(e) => panic!("failed to set process group for child: {:?}", e),
+                            }
+                            if !background {
+                                if let Ok(existing_group) = nix::unistd::tcgetpgrp(stdin().as_raw_fd()) {
+                                    if existing_group != child_pgid {
+                                        nix::unistd::tcsetpgrp(stdin().as_raw_fd(), child_pgid).expect("failed to tcsetpgrp stdin");
+                                    }
+                                }
+                            }
+                        }
+                        Ok(Status::Started(
+                            child,
+                            child_pgid,
+                            nix::sys::wait::WaitStatus::StillAlive,
+                        ))
+                    }
+                    nix::unistd::ForkResult::Child => {
+                        let (mut success, _) = apply_fd_changes(input_fd, output_fd, options);
+                        post_fork_close.into_iter().for_each(|fd| {
+                            if nix::unistd::close(*fd).is_err() {
+                                success = false;
+                            }
+                        });
+                        if success && job_control {
+                            if let Some(child_pgid) = pgid {
+                                nix::unistd::setpgid(nix::unistd::Pid::this(), child_pgid).expect("failed to setpgid to child pgid in child");
+                            } else {
+                                let child_pid = nix::unistd::getpid();
+                                nix::unistd::setpgid(nix::unistd::Pid::this(), child_pid).expect("failed to setpgid to child pid in child");
+                                //nix::unistd::setsid().expect("failed to create new session/process group in child");
+                            }
+                        }
+                        if success {
+                            traps::reset_for_child();
+                            if let Err(e) = limits::apply(limits) {
+                                eprintln!("radish: limit: {}", e);
+                                process::exit(1);
+                            }
+                            process::exit(child());
+                        }
+                        process::exit(-1);
+                    }
+                }
+            } else {
+                Err(Error::Fork)
+            }
+        }
+
+        let background = self.background;
+        let job_control = handler.job_control();
+        self.own_group = job_control;
+        let limits = &self.limits;
+        let timed = self.timeout.is_some();
         let result = {
             match self.configuration {
+                // `exec` in a process of its own would only replace or redirect that process
+                Configuration::Builtin(ref name, _, _) if name == "exec" && (background || input_fd.is_some() || output_fd.is_some() || limits.len() > 0 || timed) => {
+                    eprintln!("exec: cannot be used in a pipeline, a command substitution, the background or with `limit` or `timeout`");
+                    Ok(Status::Started(
+                        nix::unistd::getpid(),
+                        nix::unistd::getpgid(None).unwrap(), /* should always succeed */
+                        nix::sys::wait::WaitStatus::Exited(nix::unistd::getpid(), 1),
+                    ))
+                }
+                // builtins in a pipeline, a command substitution, the background or under `limit`
+                // or `timeout` get their own process, so that they neither block the shell nor
+                // change its state
+                Configuration::Builtin(ref name, ref args, ref options) if background || input_fd.is_some() || output_fd.is_some() || limits.len() > 0 || timed => {
+                    spawn(job_control, background, limits, input_fd, output_fd, options, post_fork_close, pgid, || {
+                        let result = handler.handle_builtin(&name, &args);
+                        let _ = stdout().flush();
+                        result as i32
+                    })
+                }
+                // `exec` changes the shell's own file descriptors for all later commands
+                Configuration::Builtin(ref name, ref args, ref options) if name == "exec" => {
+                    let result = match redirect_permanently(options) {
+                        Ok(_) => handler.handle_builtin(&name, &args),
+                        Err(e) => {
+                            eprintln!("exec: {}", e);
+                            1
+                        }
+                    };
+                    Ok(Status::Started(
+                        nix::unistd::getpid(),
+                        nix::unistd::getpgid(None).unwrap(), /* should always succeed */
+                        nix::sys::wait::WaitStatus::Exited(nix::unistd::getpid(), result),
+                    ))
+                }
                 Configuration::Builtin(ref name, ref args, ref options) => {
                     let (success, log) = apply_fd_changes(input_fd, output_fd, options);
                     let mut result: i8 = -1;
@@ -561,60 +1024,12 @@ impl Job {
                                     return Err(Error::StringEncoding);
                                 }
                             }
-                            if let Ok(fork_result) = nix::unistd::fork() {
-                                match fork_result {
-                                    nix::unistd::ForkResult::Parent { child } => {
-                                        let child_pgid = pgid.unwrap_or(child);
-                                        nix::unistd::setpgid(child, child_pgid).expect("failed to set process group for child");
-                                        if !self.background {
-                                            if let Ok(existing_group) = nix::unistd::tcgetpgrp(stdin().as_raw_fd()) {
-                                                if existing_group != child_pgid {
-                                                    nix::unistd::tcsetpgrp(stdin().as_raw_fd(), child_pgid).expect("failed to tcsetpgrp stdin");
-                                                }
-                                            }
-                                        }
-                                        Ok(Status::Started(
-                                            child,
-                                            child_pgid,
-                                            nix::sys::wait::WaitStatus::StillAlive,
-                                        ))
-                                    }
-                                    nix::unistd::ForkResult::Child => {
-                                        let (mut success, _) = apply_fd_changes(input_fd, output_fd, options);
-                                        post_fork_close.into_iter().for_each(|fd| {
-                                            if nix::unistd::close(*fd).is_err() {
-                                                success = false;
-                                            }
-                                        });
-                                        if success {
-                                            if let Some(child_pgid) = pgid {
-                                                nix::unistd::setpgid(nix::unistd::Pid::this(), child_pgid).expect("failed to setpgid to child pgid in child");
-                                            } else {
-                                                let child_pid = nix::unistd::getpid();
-                                                nix::unistd::setpgid(nix::unistd::Pid::this(), child_pid).expect("failed to setpgid to child pid in child");
-                                                //nix::unistd::setsid().expect("failed to create new session/process group in child");
-                                            }
-                                            let default_sigaction = nix::sys::signal::SigAction::new(
-                                                nix::sys::signal::SigHandler::SigDfl,
-                                                nix::sys::signal::SaFlags::empty(),
-                                                nix::sys::signal::SigSet::empty(),
-                                            );
-                                            unsafe {
-                                                nix::sys::signal::sigaction(nix::sys::signal::Signal::SIGINT, &default_sigaction).expect("failed to set SIGINT");
-                                                nix::sys::signal::sigaction(nix::sys::signal::Signal::SIGTSTP, &default_sigaction).expect("failed to set SIGSTP");
-                                                nix::sys::signal::sigaction(nix::sys::signal::Signal::SIGQUIT, &default_sigaction).expect("failed to set SIGQUIT");
-                                                nix::sys::signal::sigaction(nix::sys::signal::Signal::SIGPIPE, &default_sigaction).expect("failed to set SIGPIPE");
-                                            }
-                                            match nix::unistd::execvp(&binary_cstring, &args_cstring) {
-                                                _ => {}
-                                            }
-                                        }
-                                        process::exit(-1);
-                                    }
+                            spawn(job_control, background, limits, input_fd, output_fd, options, post_fork_close, pgid, || {
+                                match nix::unistd::execvp(&binary_cstring, &args_cstring) {
+                                    _ => {}
                                 }
-                            } else {
-                                Err(Error::Fork)
-                            }
+                                -1
+                            })
                         } else {
                             Err(Error::StringEncoding)
                         }
@@ -672,14 +1087,182 @@ impl Job {
     }
 }
 
-fn join_components<'a>(components: &'a [StringLiteralComponent<'a>]) -> String {
+/// Descriptors below this are left to the user's redirections, such as `exec 3>log`; the
+/// shell keeps its own files above them.
+pub const FIRST_SHELL_FD: RawFd = 10;
+
+/// Moves one of the shell's own file descriptors out of the range left to the user.
+pub fn move_out_of_user_range(fd: RawFd) -> nix::Result<RawFd> {
+    if fd >= FIRST_SHELL_FD {
+        return Ok(fd);
+    }
+    let moved = nix::fcntl::fcntl(fd, nix::fcntl::FcntlArg::F_DUPFD_CLOEXEC(FIRST_SHELL_FD))?;
+    nix::unistd::close(fd)?;
+    Ok(moved)
+}
+
+/// Occupies the free descriptors below `FIRST_SHELL_FD` until it is dropped, so that files
+/// opened in the meantime by libraries that cannot be told where to put them, like sqlite, end
+/// up above them.
+pub struct UserFdReservation(Vec<RawFd>);
+
+impl UserFdReservation {
+    pub fn new() -> UserFdReservation {
+        let mut fds = Vec::new();
+        while let Ok(fd) = nix::fcntl::open("/dev/null", nix::fcntl::O_RDONLY | nix::fcntl::O_CLOEXEC, nix::sys::stat::Mode::empty()) {
+            if fd >= FIRST_SHELL_FD {
+                let _ = nix::unistd::close(fd);
+                break;
+            }
+            fds.push(fd);
+        }
+        UserFdReservation(fds)
+    }
+}
+
+impl Drop for UserFdReservation {
+    fn drop(&mut self) {
+        for fd in &self.0 {
+            let _ = nix::unistd::close(*fd);
+        }
+    }
+}
+
+/// Applies the redirections of `exec` to the shell process in the order they were written,
+/// without saving the old file descriptors, so that `exec 3>log 2>&3` sends errors to the log.
+fn redirect_permanently(options: &[(RawFd, FdOption)]) -> Result<(), String> {
+    use nix::fcntl::*;
+    use nix::sys::stat::*;
+    for &(fd, ref option) in options {
+        if fd >= FIRST_SHELL_FD {
+            return Err(format!("{}: used by the shell", fd));
+        }
+        let opened = match option {
+            &FdOption::Append(ref path) => (path, nix::fcntl::open(path, O_WRONLY | O_CREAT | O_APPEND, S_IWUSR | S_IRUSR | S_IRGRP | S_IROTH)),
+            &FdOption::Overwrite(ref path) => (path, nix::fcntl::open(path, O_WRONLY | O_CREAT | O_TRUNC, S_IWUSR | S_IRUSR | S_IRGRP | S_IROTH)),
+            &FdOption::Input(ref path) => (path, nix::fcntl::open(path, O_RDONLY, S_IWUSR /* ignored */)),
+            &FdOption::Fd(target) => {
+                if target >= FIRST_SHELL_FD {
+                    return Err(format!("{}: used by the shell", target));
+                }
+                check_open(target)?;
+                nix::unistd::dup2(target, fd).map_err(|e| format!("{}: {}", target, e))?;
+                continue;
+            }
+            &FdOption::Close => {
+                // closing a descriptor that is not open is not an error
+                let _ = nix::unistd::close(fd);
+                continue;
+            }
+        };
+        match opened {
+            (_, Ok(newfd)) => {
+                if newfd != fd {
+                    nix::unistd::dup2(newfd, fd).map_err(|e| format!("{}: {}", fd, e))?;
+                    let _ = nix::unistd::close(newfd);
+                }
+            }
+            (path, Err(e)) => return Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+    Ok(())
+}
+
+/// Fails with "Bad file descriptor" unless `fd` is open.
+fn check_open(fd: RawFd) -> Result<(), String> {
+    match nix::fcntl::fcntl(fd, nix::fcntl::FcntlArg::F_GETFD) {
+        Ok(_) => Ok(()),
+        Err(nix::Error::Sys(errno)) => Err(format!("{}: {}", fd, errno.desc())),
+        Err(e) => Err(format!("{}: {}", fd, e)),
+    }
+}
+
+/// Converts a wait status to a shell exit status; death by a signal is reported as 128 plus the
+/// signal number.
+pub fn exit_code(status: nix::sys::wait::WaitStatus) -> i8 {
+    match status {
+        nix::sys::wait::WaitStatus::Exited(_, code) => code,
+        nix::sys::wait::WaitStatus::Signaled(_, signal, _) => (128 + signal as i32) as i8,
+        nix::sys::wait::WaitStatus::Stopped(_, signal) => (128 + signal as i32) as i8,
+        _ => 0,
+    }
+}
+
+/// Runs `f` with SIGTTOU and SIGTTIN ignored, so that the shell can change the terminal while
+/// it is not in the foreground process group.
+fn without_tty_signals<F: FnOnce()>(f: F) {
+    let block_sigaction = nix::sys::signal::SigAction::new(
+        nix::sys::signal::SigHandler::SigIgn,
+        nix::sys::signal::SaFlags::empty(),
+        nix::sys::signal::SigSet::empty(),
+    );
+    let (previous_ttou, previous_ttin) = unsafe {
+        (
+            nix::sys::signal::sigaction(nix::sys::signal::Signal::SIGTTOU, &block_sigaction).expect("failed to block SIGTTOU"),
+            nix::sys::signal::sigaction(nix::sys::signal::Signal::SIGTTIN, &block_sigaction).expect("failed to block SIGTTIN"),
+        )
+    };
+    f();
+    unsafe {
+        nix::sys::signal::sigaction(nix::sys::signal::Signal::SIGTTOU, &previous_ttou).expect("failed to restore SIGTTOU");
+        nix::sys::signal::sigaction(nix::sys::signal::Signal::SIGTTIN, &previous_ttin).expect("failed to restore SIGTTIN");
+    }
+}
+
+fn join_components<'a, B: BuiltinHandler>(components: &'a [StringLiteralComponent<'a>], builtin_handler: &B) -> String {
     let strs: Vec<String> = components
         .into_iter()
         .map(|s| match s {
             &StringLiteralComponent::Literal(s) => String::from(s),
-            &StringLiteralComponent::EnvVar(v) => env::var(v).unwrap_or(String::from("")),
+            &StringLiteralComponent::EnvVar(v) => builtin_handler
+                .variable(v)
+                .unwrap_or_else(|| env::var(v).unwrap_or(String::from(""))),
             _ => String::from(""),
         })
         .collect();
     strs.join("")
 }
+
+/// Looks for an executable file called `name` in each directory of a `PATH`-style list.
+pub fn search_path(name: &str, path: &OsStr) -> Option<PathBuf> {
+    env::split_paths(path).map(|dir| dir.join(name)).find(|candidate| candidate.is_file())
+}
+
+/// Resolves a command name to an executable, either directly or by searching `PATH`.
+pub fn resolve_command(binary_str: &str) -> Result<PathBuf, Error> {
+    if let Some(path_os_str) = env::var_os("PATH") {
+        let binary_path = PathBuf::from(binary_str);
+        if let Ok(resolved) = binary_path.canonicalize() {
+            if resolved.is_file() {
+                Ok(resolved)
+            } else {
+                Err(Error::CommandNotFound(binary_path, Vec::new()))
+            }
+        } else {
+            search_path(binary_str, &path_os_str).ok_or(Error::CommandNotFound(binary_path, Vec::new()))
+        }
+    } else {
+        Err(Error::CorruptPath)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finished(status: nix::sys::wait::WaitStatus) -> Job {
+        let job = Job::placeholder(1, "true", Instant::now());
+        let pid = nix::unistd::Pid::from_raw(4242);
+        *job.status.write().unwrap() = Status::Started(pid, pid, status);
+        job
+    }
+
+    #[test]
+    fn test_describe_exit_codes() {
+        let pid = nix::unistd::Pid::from_raw(4242);
+        let table = [(0, "Done"), (1, "Exit 1"), (-126, "Exit 130"), (-1, "Exit 255")];
+        for &(code, expected) in &table {
+            assert_eq!(finished(nix::sys::wait::WaitStatus::Exited(pid, code)).describe(), expected);
+        }
+    }
+}
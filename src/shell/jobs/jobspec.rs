@@ -0,0 +1,237 @@
+use super::job::{Job, Status};
+use std::fmt;
+use nix;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Table {
+    Background,
+    Stopped,
+}
+
+/// Location of a job in the job table.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JobRef {
+    pub table: Table,
+    pub index: usize,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    NoSuchJob(String),
+    Ambiguous(String),
+    NoCurrentJob,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Error::NoSuchJob(ref spec) => write!(f, "{}: no such job", spec),
+            &Error::Ambiguous(ref spec) => write!(f, "{}: ambiguous job spec", spec),
+            &Error::NoCurrentJob => write!(f, "no current job"),
+        }
+    }
+}
+
+pub fn get<'a>(r: JobRef, background: &'a [Job], stopped: &'a [Job]) -> &'a Job {
+    match r.table {
+        Table::Background => &background[r.index],
+        Table::Stopped => &stopped[r.index],
+    }
+}
+
+/// Returns every job in the table, starting with the current job (`%+`) and then the previous
+/// job (`%-`). Stopped jobs take precedence over running ones, most recently active first.
+pub fn ordered(background: &[Job], stopped: &[Job]) -> Vec<JobRef> {
+    let mut stopped_refs: Vec<JobRef> = (0..stopped.len())
+        .map(|i| JobRef {
+            table: Table::Stopped,
+            index: i,
+        })
+        .collect();
+    let mut background_refs: Vec<JobRef> = (0..background.len())
+        .map(|i| JobRef {
+            table: Table::Background,
+            index: i,
+        })
+        .collect();
+    stopped_refs.sort_by(|a, b| get(*b, background, stopped).last_active.cmp(&get(*a, background, stopped).last_active));
+    background_refs.sort_by(|a, b| get(*b, background, stopped).last_active.cmp(&get(*a, background, stopped).last_active));
+    stopped_refs.append(&mut background_refs);
+    stopped_refs
+}
+
+/// Returns the `+`/`-` marker shown next to a job by `jobs`.
+pub fn marker(r: JobRef, background: &[Job], stopped: &[Job]) -> char {
+    let order = ordered(background, stopped);
+    if order.get(0) == Some(&r) {
+        '+'
+    } else if order.get(1) == Some(&r) {
+        '-'
+    } else {
+        ' '
+    }
+}
+
+/// Returns the smallest job number greater than every job number in use.
+pub fn next_id(background: &[Job], stopped: &[Job]) -> usize {
+    background
+        .iter()
+        .chain(stopped.iter())
+        .filter_map(|j| j.id)
+        .max()
+        .unwrap_or(0) + 1
+}
+
+/// Resolves a jobspec (`%n`, `%%`, `%+`, `%-`, `%string`, `%?string`) or a process id.
+pub fn resolve(spec: &str, background: &[Job], stopped: &[Job]) -> Result<JobRef, Error> {
+    let order = ordered(background, stopped);
+    if spec.starts_with('%') {
+        let rest = &spec[1..];
+        match rest {
+            "" | "%" | "+" => order.get(0).cloned().ok_or(Error::NoCurrentJob),
+            "-" => order.get(1).cloned().ok_or_else(|| Error::NoSuchJob(spec.to_owned())),
+            _ => {
+                let matches: Vec<JobRef> = if let Ok(number) = rest.parse::<usize>() {
+                    order.into_iter().filter(|r| get(*r, background, stopped).id == Some(number)).collect()
+                } else if rest.starts_with('?') {
+                    order.into_iter().filter(|r| get(*r, background, stopped).command.contains(&rest[1..])).collect()
+                } else {
+                    order.into_iter().filter(|r| get(*r, background, stopped).command.starts_with(rest)).collect()
+                };
+                match matches.len() {
+                    0 => Err(Error::NoSuchJob(spec.to_owned())),
+                    1 => Ok(matches[0]),
+                    _ => Err(Error::Ambiguous(spec.to_owned())),
+                }
+            }
+        }
+    } else if let Ok(pid) = spec.parse::<nix::libc::pid_t>() {
+        order
+            .into_iter()
+            .find(|r| match get(*r, background, stopped).get_status() {
+                Status::Started(job_pid, job_pgid, _) => nix::libc::pid_t::from(job_pid) == pid || nix::libc::pid_t::from(job_pgid) == pid,
+                Status::NotStarted => false,
+            })
+            .ok_or_else(|| Error::NoSuchJob(spec.to_owned()))
+    } else {
+        Err(Error::NoSuchJob(spec.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    /// Two running jobs and two stopped ones, each started a second after the one before.
+    fn jobs() -> (Vec<Job>, Vec<Job>) {
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs(seconds);
+        let background = vec![Job::placeholder(1, "sleep 100", at(0)), Job::placeholder(3, "make -j4", at(2))];
+        let stopped = vec![Job::placeholder(2, "vim notes", at(1)), Job::placeholder(4, "less log", at(3))];
+        (background, stopped)
+    }
+
+    fn id(result: Result<JobRef, Error>, background: &[Job], stopped: &[Job]) -> Option<usize> {
+        get(result.unwrap(), background, stopped).id
+    }
+
+    #[test]
+    fn test_ordered() {
+        let (background, stopped) = jobs();
+        let ids: Vec<Option<usize>> = ordered(&background, &stopped).into_iter().map(|r| get(r, &background, &stopped).id).collect();
+        // stopped jobs first, most recently active first
+        assert_eq!(ids, vec![Some(4), Some(2), Some(3), Some(1)]);
+    }
+
+    #[test]
+    fn test_markers() {
+        let (background, stopped) = jobs();
+        let stopped_ref = |index| JobRef {
+            table: Table::Stopped,
+            index: index,
+        };
+        let background_ref = |index| JobRef {
+            table: Table::Background,
+            index: index,
+        };
+        assert_eq!(marker(stopped_ref(1), &background, &stopped), '+');
+        assert_eq!(marker(stopped_ref(0), &background, &stopped), '-');
+        assert_eq!(marker(background_ref(0), &background, &stopped), ' ');
+        assert_eq!(marker(background_ref(1), &background, &stopped), ' ');
+        assert_eq!(marker(background_ref(1), &background, &[]), '+');
+        assert_eq!(marker(background_ref(0), &background, &[]), '-');
+    }
+
+    #[test]
+    fn test_resolve_current_and_previous() {
+        let (background, stopped) = jobs();
+        for spec in &["%", "%%", "%+"] {
+            assert_eq!(id(resolve(spec, &background, &stopped), &background, &stopped), Some(4));
+        }
+        assert_eq!(id(resolve("%-", &background, &stopped), &background, &stopped), Some(2));
+        assert!(match resolve("%%", &[], &[]) {
+            Err(Error::NoCurrentJob) => true,
+            _ => false,
+        });
+        assert_eq!(resolve("%-", &background[..1], &[]).unwrap_err().to_string(), "%-: no such job");
+    }
+
+    #[test]
+    fn test_resolve_by_number_and_command() {
+        let (background, stopped) = jobs();
+        let table = [
+            ("%1", Some(1)),
+            ("%3", Some(3)),
+            ("%vim", Some(2)),
+            ("%make", Some(3)),
+            ("%?log", Some(4)),
+            ("%?j4", Some(3)),
+        ];
+        for &(spec, expected) in &table {
+            assert_eq!(id(resolve(spec, &background, &stopped), &background, &stopped), expected, "{}", spec);
+        }
+    }
+
+    #[test]
+    fn test_resolve_errors() {
+        let (background, stopped) = jobs();
+        let table = [
+            ("%5", "%5: no such job"),
+            ("%emacs", "%emacs: no such job"),
+            ("%?xyz", "%?xyz: no such job"),
+            ("%?e", "%?e: ambiguous job spec"),
+            ("nonsense", "nonsense: no such job"),
+            ("12345", "12345: no such job"),
+        ];
+        for &(spec, expected) in &table {
+            assert_eq!(resolve(spec, &background, &stopped).unwrap_err().to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_resolve_ambiguous_prefix() {
+        let start = Instant::now();
+        let background = vec![Job::placeholder(1, "make all", start), Job::placeholder(2, "make test", start)];
+        assert_eq!(resolve("%make", &background, &[]).unwrap_err().to_string(), "%make: ambiguous job spec");
+        assert_eq!(id(resolve("%make t", &background, &[]), &background, &[]), Some(2));
+    }
+
+    #[test]
+    fn test_resolve_pid() {
+        let (background, stopped) = jobs();
+        let pid = nix::unistd::Pid::from_raw(4242);
+        let pgid = nix::unistd::Pid::from_raw(4240);
+        *background[1].status.write().unwrap() = Status::Started(pid, pgid, nix::sys::wait::WaitStatus::StillAlive);
+        assert_eq!(id(resolve("4242", &background, &stopped), &background, &stopped), Some(3));
+        assert_eq!(id(resolve("4240", &background, &stopped), &background, &stopped), Some(3));
+    }
+
+    #[test]
+    fn test_next_id() {
+        let (background, stopped) = jobs();
+        assert_eq!(next_id(&background, &stopped), 5);
+        assert_eq!(next_id(&background[..1], &[]), 2);
+        assert_eq!(next_id(&[], &[]), 1);
+    }
+}
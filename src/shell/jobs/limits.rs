@@ -0,0 +1,373 @@
+use super::JobManager;
+use nix;
+use nix::libc;
+use std::mem;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Size,
+    Time,
+    Count,
+}
+
+/// A resource that can be limited with `ulimit` or the `limit` prefix.
+struct Resource {
+    /// The `ulimit` option, e.g. `n` for `ulimit -n`.
+    option: char,
+    description: &'static str,
+    /// The name used by `limit`, e.g. `files` in `limit files=64`.
+    key: &'static str,
+    /// The `RLIMIT_*` constant. glibc gives these, and the argument of `getrlimit` and
+    /// `setrlimit`, an unsigned type, so they are kept as `c_int` and converted at each call.
+    resource: libc::c_int,
+    kind: Kind,
+}
+
+const RESOURCES: &[Resource] = &[
+    Resource {
+        option: 'c',
+        description: "core file size",
+        key: "core",
+        resource: libc::RLIMIT_CORE as libc::c_int,
+        kind: Kind::Size,
+    },
+    Resource {
+        option: 'd',
+        description: "data seg size",
+        key: "data",
+        resource: libc::RLIMIT_DATA as libc::c_int,
+        kind: Kind::Size,
+    },
+    Resource {
+        option: 'f',
+        description: "file size",
+        key: "fsize",
+        resource: libc::RLIMIT_FSIZE as libc::c_int,
+        kind: Kind::Size,
+    },
+    Resource {
+        option: 'n',
+        description: "open files",
+        key: "files",
+        resource: libc::RLIMIT_NOFILE as libc::c_int,
+        kind: Kind::Count,
+    },
+    Resource {
+        option: 's',
+        description: "stack size",
+        key: "stack",
+        resource: libc::RLIMIT_STACK as libc::c_int,
+        kind: Kind::Size,
+    },
+    Resource {
+        option: 't',
+        description: "cpu time",
+        key: "cpu",
+        resource: libc::RLIMIT_CPU as libc::c_int,
+        kind: Kind::Time,
+    },
+    Resource {
+        option: 'u',
+        description: "max user processes",
+        key: "procs",
+        resource: libc::RLIMIT_NPROC as libc::c_int,
+        kind: Kind::Count,
+    },
+    Resource {
+        option: 'v',
+        description: "virtual memory",
+        key: "mem",
+        resource: libc::RLIMIT_AS as libc::c_int,
+        kind: Kind::Size,
+    },
+];
+
+impl Resource {
+    /// `ulimit` counts sizes in kilobytes.
+    fn unit(&self) -> libc::rlim_t {
+        match self.kind {
+            Kind::Size => 1024,
+            Kind::Time | Kind::Count => 1,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self.kind {
+            Kind::Size => format!("{} (kbytes, -{})", self.description, self.option),
+            Kind::Time => format!("{} (seconds, -{})", self.description, self.option),
+            Kind::Count => format!("{} (-{})", self.description, self.option),
+        }
+    }
+
+    fn get(&self) -> nix::Result<libc::rlimit> {
+        let mut limit: libc::rlimit = unsafe { mem::zeroed() };
+        nix::Errno::result(unsafe { libc::getrlimit(self.resource as _, &mut limit) })?;
+        Ok(limit)
+    }
+
+    fn set(&self, limit: &libc::rlimit) -> nix::Result<()> {
+        nix::Errno::result(unsafe { libc::setrlimit(self.resource as _, limit) }).map(|_| ())
+    }
+}
+
+/// A limit given to the `limit` prefix, applied in the child process before it runs the
+/// command.
+#[derive(Clone, Copy, Debug)]
+pub struct Limit {
+    resource: libc::c_int,
+    value: libc::rlim_t,
+}
+
+fn format_value(value: libc::rlim_t, unit: libc::rlim_t) -> String {
+    if value == libc::RLIM_INFINITY {
+        String::from("unlimited")
+    } else {
+        (value / unit).to_string()
+    }
+}
+
+/// Parses a `limit` value such as `2G`, `30s` or `unlimited`. Sizes are in bytes unless
+/// suffixed with `K`, `M`, `G` or `T`; times are in seconds unless suffixed with `m` or `h`.
+fn parse_limit_value(value: &str, kind: Kind) -> Option<libc::rlim_t> {
+    if value == "unlimited" {
+        return Some(libc::RLIM_INFINITY);
+    }
+    let split = value.find(|c: char| !c.is_digit(10)).unwrap_or(value.len());
+    let number = value[..split].parse::<libc::rlim_t>().ok()?;
+    let multiplier: libc::rlim_t = match (kind, &value[split..]) {
+        (_, "") => 1,
+        (Kind::Size, "K") | (Kind::Size, "k") => 1 << 10,
+        (Kind::Size, "M") | (Kind::Size, "m") => 1 << 20,
+        (Kind::Size, "G") | (Kind::Size, "g") => 1 << 30,
+        (Kind::Size, "T") | (Kind::Size, "t") => 1 << 40,
+        (Kind::Time, "s") => 1,
+        (Kind::Time, "m") => 60,
+        (Kind::Time, "h") => 60 * 60,
+        _ => return None,
+    };
+    number.checked_mul(multiplier)
+}
+
+/// Splits the arguments of `limit mem=2G cpu=30s -- command args` into the limits and the
+/// command line they apply to. The `--` may be left out.
+pub fn split_prefix(args: &[String]) -> Result<(Vec<Limit>, &[String]), String> {
+    let mut limits = Vec::new();
+    let mut index = 0;
+    while index < args.len() {
+        let arg = &args[index];
+        if arg == "--" {
+            index += 1;
+            break;
+        }
+        let (key, value) = match arg.find('=') {
+            Some(position) => (&arg[..position], &arg[position + 1..]),
+            None => break,
+        };
+        let resource = match RESOURCES.iter().find(|r| r.key == key) {
+            Some(resource) => resource,
+            None => return Err(format!("{}: unknown resource", key)),
+        };
+        match parse_limit_value(value, resource.kind) {
+            Some(value) => limits.push(Limit {
+                resource: resource.resource,
+                value: value,
+            }),
+            None => return Err(format!("{}: invalid limit", arg)),
+        }
+        index += 1;
+    }
+    Ok((limits, &args[index..]))
+}
+
+/// Sets both the soft and the hard limit, so that the command cannot raise them again.
+pub fn apply(limits: &[Limit]) -> Result<(), String> {
+    for limit in limits {
+        let rlimit = libc::rlimit {
+            rlim_cur: limit.value,
+            rlim_max: limit.value,
+        };
+        if let Err(e) = nix::Errno::result(unsafe { libc::setrlimit(limit.resource as _, &rlimit) }) {
+            let key = RESOURCES.iter().find(|r| r.resource == limit.resource).map(|r| r.key).unwrap_or("?");
+            return Err(format!("{}: {}", key, e));
+        }
+    }
+    Ok(())
+}
+
+impl JobManager {
+    pub fn ulimit_builtin(&mut self, args: &[String]) -> i8 {
+        let mut soft = false;
+        let mut hard = false;
+        let mut all = false;
+        let mut selected: Vec<&Resource> = Vec::new();
+        let mut value = None;
+        for arg in args {
+            if arg.starts_with('-') && arg.len() > 1 {
+                for option in arg[1..].chars() {
+                    match option {
+                        'S' => soft = true,
+                        'H' => hard = true,
+                        'a' => all = true,
+                        _ => match RESOURCES.iter().find(|r| r.option == option) {
+                            Some(resource) => selected.push(resource),
+                            None => {
+                                eprintln!("ulimit: -{}: invalid option", option);
+                                eprintln!("ulimit: usage: ulimit [-SHacdfnstuv] [limit]");
+                                return 2;
+                            }
+                        },
+                    }
+                }
+            } else if value.is_none() {
+                value = Some(arg);
+            } else {
+                eprintln!("ulimit: {}: too many arguments", arg);
+                return 2;
+            }
+        }
+        if all {
+            selected = RESOURCES.iter().collect();
+        } else if selected.len() == 0 {
+            // like bash, `ulimit` on its own is about the file size
+            selected.extend(RESOURCES.iter().find(|r| r.key == "fsize"));
+        }
+        let mut result = 0;
+        match value {
+            None => for resource in &selected {
+                match resource.get() {
+                    Ok(limit) => {
+                        let current = if hard && !soft { limit.rlim_max } else { limit.rlim_cur };
+                        if selected.len() > 1 {
+                            println!("{:<36}{}", resource.describe(), format_value(current, resource.unit()));
+                        } else {
+                            println!("{}", format_value(current, resource.unit()));
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("ulimit: {}: cannot get limit: {}", resource.description, e);
+                        result = 1;
+                    }
+                }
+            },
+            Some(value) => for resource in &selected {
+                let mut limit = match resource.get() {
+                    Ok(limit) => limit,
+                    Err(e) => {
+                        eprintln!("ulimit: {}: cannot get limit: {}", resource.description, e);
+                        result = 1;
+                        continue;
+                    }
+                };
+                let new = match value.as_ref() {
+                    "unlimited" => libc::RLIM_INFINITY,
+                    "soft" => limit.rlim_cur,
+                    "hard" => limit.rlim_max,
+                    number => match number.parse::<libc::rlim_t>().ok().and_then(|n| n.checked_mul(resource.unit())) {
+                        Some(new) => new,
+                        None => {
+                            eprintln!("ulimit: {}: invalid number", number);
+                            return 1;
+                        }
+                    },
+                };
+                // without -S or -H both limits are set
+                if soft || !hard {
+                    limit.rlim_cur = new;
+                }
+                if hard || !soft {
+                    limit.rlim_max = new;
+                }
+                if let Err(e) = resource.set(&limit) {
+                    eprintln!("ulimit: {}: cannot modify limit: {}", resource.description, e);
+                    result = 1;
+                }
+            },
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|&arg| arg.to_owned()).collect()
+    }
+
+    #[test]
+    fn test_parse_sizes() {
+        assert_eq!(parse_limit_value("512", Kind::Size), Some(512));
+        assert_eq!(parse_limit_value("4K", Kind::Size), Some(4 << 10));
+        assert_eq!(parse_limit_value("3m", Kind::Size), Some(3 << 20));
+        assert_eq!(parse_limit_value("2G", Kind::Size), Some(2 << 30));
+        assert_eq!(parse_limit_value("1t", Kind::Size), Some(1 << 40));
+        assert_eq!(parse_limit_value("unlimited", Kind::Size), Some(libc::RLIM_INFINITY));
+    }
+
+    #[test]
+    fn test_parse_times() {
+        assert_eq!(parse_limit_value("30", Kind::Time), Some(30));
+        assert_eq!(parse_limit_value("30s", Kind::Time), Some(30));
+        assert_eq!(parse_limit_value("2m", Kind::Time), Some(120));
+        assert_eq!(parse_limit_value("1h", Kind::Time), Some(3600));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert_eq!(parse_limit_value("", Kind::Size), None);
+        assert_eq!(parse_limit_value("G", Kind::Size), None);
+        assert_eq!(parse_limit_value("-1", Kind::Size), None);
+        assert_eq!(parse_limit_value("2s", Kind::Size), None);
+        assert_eq!(parse_limit_value("2G", Kind::Time), None);
+        assert_eq!(parse_limit_value("2K", Kind::Count), None);
+        assert_eq!(parse_limit_value("2GB", Kind::Size), None);
+        assert_eq!(parse_limit_value("99999999999999999999", Kind::Size), None);
+        assert_eq!(parse_limit_value(&format!("{}T", libc::rlim_t::max_value()), Kind::Size), None);
+    }
+
+    #[test]
+    fn test_split_prefix() {
+        let args = strings(&["mem=2G", "cpu=30s", "--", "make", "-j4"]);
+        let (limits, rest) = split_prefix(&args).unwrap();
+        assert_eq!(rest, &args[3..]);
+        assert_eq!(limits.len(), 2);
+        assert_eq!(limits[0].resource, libc::RLIMIT_AS as libc::c_int);
+        assert_eq!(limits[0].value, 2 << 30);
+        assert_eq!(limits[1].resource, libc::RLIMIT_CPU as libc::c_int);
+        assert_eq!(limits[1].value, 30);
+    }
+
+    #[test]
+    fn test_split_prefix_without_separator() {
+        let args = strings(&["files=64", "ls", "a=b"]);
+        let (limits, rest) = split_prefix(&args).unwrap();
+        assert_eq!(limits.len(), 1);
+        assert_eq!(limits[0].resource, libc::RLIMIT_NOFILE as libc::c_int);
+        assert_eq!(rest, &args[1..]);
+    }
+
+    #[test]
+    fn test_split_prefix_without_command() {
+        let args = strings(&["core=0", "--"]);
+        let (limits, rest) = split_prefix(&args).unwrap();
+        assert_eq!(limits.len(), 1);
+        assert!(rest.is_empty());
+        let (limits, rest) = split_prefix(&[]).unwrap();
+        assert!(limits.is_empty() && rest.is_empty());
+    }
+
+    #[test]
+    fn test_split_prefix_errors() {
+        assert_eq!(split_prefix(&strings(&["disk=1G", "ls"])).unwrap_err(), "disk: unknown resource");
+        assert_eq!(split_prefix(&strings(&["mem=lots", "ls"])).unwrap_err(), "mem=lots: invalid limit");
+    }
+
+    #[test]
+    fn test_resources_have_distinct_names() {
+        for (index, resource) in RESOURCES.iter().enumerate() {
+            assert!(RESOURCES[index + 1..].iter().all(|r| r.key != resource.key && r.option != resource.option));
+        }
+        assert!(RESOURCES.iter().any(|r| r.key == "fsize"));
+    }
+}
@@ -1,23 +1,101 @@
 use super::syntax::ast::Expr;
+use super::history::Abbreviations;
 use std::env;
 extern crate users;
 mod job;
-pub use self::job::Job;
+pub use self::job::{resolve_command, search_path, BuiltinHandler, Job, UserFdReservation};
+mod jobspec;
+use self::jobspec::Table;
+mod commands;
+use self::commands::CommandTable;
+mod conditions;
+mod control;
+mod directories;
+pub use self::directories::Directories;
+mod not_found;
+pub use self::not_found::NotFound;
+mod notifications;
+pub use self::notifications::Notifications;
+mod signals;
+mod source;
+pub use self::source::Script;
+use self::source::LineRunner;
+mod limits;
+mod printf;
+mod read;
+mod regex;
+mod timeout;
+mod usage;
+use self::usage::Usage;
+mod traps;
+pub use self::traps::{hangup_received, reset_for_child, Condition};
+use self::traps::Traps;
+mod reaper;
+pub use self::reaper::{poll_timeout, Reaper};
+mod variables;
+use self::variables::Variables;
 use std::cell::Cell;
+use std::collections::HashMap;
 use std::path::PathBuf;
-use std::ops::DerefMut;
-use std::time;
-use std::process;
+use std::rc::Rc;
 use std::sync::RwLock;
 use std::sync::Arc;
-use std::thread;
+use std::time::Duration;
 use nix;
+use super::rlua::Lua;
+
+/// The names `handle_builtin` knows.
+const BUILTINS: &[&str] = &[
+    "cd", "pushd", "popd", "dirs", "prevd", "nextd", "echo", "printf", "exit", "exec", "set",
+    "alias", "unalias", "abbr", "jobs", "fg", "bg", "kill", "wait", "disown", "trap", "ulimit",
+    "limit", "timeout", "read", "test", "[", "source", ".", "return", "hash", "type", "which",
+    "command", "builtin",
+];
+
+/// Whether `name` is a builtin or a keyword, for code without a `JobManager` to ask, such as
+/// the syntax highlighter.
+pub fn is_builtin_or_keyword(name: &str) -> bool {
+    BUILTINS.contains(&name) || commands::KEYWORDS.contains(&name)
+}
 
 pub struct JobManager {
     background_jobs: Arc<RwLock<Vec<Job>>>,
     foreground_jobs: Arc<RwLock<Vec<Job>>>,
     stopped_jobs: Arc<RwLock<Vec<Job>>>,
     current_job_pid: RwLock<Cell<Option<nix::unistd::Pid>>>,
+    aliases: Arc<RwLock<HashMap<String, String>>>,
+    abbreviations: Option<Arc<RwLock<Abbreviations>>>,
+    notifications: Arc<RwLock<Notifications>>,
+    exit_status: Option<i8>,
+    /// The command that `exec` replaces the shell with once the current line is done.
+    exec_command: Option<(PathBuf, Vec<String>)>,
+    /// How `source` runs the lines of a script.
+    line_runner: Option<LineRunner>,
+    /// How many sourced scripts are running, to tell whether `return` is allowed.
+    source_depth: usize,
+    /// Set by `return` to end the sourced script.
+    returning: Option<i8>,
+    variables: Variables,
+    /// Where commands run so far were found in `PATH`.
+    commands: CommandTable,
+    pipefail: bool,
+    /// Set by `set -o autocd`, which makes a directory typed on its own a `cd` into it.
+    autocd: bool,
+    directories: Arc<RwLock<Directories>>,
+    reaper: Option<Reaper>,
+    traps: Traps,
+    /// Number of command lines typed so far, to tell whether `exit` was typed twice in a row;
+    /// commands run by traps and hooks do not count.
+    commands_run: usize,
+    /// The command line at which `exit` last warned about remaining jobs.
+    exit_warning: Option<usize>,
+    /// The Lua state of an interactive shell, for hooks defined in `config.lua`.
+    lua: Option<Rc<Lua>>,
+    /// Set while a `command_not_found` hook runs, so that it is not used again for the
+    /// commands it runs.
+    in_not_found_hook: bool,
+    /// Set while running a pipeline prefixed with the `time` keyword.
+    timed: bool,
 }
 
 impl JobManager {
@@ -27,17 +105,120 @@ impl JobManager {
             foreground_jobs: Arc::new(RwLock::new(Vec::<Job>::new())),
             stopped_jobs: Arc::new(RwLock::new(Vec::<Job>::new())),
             current_job_pid: RwLock::new(Cell::new(None)),
+            aliases: Arc::new(RwLock::new(HashMap::new())),
+            abbreviations: None,
+            notifications: Arc::new(RwLock::new(Notifications::new())),
+            exit_status: None,
+            exec_command: None,
+            line_runner: None,
+            source_depth: 0,
+            returning: None,
+            variables: Variables::new(),
+            commands: CommandTable::new(),
+            pipefail: false,
+            autocd: false,
+            directories: Arc::new(RwLock::new(Directories::new())),
+            reaper: None,
+            traps: Traps::new(),
+            commands_run: 0,
+            exit_warning: None,
+            lua: None,
+            in_not_found_hook: false,
+            timed: false,
+        }
+    }
+
+    pub fn set_abbreviations(&mut self, abbreviations: Arc<RwLock<Abbreviations>>) {
+        self.abbreviations = Some(abbreviations);
+    }
+
+    pub fn aliases(&self) -> Arc<RwLock<HashMap<String, String>>> {
+        self.aliases.clone()
+    }
+
+    pub fn last_status(&self) -> i8 {
+        self.variables.last_status
+    }
+
+    pub fn set_last_status(&mut self, status: i8) {
+        self.variables.last_status = status;
+    }
+
+    /// Switches between the signal dispositions of an interactive shell and those of a script.
+    pub fn set_interactive(&mut self, interactive: bool) -> nix::Result<()> {
+        self.traps.set_interactive(interactive)
+    }
+
+    pub fn trap_command(&self, condition: Condition) -> Option<String> {
+        self.traps.command(condition).map(String::from)
+    }
+
+    /// Commands of the traps whose signals have arrived since the last call.
+    pub fn pending_traps(&self) -> Vec<String> {
+        self.traps.take_pending()
+    }
+
+    /// Returns the status the shell should exit with once `exit` has run.
+    pub fn exit_status(&self) -> Option<i8> {
+        self.exit_status
+    }
+
+    /// Counts a command line typed by the user, for the warning `exit` gives about jobs.
+    pub fn count_command_line(&mut self) {
+        self.commands_run += 1;
+    }
+
+    /// Returns the path and arguments of the command given to `exec`, if it has run.
+    pub fn take_exec_command(&mut self) -> Option<(PathBuf, Vec<String>)> {
+        self.exec_command.take()
+    }
+
+    /// Reaps background jobs and prints the status changes that have not been reported yet.
+    pub fn report_notifications(&self) {
+        reaper::reap_jobs(&self.background_jobs, &self.stopped_jobs, &self.notifications);
+        for message in self.notifications.write().unwrap().take() {
+            println!("{}", message);
+        }
+    }
+
+    /// Turns a `set -o` option on or off, returning false if there is no such option.
+    fn set_option(&mut self, name: &str, enabled: bool) -> bool {
+        match name {
+            "autocd" => {
+                self.autocd = enabled;
+                true
+            }
+            "notify" => {
+                self.notifications.write().unwrap().immediate = enabled;
+                true
+            }
+            "pipefail" => {
+                self.pipefail = enabled;
+                true
+            }
+            _ => false,
         }
     }
 
-    pub fn enqueue_job_from_expr(&mut self, expr: &Expr) -> Result<(), job::Error> {
+    fn print_options(&self) {
+        let on_off = |enabled: bool| if enabled { "on" } else { "off" };
+        println!("{:<16}{}", "autocd", on_off(self.autocd));
+        println!("{:<16}{}", "notify", on_off(self.notifications.read().unwrap().immediate));
+        println!("{:<16}{}", "pipefail", on_off(self.pipefail));
+    }
+
+    pub fn enqueue_job_from_expr(&mut self, expr: &Expr, command: &str) -> Result<(), job::Error> {
         match Job::from_expr(&expr, self) {
             Ok(mut job) => {
+                job.command = command.trim_end_matches(|c: char| c == '&' || c.is_whitespace()).to_owned();
                 if job.background {
                     match job.run(self) {
                         Ok(_) => {
-                            let mut background_jobs = self.background_jobs.write().unwrap();
-                            background_jobs.push(job);
+                            let pgid = job.pgid();
+                            let id = self.add_job(Table::Background, job);
+                            if let Some(pgid) = pgid {
+                                println!("[{}] {}", id, pgid);
+                            }
                             Ok(())
                         }
                         Err(e) => Err(e),
@@ -52,13 +233,45 @@ impl JobManager {
         }
     }
 
-    pub fn start_background_reaper(&mut self) -> thread::JoinHandle<()> {
-        let background_jobs = self.background_jobs.clone();
-        let stopped_jobs = self.stopped_jobs.clone();
-        thread::spawn(move || loop {
-            background_reaper(background_jobs.clone(), stopped_jobs.clone());
-            thread::sleep(time::Duration::from_millis(500));
-        })
+    /// Sets `?` and `PIPESTATUS` from a foreground job that has finished or stopped. The status
+    /// of a pipeline is that of its last stage, or with `pipefail` the rightmost stage that failed.
+    fn record_status(&mut self, job: &Job) {
+        let statuses = job.stage_statuses();
+        self.variables.last_status = if self.pipefail {
+            statuses.iter().rev().find(|s| **s != 0).cloned().unwrap_or(0)
+        } else {
+            statuses.last().cloned().unwrap_or(0)
+        };
+        self.variables
+            .set_array("PIPESTATUS", statuses.iter().map(|s| (*s as u8).to_string()).collect());
+        self.variables.last_duration = job.elapsed();
+        if self.timed {
+            if let Some(wall) = job.elapsed() {
+                self.print_times(wall, &job.usage);
+            }
+        }
+    }
+
+    /// Reports the resource usage of the jobs run while this is on, for the `time` keyword.
+    pub fn set_timed(&mut self, timed: bool) {
+        self.timed = timed;
+    }
+
+    /// Prints the time a job took in the format given by `TIMEFORMAT`.
+    pub fn print_times(&self, wall: Duration, usage: &Usage) {
+        let format = env::var("TIMEFORMAT").unwrap_or(String::from(usage::DEFAULT_FORMAT));
+        eprintln!("{}", usage::format(&format, wall, usage));
+    }
+
+    pub fn directories(&self) -> Arc<RwLock<Directories>> {
+        self.directories.clone()
+    }
+
+    /// Installs the SIGCHLD handler and returns a reaper for the line editor to poll.
+    pub fn start_reaper(&mut self) -> nix::Result<Reaper> {
+        let reaper = Reaper::new(self.background_jobs.clone(), self.stopped_jobs.clone(), self.notifications.clone())?;
+        self.reaper = Some(reaper.clone());
+        Ok(reaper)
     }
 
     pub fn run_foreground_jobs(&mut self) -> Result<(), job::Error> {
@@ -80,12 +293,17 @@ impl JobManager {
                             job.set_foreground();
                         }
                         match status {
-                            nix::sys::wait::WaitStatus::StillAlive | nix::sys::wait::WaitStatus::Continued(_) => match job.wait(Some(nix::sys::wait::WUNTRACED)) {
+                            nix::sys::wait::WaitStatus::StillAlive | nix::sys::wait::WaitStatus::Continued(_) => match self.wait_job(&mut job, nix::sys::wait::WUNTRACED) {
                                 Ok(nix::sys::wait::WaitStatus::Stopped(_, _)) => {
-                                    self.stopped_jobs.write().unwrap().push(job);
+                                    self.record_status(&job);
+                                    let id = self.add_job(Table::Stopped, job);
+                                    // the terminal echoed ^Z without a newline
+                                    println!("");
+                                    self.print_job(id);
                                     break;
                                 }
                                 Ok(nix::sys::wait::WaitStatus::Exited(_, _)) | Ok(nix::sys::wait::WaitStatus::Signaled(_, _, _)) => {
+                                    self.record_status(&job);
                                     break;
                                 }
                                 Ok(nix::sys::wait::WaitStatus::StillAlive) => {
@@ -101,6 +319,7 @@ impl JobManager {
                                 }
                             },
                             nix::sys::wait::WaitStatus::Exited(_, _) => {
+                                self.record_status(&job);
                                 break;
                             }
                             nix::sys::wait::WaitStatus::Signaled(_, _, _) => {
@@ -126,33 +345,82 @@ impl JobManager {
 impl job::BuiltinHandler for JobManager {
     fn handle_builtin(&mut self, name: &str, args: &[String]) -> i8 {
         match name {
-            "cd" => {
-                if let Some(first) = args.first() {
-                    let p = PathBuf::from(first);
-                    if p.exists() && p.is_dir() {
-                        match env::set_current_dir(p) {
-                            Ok(_) => 0,
-                            Err(_) => -1,
+            "cd" => self.cd_builtin(args),
+            "pushd" => self.pushd_builtin(args),
+            "popd" => self.popd_builtin(args),
+            "dirs" => self.dirs_builtin(args),
+            "prevd" => self.prevd_builtin(args),
+            "nextd" => self.nextd_builtin(args),
+            "echo" => self.echo_builtin(args),
+            "printf" => self.printf_builtin(args),
+            "exit" if self.warn_about_jobs() => 1,
+            "exit" => match args.first().map(|a| a.parse::<i32>()) {
+                None => {
+                    self.exit_status = Some(self.variables.last_status);
+                    self.variables.last_status
+                }
+                Some(Ok(status)) => {
+                    self.exit_status = Some(status as i8);
+                    status as i8
+                }
+                Some(Err(_)) => {
+                    eprintln!("exit: {}: numeric argument required", args[0]);
+                    self.exit_status = Some(2);
+                    2
+                }
+            },
+            // the redirections of `exec` have already been applied to the shell by the job
+            "exec" => match args.first() {
+                None => 0,
+                Some(command) => match self.commands.find(command) {
+                    Ok(path) => {
+                        self.exec_command = Some((path, args.to_vec()));
+                        0
+                    }
+                    Err(_) => {
+                        eprintln!("exec: {}: not found", command);
+                        127
+                    }
+                },
+            },
+            "set" => {
+                match args.first().map(|a| a.as_ref()) {
+                    Some("-o") | Some("+o") if args.len() == 1 => {
+                        self.print_options();
+                        return 0;
+                    }
+                    Some(flag) if flag.starts_with('-') || flag.starts_with('+') => {
+                        let mut result = 0;
+                        let mut index = 0;
+                        while index < args.len() {
+                            let flag = &args[index];
+                            if flag.len() < 2 || !(flag.starts_with('-') || flag.starts_with('+')) {
+                                eprintln!("set: {}: invalid option", flag);
+                                eprintln!("set: usage: set [-+b] [-+o option] ... or set name value");
+                                return 2;
+                            }
+                            let enabled = flag.starts_with('-');
+                            let name = match &flag[1..] {
+                                "o" => {
+                                    index += 1;
+                                    match args.get(index) {
+                                        Some(name) => name.as_ref(),
+                                        None => "",
+                                    }
+                                }
+                                "b" => "notify",
+                                other => other,
+                            };
+                            if !self.set_option(name, enabled) {
+                                eprintln!("set: {}: invalid option", name);
+                                result = 2;
+                            }
+                            index += 1;
                         }
-                    } else {
-                        1
+                        return result;
                     }
-                } else {
-                    1
+                    _ => {}
                 }
-            }
-            "echo" => {
-                println!("{}", args.join(" "));
-                0
-            }
-            "echo-stderr" => {
-                eprintln!("{}", args.join(" "));
-                0
-            }
-            "exit" => {
-                process::exit(0);
-            }
-            "set" => {
                 if args.len() < 2 {
                     -1
                 } else {
@@ -162,128 +430,158 @@ impl job::BuiltinHandler for JobManager {
                     0
                 }
             }
-            "jobs" => {
-                println!("background: ");
-                for (i, job) in self.background_jobs.read().unwrap().iter().enumerate() {
-                    println!("  {}: {:?}", i, job);
+            "alias" => {
+                fn print_alias(name: &str, value: &str) {
+                    println!("alias {}='{}'", name, value.replace("'", "'\\''"));
                 }
-                println!("stopped: ");
-                for (i, job) in self.stopped_jobs.read().unwrap().iter().enumerate() {
-                    println!("  {}: {:?}", i, job);
+                if args.len() == 0 {
+                    let aliases = self.aliases.read().unwrap();
+                    let mut names: Vec<&String> = aliases.keys().collect();
+                    names.sort();
+                    for name in names {
+                        print_alias(name, &aliases[name]);
+                    }
+                    return 0;
                 }
-                0
-            }
-            "fg" | "bg" => {
-                fn find_job_by_pid(jobs: &[Job], pid: nix::unistd::Pid) -> Option<usize> {
-                    for (index, job) in jobs.iter().enumerate() {
-                        match job.get_status() {
-                            job::Status::Started(job_pid, _, _) => {
-                                if job_pid == pid {
-                                    return Some(index);
-                                }
+                let mut result = 0;
+                for arg in args {
+                    if let Some(index) = arg.find('=') {
+                        let (name, value) = arg.split_at(index);
+                        if name.len() == 0 || name.contains(|c: char| c.is_whitespace() || c == '/' || c == '`' || c == '|' || c == '&') {
+                            eprintln!("alias: `{}': invalid alias name", name);
+                            result = 1;
+                        } else {
+                            self.aliases.write().unwrap().insert(name.to_owned(), value[1..].to_owned());
+                        }
+                    } else {
+                        match self.aliases.read().unwrap().get(arg) {
+                            Some(value) => print_alias(arg, value),
+                            None => {
+                                eprintln!("alias: {}: not found", arg);
+                                result = 1;
                             }
-                            _ => {}
                         }
                     }
-                    None
                 }
-
-                fn max_job_pid(jobs: &[Job]) -> Option<nix::unistd::Pid> {
-                    let mut max_pid = None;
-                    for job in jobs {
-                        match job.get_status() {
-                            job::Status::Started(job_pid, _, _) => {
-                                if max_pid.is_none() {
-                                    max_pid = Some(job_pid);
-                                } else {
-                                    if nix::libc::pid_t::from(job_pid) > nix::libc::pid_t::from(max_pid.unwrap()) {
-                                        max_pid = Some(job_pid);
-                                    }
-                                }
-                            }
-                            _ => {}
-                        }
+                result
+            }
+            "unalias" => {
+                if args.len() == 0 {
+                    eprintln!("unalias: usage: unalias [-a] name [name ...]");
+                    return 2;
+                }
+                let mut aliases = self.aliases.write().unwrap();
+                let mut result = 0;
+                for arg in args {
+                    if arg == "-a" {
+                        aliases.clear();
+                    } else if aliases.remove(arg).is_none() {
+                        eprintln!("unalias: {}: not found", arg);
+                        result = 1;
                     }
-                    max_pid
                 }
-                let mut bg_jobs = self.background_jobs.write().unwrap();
-                let mut stopped_jobs = self.stopped_jobs.write().unwrap();
-                let pid: Option<nix::unistd::Pid> = {
-                    match args.first() {
-                        Some(pid_str) => {
-                            let parsed = pid_str.parse();
-                            if parsed.is_ok() {
-                                Some(nix::unistd::Pid::from_raw(parsed.unwrap()))
-                            } else {
-                                None
-                            }
+                result
+            }
+            "abbr" => {
+                let abbreviations = match self.abbreviations {
+                    Some(ref a) => a.clone(),
+                    None => {
+                        eprintln!("abbr: abbreviations are not available");
+                        return 1;
+                    }
+                };
+                let mut abbreviations = abbreviations.write().unwrap();
+                match args.first().map(|a| a.as_ref()) {
+                    None | Some("-s") | Some("--show") => {
+                        let mut entries: Vec<(&String, &String)> = abbreviations.entries().iter().collect();
+                        entries.sort();
+                        for (abbreviation, expansion) in entries {
+                            println!("abbr -a {} '{}'", abbreviation, expansion.replace("'", "'\\''"));
                         }
-                        None => {
-                            if name == "bg" {
-                                max_job_pid(&stopped_jobs)
-                            } else {
-                                match max_job_pid(&bg_jobs) {
-                                    Some(max_bg_pid) => match max_job_pid(&stopped_jobs) {
-                                        Some(max_stopped_pid) => {
-                                            if nix::libc::pid_t::from(max_stopped_pid) > nix::libc::pid_t::from(max_bg_pid) {
-                                                Some(max_stopped_pid)
-                                            } else {
-                                                Some(max_bg_pid)
-                                            }
-                                        }
-                                        None => Some(max_bg_pid),
-                                    },
-                                    None => max_job_pid(&stopped_jobs),
+                        0
+                    }
+                    Some("-l") | Some("--list") => {
+                        let mut names: Vec<&String> = abbreviations.entries().keys().collect();
+                        names.sort();
+                        for abbreviation in names {
+                            println!("{}", abbreviation);
+                        }
+                        0
+                    }
+                    Some("-e") | Some("--erase") => {
+                        let mut result = 0;
+                        for abbreviation in &args[1..] {
+                            match abbreviations.remove(abbreviation) {
+                                Ok(true) => {}
+                                Ok(false) => {
+                                    eprintln!("abbr: no such abbreviation `{}`", abbreviation);
+                                    result = 1;
                                 }
+                                Err(_) => result = 1,
                             }
                         }
+                        result
                     }
-                };
-                if let Some(pid) = pid {
-                    let mut job: Job;
-                    match find_job_by_pid(&stopped_jobs, pid) {
-                        Some(stopped_jobs_index) => {
-                            job = stopped_jobs.remove(stopped_jobs_index);
+                    Some(first) => {
+                        let definition = if first == "-a" || first == "--add" { &args[1..] } else { &args[..] };
+                        if definition.len() < 2 || definition[0].starts_with("-") {
+                            eprintln!("abbr: usage: abbr [-a] name expansion...");
+                            return 2;
                         }
-                        None => {
-                            if name == "bg" {
-                                eprintln!("error: job {} has not stopped", pid);
-                                return -1;
-                            } else {
-                                match find_job_by_pid(&bg_jobs, pid) {
-                                    Some(bg_jobs_index) => {
-                                        job = bg_jobs.remove(bg_jobs_index);
-                                    }
-                                    None => {
-                                        eprintln!("error: no such job");
-                                        return -1;
-                                    }
-                                }
-                            }
+                        match abbreviations.insert(&definition[0], &definition[1..].join(" ")) {
+                            Ok(_) => 0,
+                            Err(_) => 1,
                         }
-                    };
-                    if name == "fg" {
-                        self.foreground_jobs.write().unwrap().push(job);
-                    } else {
-                        job.cont(true)
-                            .expect("failed to continue job in background");
-                        bg_jobs.push(job);
                     }
-                    0
-                } else {
-                    eprintln!("error: no such job");
-                    -1
                 }
             }
+            "jobs" => self.jobs_builtin(args),
+            "fg" => self.fg_builtin(args),
+            "bg" => self.bg_builtin(args),
+            "kill" => self.kill_builtin(args),
+            "wait" => self.wait_builtin(args),
+            "disown" => self.disown_builtin(args),
+            "trap" => self.trap_builtin(args),
+            "ulimit" => self.ulimit_builtin(args),
+            "read" => self.read_builtin(args),
+            "source" | "." => self.source_builtin(name, args),
+            "return" => self.return_builtin(args),
+            "hash" => self.hash_builtin(args),
+            "type" => self.type_builtin(args),
+            "which" => self.which_builtin(args),
+            "command" => self.command_builtin(args),
+            "builtin" => self.builtin_builtin(args),
+            "test" | "[" => self.test_builtin(name, args),
+            "limit" => {
+                eprintln!("limit: usage: limit resource=value ... -- command [args]");
+                2
+            }
+            "timeout" => {
+                eprintln!("timeout: usage: timeout [-k grace] duration command [args]");
+                2
+            }
             _ => -1,
         }
     }
 
     fn is_builtin(&mut self, name: &str) -> bool {
-        match name {
-            "cd" | "echo" | "echo-stderr" | "exit" | "set" | "jobs" | "fg" | "bg" => true,
-            _ => false,
-        }
+        BUILTINS.contains(&name)
+    }
+
+    fn find_command(&mut self, name: &str) -> Result<PathBuf, job::Error> {
+        self.commands.find(name)
+    }
+
+    fn autocd(&self) -> bool {
+        self.autocd
+    }
+
+    fn variable(&self, name: &str) -> Option<String> {
+        self.variables.get(name)
+    }
+
+    fn job_control(&self) -> bool {
+        self.traps.interactive()
     }
 }
 
@@ -296,33 +594,3 @@ fn get_next_job(queue: &RwLock<Vec<Job>>) -> Option<Job> {
         None
     }
 }
-
-fn background_reaper(background_jobs: Arc<RwLock<Vec<Job>>>, stopped_jobs: Arc<RwLock<Vec<Job>>>) {
-    let mut bg_jobs = background_jobs.write().unwrap();
-    let mut st_jobs = stopped_jobs.write().unwrap();
-    let mut new_bg_jobs = Vec::new();
-    while let Some(mut job) = bg_jobs.pop() {
-        match job.get_status() {
-            job::Status::NotStarted => {
-                panic!("found job in bg queue that has not been started");
-            }
-            job::Status::Started(_, _, status) => {
-                match status {
-                    nix::sys::wait::WaitStatus::StillAlive | nix::sys::wait::WaitStatus::Continued(_) => {
-                        match job.wait(Some(nix::sys::wait::WUNTRACED | nix::sys::wait::WNOHANG)) {
-                            Ok(_) => {
-                                new_bg_jobs.push(job);
-                            }
-                            Err(_) => {} // silently drop and kill job
-                        }
-                    }
-                    nix::sys::wait::WaitStatus::Stopped(_, _) => {
-                        st_jobs.push(job);
-                    }
-                    _ => {} // remove from queue
-                }
-            }
-        }
-    }
-    *bg_jobs.deref_mut() = new_bg_jobs;
-}
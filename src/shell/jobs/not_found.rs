@@ -0,0 +1,139 @@
+use super::{Condition, JobManager, BUILTINS};
+use super::super::rlua::{Function, Lua, Value};
+extern crate strsim;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+/// Suggestions further away than this many edits are not worth showing.
+const MAX_DISTANCE: usize = 2;
+
+/// What the shell should do about a command that was not found.
+pub enum NotFound {
+    /// Run the `COMMAND_NOT_FOUND` trap.
+    Trap(String),
+    /// Run the command line returned by the Lua hook instead.
+    Replace(String),
+    /// A hook took care of it, or the error has been reported.
+    Done,
+}
+
+/// Every name that could be run: builtins, aliases and the files in `PATH`.
+fn known_commands<'a>(aliases: &'a HashMap<String, String>, path_names: &'a HashSet<String>) -> HashSet<&'a str> {
+    let mut names: HashSet<&str> = BUILTINS.iter().cloned().collect();
+    names.extend(aliases.keys().map(|alias| alias.as_str()));
+    names.extend(path_names.iter().map(|name| name.as_str()));
+    names
+}
+
+/// The closest of the `known` command names to `name`, nearest first.
+fn suggestions(name: &str, known: HashSet<&str>) -> Vec<String> {
+    let mut scored: Vec<(usize, &str)> = known
+        .into_iter()
+        .map(|candidate| (strsim::osa_distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= MAX_DISTANCE && distance < name.chars().count())
+        .collect();
+    scored.sort();
+    let best = match scored.first() {
+        Some(&(distance, _)) => distance,
+        None => return Vec::new(),
+    };
+    scored.into_iter().take_while(|&(distance, _)| distance == best).take(3).map(|(_, candidate)| candidate.to_owned()).collect()
+}
+
+impl JobManager {
+    /// Gives the job manager the shell's Lua state, for the hooks defined in `config.lua`.
+    pub fn set_lua(&mut self, lua: Rc<Lua>) {
+        self.lua = Some(lua);
+    }
+
+    /// Calls the Lua function `command_not_found(name, args)`, if there is one. It can return a
+    /// command line to run instead, or `true` to say it dealt with the command itself.
+    fn lua_not_found_hook(&self, name: &str, args: &[String]) -> Option<NotFound> {
+        let lua = self.lua.as_ref()?;
+        let hook = lua.globals().get::<_, Function>("command_not_found").ok()?;
+        match hook.call::<_, Value>((name, args.to_vec())) {
+            Ok(Value::String(replacement)) => replacement.to_str().ok().map(|line| NotFound::Replace(line.to_owned())),
+            Ok(Value::Boolean(true)) => Some(NotFound::Done),
+            Ok(_) => None,
+            Err(e) => {
+                eprintln!("radish: command_not_found: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Decides what to do about `name`, which is neither a builtin, an alias nor in `PATH`. The
+    /// hooks are not used for commands that they run themselves.
+    pub fn command_not_found(&mut self, name: &str, args: &[String]) -> NotFound {
+        self.set_last_status(127);
+        if !self.in_not_found_hook {
+            if let Some(command) = self.trap_command(Condition::CommandNotFound) {
+                return NotFound::Trap(command);
+            }
+            if let Some(action) = self.lua_not_found_hook(name, args) {
+                return action;
+            }
+        }
+        let suggestions = if name.contains('/') {
+            Vec::new()
+        } else {
+            let aliases = self.aliases.read().unwrap();
+            suggestions(name, known_commands(&aliases, self.commands.names()))
+        };
+        match suggestions.len() {
+            0 => eprintln!("radish: command not found: {}", name),
+            _ => eprintln!("radish: command not found: {}. Did you mean {}?", name, suggestions.join(" or ")),
+        }
+        NotFound::Done
+    }
+
+    /// Runs `f` as a `command_not_found` hook, with the command and its arguments as the
+    /// positional parameters if `positional` is given.
+    pub fn run_not_found_hook<F: FnOnce(&mut JobManager)>(&mut self, positional: Option<Vec<String>>, f: F) {
+        let saved = positional.map(|words| self.variables.replace_positional(words));
+        self.in_not_found_hook = true;
+        f(self);
+        self.in_not_found_hook = false;
+        if let Some(saved) = saved {
+            self.variables.replace_positional(saved);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn known(names: &[&'static str]) -> HashSet<&'static str> {
+        names.iter().cloned().collect()
+    }
+
+    #[test]
+    fn test_nearest_suggestions_first() {
+        let names = known(&["git", "grep", "gzip", "cat"]);
+        assert_eq!(suggestions("gti", names.clone()), vec!["git"]);
+        assert_eq!(suggestions("gerp", names.clone()), vec!["grep"]);
+        assert_eq!(suggestions("xyzzy", names), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_ties_are_sorted_and_limited() {
+        let names = known(&["lsa", "lsb", "lsc", "lsd"]);
+        assert_eq!(suggestions("ls", names), vec!["lsa", "lsb", "lsc"]);
+    }
+
+    #[test]
+    fn test_short_names_are_not_replaced_entirely() {
+        assert_eq!(suggestions("x", known(&["y", "xy"])), Vec::<String>::new());
+        assert_eq!(suggestions("sl", known(&["ls", "xy"])), vec!["ls"]);
+    }
+
+    #[test]
+    fn test_known_commands() {
+        let mut aliases = HashMap::new();
+        aliases.insert(String::from("ll"), String::from("ls -l"));
+        let path_names: HashSet<String> = vec![String::from("vim")].into_iter().collect();
+        let names = known_commands(&aliases, &path_names);
+        assert!(names.contains("ll") && names.contains("vim") && names.contains("cd"));
+    }
+}
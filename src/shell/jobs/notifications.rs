@@ -0,0 +1,25 @@
+use std::mem;
+
+/// Job status changes that have not been reported to the user yet.
+pub struct Notifications {
+    messages: Vec<String>,
+    /// Report changes as soon as they happen (`set -b`) instead of before the next prompt.
+    pub immediate: bool,
+}
+
+impl Notifications {
+    pub fn new() -> Self {
+        Notifications {
+            messages: Vec::new(),
+            immediate: false,
+        }
+    }
+
+    pub fn push(&mut self, message: String) {
+        self.messages.push(message);
+    }
+
+    pub fn take(&mut self) -> Vec<String> {
+        mem::replace(&mut self.messages, Vec::new())
+    }
+}
@@ -0,0 +1,655 @@
+use super::JobManager;
+use std::char;
+use std::env;
+use std::ffi::OsString;
+use std::io::{stdout, Write};
+use std::os::unix::ffi::OsStringExt;
+
+/// Characters that `%q` leaves alone; everything else gets a backslash.
+fn is_shell_safe(c: char) -> bool {
+    c.is_alphanumeric() || "_-./:=@,+%^".contains(c)
+}
+
+/// Reads up to `max` digits in `radix` from the start of `chars`, returning the value and how
+/// many characters were used.
+fn take_digits(chars: &[char], radix: u32, max: usize) -> (u32, usize) {
+    let mut value = 0;
+    let mut used = 0;
+    while used < max && used < chars.len() {
+        match chars[used].to_digit(radix) {
+            Some(digit) => value = value * radix + digit,
+            None => break,
+        }
+        used += 1;
+    }
+    (value, used)
+}
+
+/// Reads the decimal width or precision of a conversion, which like in C must fit an `int`.
+fn take_field(chars: &[char], what: &str) -> Result<(usize, usize), String> {
+    let mut value: u32 = 0;
+    let mut used = 0;
+    while let Some(digit) = chars.get(used).and_then(|c| c.to_digit(10)) {
+        value = match value.checked_mul(10).and_then(|value| value.checked_add(digit)) {
+            Some(value) if value <= i32::max_value() as u32 => value,
+            _ => {
+                let digits: String = chars.iter().take_while(|c| c.is_digit(10)).collect();
+                return Err(format!("{}: invalid {}", digits, what));
+            }
+        };
+        used += 1;
+    }
+    Ok((value as usize, used))
+}
+
+/// Expands the escape after a backslash, returning the bytes it stands for and how many
+/// characters it used. Octal and `\x` escapes give a single byte, which need not be valid
+/// UTF-8, and `\u` and `\U` the UTF-8 encoding of a character. In `echo -e` and `%b` octal
+/// escapes are written `\0nnn`, in a `printf` format `\nnn`. `None` means `\c`, which drops
+/// all further output.
+fn escape(rest: &[char], echo_style: bool) -> (Option<Vec<u8>>, usize) {
+    let single = |byte: u8| (Some(vec![byte]), 1);
+    match rest[0] {
+        'a' => single(0x07),
+        'b' => single(0x08),
+        'c' if echo_style => (None, 1),
+        'e' | 'E' => single(0x1b),
+        'f' => single(0x0c),
+        'n' => single(b'\n'),
+        'r' => single(b'\r'),
+        't' => single(b'\t'),
+        'v' => single(0x0b),
+        '\\' => single(b'\\'),
+        '0' if echo_style => {
+            let (value, used) = take_digits(&rest[1..], 8, 3);
+            (Some(vec![value as u8]), used + 1)
+        }
+        c if !echo_style && c.is_digit(8) => {
+            let (value, used) = take_digits(rest, 8, 3);
+            (Some(vec![value as u8]), used)
+        }
+        'x' => match take_digits(&rest[1..], 16, 2) {
+            (_, 0) => (Some(b"\\x".to_vec()), 1),
+            (value, used) => (Some(vec![value as u8]), used + 1),
+        },
+        'u' | 'U' => {
+            let max = if rest[0] == 'u' { 4 } else { 8 };
+            match take_digits(&rest[1..], 16, max) {
+                (_, 0) => (Some(format!("\\{}", rest[0]).into_bytes()), 1),
+                (value, used) => (Some(char::from_u32(value).unwrap_or(char::REPLACEMENT_CHARACTER).to_string().into_bytes()), used + 1),
+            }
+        }
+        other => (Some(format!("\\{}", other).into_bytes()), 1),
+    }
+}
+
+/// Appends the UTF-8 encoding of `c`.
+fn push_char(output: &mut Vec<u8>, c: char) {
+    let mut buffer = [0; 4];
+    output.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+}
+
+/// Expands the backslash escapes of `echo -e` and `%b`. The returned flag is set when a `\c`
+/// cut the text short.
+fn unescape(text: &str) -> (Vec<u8>, bool) {
+    let chars: Vec<char> = text.chars().collect();
+    let mut result = Vec::new();
+    let mut index = 0;
+    while index < chars.len() {
+        if chars[index] != '\\' || index + 1 == chars.len() {
+            push_char(&mut result, chars[index]);
+            index += 1;
+            continue;
+        }
+        match escape(&chars[index + 1..], true) {
+            (Some(bytes), used) => {
+                result.extend(bytes);
+                index += 1 + used;
+            }
+            (None, _) => return (result, true),
+        }
+    }
+    (result, false)
+}
+
+/// Quotes a string for `%q` so that radish reads it back as the same word.
+fn shell_quote(text: &str) -> String {
+    if text.is_empty() {
+        return String::from("\"\"");
+    }
+    let mut result = String::new();
+    for c in text.chars() {
+        if !is_shell_safe(c) {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// A conversion such as `%-10.3s`.
+struct Spec {
+    left: bool,
+    zero: bool,
+    plus: bool,
+    space: bool,
+    alternate: bool,
+    width: usize,
+    precision: Option<usize>,
+    conversion: char,
+}
+
+impl Spec {
+    fn pad(&self, text: String) -> String {
+        let length = text.chars().count();
+        if length >= self.width {
+            return text;
+        }
+        let fill = self.width - length;
+        if self.left {
+            format!("{}{}", text, " ".repeat(fill))
+        } else if self.zero && !"sbqc".contains(self.conversion) && (self.precision.is_none() || "fFeEgG".contains(self.conversion)) {
+            // zeros go between the sign or radix prefix and the digits
+            let prefix_length = if text.starts_with("0x") || text.starts_with("0X") {
+                2
+            } else if text.starts_with(|c| c == '-' || c == '+' || c == ' ') {
+                1
+            } else {
+                0
+            };
+            format!("{}{}{}", &text[..prefix_length], "0".repeat(fill), &text[prefix_length..])
+        } else {
+            format!("{}{}", " ".repeat(fill), text)
+        }
+    }
+
+    /// Pads the bytes of a `%b` argument, which need not be valid UTF-8, with spaces.
+    fn pad_bytes(&self, mut bytes: Vec<u8>) -> Vec<u8> {
+        let length = String::from_utf8_lossy(&bytes).chars().count();
+        if length >= self.width {
+            return bytes;
+        }
+        let fill = vec![b' '; self.width - length];
+        if self.left {
+            bytes.extend(fill);
+            bytes
+        } else {
+            let mut padded = fill;
+            padded.extend(bytes);
+            padded
+        }
+    }
+
+    fn sign(&self, negative: bool) -> &'static str {
+        if negative {
+            "-"
+        } else if self.plus {
+            "+"
+        } else if self.space {
+            " "
+        } else {
+            ""
+        }
+    }
+
+    fn integer(&self, value: i64) -> String {
+        let magnitude = if value < 0 { (value as u64).wrapping_neg() } else { value as u64 };
+        let digits = self.digits(magnitude.to_string());
+        self.pad(format!("{}{}", self.sign(value < 0), digits))
+    }
+
+    fn unsigned(&self, value: u64) -> String {
+        let digits = match self.conversion {
+            'x' => format!("{:x}", value),
+            'X' => format!("{:X}", value),
+            'o' => format!("{:o}", value),
+            _ => value.to_string(),
+        };
+        let mut digits = self.digits(digits);
+        if self.alternate && value != 0 {
+            match self.conversion {
+                'x' => digits.insert_str(0, "0x"),
+                'X' => digits.insert_str(0, "0X"),
+                'o' if !digits.starts_with('0') => digits.insert(0, '0'),
+                _ => {}
+            }
+        }
+        self.pad(digits)
+    }
+
+    /// Applies the precision of an integer conversion, the minimum number of digits.
+    fn digits(&self, digits: String) -> String {
+        match self.precision {
+            Some(0) if digits == "0" => String::new(),
+            Some(precision) if precision > digits.len() => format!("{}{}", "0".repeat(precision - digits.len()), digits),
+            _ => digits,
+        }
+    }
+
+    fn float(&self, value: f64) -> String {
+        let precision = self.precision.unwrap_or(6);
+        let magnitude = value.abs();
+        let body = if !magnitude.is_finite() {
+            let text = if magnitude.is_nan() { "nan" } else { "inf" };
+            if self.conversion.is_uppercase() { text.to_uppercase() } else { text.to_owned() }
+        } else {
+            match self.conversion {
+                'e' | 'E' => exponential(magnitude, precision, self.conversion == 'E'),
+                'g' | 'G' => self.general(magnitude, precision),
+                _ => format!("{:.*}", precision, magnitude),
+            }
+        };
+        let negative = value.is_sign_negative() && !value.is_nan();
+        self.pad(format!("{}{}", self.sign(negative), body))
+    }
+
+    /// `%g`: the shorter of `%f` and `%e`, without trailing zeros unless `#` is given.
+    fn general(&self, magnitude: f64, precision: usize) -> String {
+        let precision = if precision == 0 { 1 } else { precision };
+        let exponent = if magnitude == 0.0 {
+            0
+        } else {
+            let formatted = format!("{:.*e}", precision - 1, magnitude);
+            formatted[formatted.find('e').unwrap() + 1..].parse::<i32>().unwrap_or(0)
+        };
+        let text = if exponent < -4 || exponent >= precision as i32 {
+            exponential(magnitude, precision - 1, self.conversion == 'G')
+        } else {
+            format!("{:.*}", (precision as i32 - 1 - exponent) as usize, magnitude)
+        };
+        if self.alternate {
+            return text;
+        }
+        let (mantissa, exponent) = match text.find(|c| c == 'e' || c == 'E') {
+            Some(position) => (&text[..position], &text[position..]),
+            None => (&text[..], ""),
+        };
+        let mantissa = if mantissa.contains('.') {
+            mantissa.trim_end_matches('0').trim_end_matches('.')
+        } else {
+            mantissa
+        };
+        format!("{}{}", mantissa, exponent)
+    }
+}
+
+/// Formats like C's `%e`, with at least two exponent digits.
+fn exponential(magnitude: f64, precision: usize, uppercase: bool) -> String {
+    let formatted = format!("{:.*e}", precision, magnitude);
+    let position = formatted.find('e').unwrap();
+    let exponent = formatted[position + 1..].parse::<i32>().unwrap_or(0);
+    let sign = if exponent < 0 { '-' } else { '+' };
+    let e = if uppercase { 'E' } else { 'e' };
+    format!("{}{}{}{:02}", &formatted[..position], e, sign, exponent.abs())
+}
+
+/// Parses a numeric argument the way C's `strtol` would, also accepting `'c` for the code of
+/// the character `c`.
+fn parse_integer(text: &str) -> Result<i64, String> {
+    let trimmed = text.trim();
+    if trimmed.starts_with('\'') || trimmed.starts_with('"') {
+        return Ok(trimmed.chars().nth(1).map(|c| c as i64).unwrap_or(0));
+    }
+    let (negative, digits) = if trimmed.starts_with('-') {
+        (true, &trimmed[1..])
+    } else {
+        (false, trimmed.trim_start_matches('+'))
+    };
+    let parsed = if digits.starts_with("0x") || digits.starts_with("0X") {
+        i64::from_str_radix(&digits[2..], 16)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        i64::from_str_radix(&digits[1..], 8)
+    } else {
+        digits.parse::<i64>()
+    };
+    match parsed {
+        Ok(value) if negative => Ok(-value),
+        Ok(value) => Ok(value),
+        Err(_) if trimmed.is_empty() => Ok(0),
+        Err(_) => Err(format!("{}: invalid number", text)),
+    }
+}
+
+fn parse_float(text: &str) -> Result<f64, String> {
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        return Ok(0.0);
+    }
+    match trimmed.parse::<f64>() {
+        Ok(value) => Ok(value),
+        Err(_) => parse_integer(text).map(|value| value as f64),
+    }
+}
+
+/// The output of one `printf` call and whatever went wrong while producing it.
+struct Formatter<'a> {
+    args: &'a [String],
+    next_arg: usize,
+    output: Vec<u8>,
+    errors: Vec<String>,
+    /// Set by `\c` in a `%b` argument, which ends the output.
+    stopped: bool,
+}
+
+impl<'a> Formatter<'a> {
+    fn next(&mut self) -> Option<&'a str> {
+        let arg = self.args.get(self.next_arg).map(|a| a.as_str());
+        self.next_arg += 1;
+        arg
+    }
+
+    fn next_number(&mut self) -> usize {
+        let arg = self.next().unwrap_or("");
+        match parse_integer(arg) {
+            Ok(value) => value.max(0) as usize,
+            Err(e) => {
+                self.errors.push(e);
+                0
+            }
+        }
+    }
+
+    /// Goes through the format once, using up arguments as conversions ask for them.
+    fn run(&mut self, format: &[char]) -> Result<(), String> {
+        let mut index = 0;
+        while index < format.len() && !self.stopped {
+            match format[index] {
+                '\\' if index + 1 < format.len() => {
+                    let (bytes, used) = escape(&format[index + 1..], false);
+                    self.output.extend(bytes.unwrap_or(Vec::new()));
+                    index += 1 + used;
+                }
+                '%' if format.get(index + 1) == Some(&'%') => {
+                    self.output.push(b'%');
+                    index += 2;
+                }
+                '%' => index = self.conversion(format, index + 1)?,
+                c => {
+                    push_char(&mut self.output, c);
+                    index += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Formats the conversion starting after the `%` at `index`, returning where the format
+    /// continues.
+    fn conversion(&mut self, format: &[char], mut index: usize) -> Result<usize, String> {
+        let mut spec = Spec {
+            left: false,
+            zero: false,
+            plus: false,
+            space: false,
+            alternate: false,
+            width: 0,
+            precision: None,
+            conversion: 's',
+        };
+        while let Some(&flag) = format.get(index) {
+            match flag {
+                '-' => spec.left = true,
+                '0' => spec.zero = true,
+                '+' => spec.plus = true,
+                ' ' => spec.space = true,
+                '#' => spec.alternate = true,
+                _ => break,
+            }
+            index += 1;
+        }
+        if format.get(index) == Some(&'*') {
+            let width = self.args.get(self.next_arg).map(|a| parse_integer(a).unwrap_or(0)).unwrap_or(0);
+            self.next_arg += 1;
+            if width < 0 {
+                spec.left = true;
+            }
+            spec.width = width.abs() as usize;
+            index += 1;
+        } else {
+            let (width, used) = take_field(&format[index..], "field width")?;
+            spec.width = width;
+            index += used;
+        }
+        if format.get(index) == Some(&'.') {
+            index += 1;
+            if format.get(index) == Some(&'*') {
+                spec.precision = Some(self.next_number());
+                index += 1;
+            } else {
+                let (precision, used) = take_field(&format[index..], "precision")?;
+                spec.precision = Some(precision);
+                index += used;
+            }
+        }
+        // length modifiers mean nothing here
+        while format.get(index).map(|c| "hlLjzt".contains(*c)).unwrap_or(false) {
+            index += 1;
+        }
+        spec.conversion = match format.get(index) {
+            Some(&c) => c,
+            None => return Err(String::from("`%': missing format character")),
+        };
+        let arg = self.next().unwrap_or("");
+        let text = match spec.conversion {
+            's' => {
+                let text: String = match spec.precision {
+                    Some(precision) => arg.chars().take(precision).collect(),
+                    None => arg.to_owned(),
+                };
+                spec.pad(text)
+            }
+            'b' => {
+                let (mut bytes, stopped) = unescape(arg);
+                self.stopped = stopped;
+                // like C's printf, the precision counts bytes here
+                if let Some(precision) = spec.precision {
+                    bytes.truncate(precision);
+                }
+                self.output.extend(spec.pad_bytes(bytes));
+                return Ok(index + 1);
+            }
+            'q' => spec.pad(shell_quote(arg)),
+            'c' => spec.pad(arg.chars().take(1).collect()),
+            'd' | 'i' => {
+                let value = parse_integer(arg).unwrap_or_else(|e| {
+                    self.errors.push(e);
+                    0
+                });
+                spec.integer(value)
+            }
+            'u' | 'x' | 'X' | 'o' => {
+                let value = parse_integer(arg).unwrap_or_else(|e| {
+                    self.errors.push(e);
+                    0
+                });
+                spec.unsigned(value as u64)
+            }
+            'f' | 'F' | 'e' | 'E' | 'g' | 'G' => {
+                let value = parse_float(arg).unwrap_or_else(|e| {
+                    self.errors.push(e);
+                    0.0
+                });
+                spec.float(value)
+            }
+            other => return Err(format!("`{}': invalid format character", other)),
+        };
+        self.output.extend(text.into_bytes());
+        Ok(index + 1)
+    }
+}
+
+/// Writes builtin output straight away, so that it lands before that of later commands and in
+/// the right file when the builtin's stdout is redirected.
+fn write_out(name: &str, bytes: &[u8]) -> i8 {
+    let mut out = stdout();
+    match out.write_all(bytes).and_then(|_| out.flush()) {
+        Ok(_) => 0,
+        Err(e) => {
+            eprintln!("{}: write error: {}", name, e);
+            1
+        }
+    }
+}
+
+impl JobManager {
+    pub fn echo_builtin(&mut self, args: &[String]) -> i8 {
+        let mut newline = true;
+        let mut escapes = false;
+        let mut index = 0;
+        // only words made entirely of known flags are options, so `echo -x` prints `-x`
+        while let Some(arg) = args.get(index) {
+            if arg.len() < 2 || !arg.starts_with('-') || !arg[1..].chars().all(|c| "neE".contains(c)) {
+                break;
+            }
+            for flag in arg[1..].chars() {
+                match flag {
+                    'n' => newline = false,
+                    'e' => escapes = true,
+                    _ => escapes = false,
+                }
+            }
+            index += 1;
+        }
+        let text = args[index..].join(" ");
+        let mut bytes = if escapes {
+            let (unescaped, stopped) = unescape(&text);
+            if stopped {
+                newline = false;
+            }
+            unescaped
+        } else {
+            text.into_bytes()
+        };
+        if newline {
+            bytes.push(b'\n');
+        }
+        write_out("echo", &bytes)
+    }
+
+    pub fn printf_builtin(&mut self, args: &[String]) -> i8 {
+        let mut variable = None;
+        let mut args = args;
+        if args.first().map(|a| a == "-v").unwrap_or(false) {
+            match args.get(1) {
+                Some(name) if !name.is_empty() && !name.starts_with(|c: char| c.is_digit(10)) && name.chars().all(|c| c.is_alphanumeric() || c == '_') => {
+                    variable = Some(name.clone());
+                    args = &args[2..];
+                }
+                Some(name) => {
+                    eprintln!("printf: `{}': not a valid identifier", name);
+                    return 2;
+                }
+                None => {
+                    eprintln!("printf: -v: option requires an argument");
+                    eprintln!("printf: usage: printf [-v var] format [arguments]");
+                    return 2;
+                }
+            }
+        }
+        if args.first().map(|a| a == "--").unwrap_or(false) {
+            args = &args[1..];
+        }
+        let (format, args) = match args.split_first() {
+            Some((format, args)) => (format.chars().collect::<Vec<char>>(), args),
+            None => {
+                eprintln!("printf: usage: printf [-v var] format [arguments]");
+                return 2;
+            }
+        };
+        let mut formatter = Formatter {
+            args: args,
+            next_arg: 0,
+            output: Vec::new(),
+            errors: Vec::new(),
+            stopped: false,
+        };
+        // the format is used again for as long as arguments are left over
+        loop {
+            let before = formatter.next_arg;
+            if let Err(e) = formatter.run(&format) {
+                eprintln!("printf: {}", e);
+                return 1;
+            }
+            if formatter.stopped || formatter.next_arg >= args.len() || formatter.next_arg == before {
+                break;
+            }
+        }
+        for error in &formatter.errors {
+            eprintln!("printf: {}", error);
+        }
+        let status = if formatter.errors.is_empty() { 0 } else { 1 };
+        match variable {
+            Some(name) => {
+                self.variables.remove_array(&name);
+                // like bash, the value ends at the first NUL, which variables cannot hold
+                let mut output = formatter.output;
+                if let Some(end) = output.iter().position(|&byte| byte == 0) {
+                    output.truncate(end);
+                }
+                env::set_var(&name, OsString::from_vec(output));
+                status
+            }
+            None => match write_out("printf", &formatter.output) {
+                0 => status,
+                error => error,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn printf(format: &str, args: &[&str]) -> Vec<u8> {
+        let args: Vec<String> = args.iter().map(|arg| String::from(*arg)).collect();
+        let mut formatter = Formatter {
+            args: &args,
+            next_arg: 0,
+            output: Vec::new(),
+            errors: Vec::new(),
+            stopped: false,
+        };
+        formatter.run(&format.chars().collect::<Vec<char>>()).unwrap();
+        formatter.output
+    }
+
+    #[test]
+    fn test_escapes_are_bytes() {
+        assert_eq!(printf("\\377\\xff\\0", &[]), vec![0xff, 0xff, 0]);
+        assert_eq!(printf("\\u00e9", &[]), "é".as_bytes().to_vec());
+        assert_eq!(printf("é%%\\n", &[]), "é%\n".as_bytes().to_vec());
+        assert_eq!(printf("\\x\\q", &[]), b"\\x\\q".to_vec());
+    }
+
+    #[test]
+    fn test_echo_escapes() {
+        assert_eq!(unescape("a\\0377b"), (vec![b'a', 0xff, b'b'], false));
+        assert_eq!(unescape("a\\tb\\cc"), (b"a\tb".to_vec(), true));
+        assert_eq!(unescape("trailing\\"), (b"trailing\\".to_vec(), false));
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(printf("%5s|%-3d|%03x|%.2f", &["ab", "7", "255", "3.14159"]), b"   ab|7  |0ff|3.14".to_vec());
+        assert_eq!(printf("%4b|%.1b", &["\\0377", "\\0377\\0376"]), vec![b' ', b' ', b' ', 0xff, b'|', 0xff]);
+        assert_eq!(printf("%q", &["a b"]), b"a\\ b".to_vec());
+    }
+
+    #[test]
+    fn test_field_width_overflow() {
+        let args = vec![String::from("1")];
+        let mut formatter = Formatter {
+            args: &args,
+            next_arg: 0,
+            output: Vec::new(),
+            errors: Vec::new(),
+            stopped: false,
+        };
+        let run = |formatter: &mut Formatter, format: &str| formatter.run(&format.chars().collect::<Vec<char>>());
+        assert_eq!(run(&mut formatter, "%99999999999d"), Err(String::from("99999999999: invalid field width")));
+        assert_eq!(run(&mut formatter, "%.4294967296d"), Err(String::from("4294967296: invalid precision")));
+        assert_eq!(run(&mut formatter, "%2147483648d"), Err(String::from("2147483648: invalid field width")));
+        assert_eq!(take_field(&['1', '2', 'd'], "field width"), Ok((12, 2)));
+    }
+}
@@ -0,0 +1,376 @@
+use super::JobManager;
+use super::super::readline::{LineOptions, Readline, ReadlineEvent};
+use nix;
+use std::env;
+use std::time::{Duration, Instant};
+
+/// Status of `read` when `-t` runs out, 128 plus SIGALRM as in bash.
+const TIMEOUT_STATUS: i8 = (128 + nix::libc::SIGALRM) as i8;
+
+const STDIN: nix::libc::c_int = 0;
+
+struct ReadOptions {
+    /// `-r`: backslashes are ordinary characters.
+    raw: bool,
+    prompt: Option<String>,
+    /// `-s`: the typed characters are not shown.
+    silent: bool,
+    timeout: Option<Duration>,
+    max_chars: Option<usize>,
+    delimiter: char,
+    array: Option<String>,
+    names: Vec<String>,
+}
+
+fn parse_options(args: &[String]) -> Result<ReadOptions, String> {
+    let mut options = ReadOptions {
+        raw: false,
+        prompt: None,
+        silent: false,
+        timeout: None,
+        max_chars: None,
+        delimiter: '\n',
+        array: None,
+        names: Vec::new(),
+    };
+    let mut index = 0;
+    while index < args.len() {
+        let arg = &args[index];
+        index += 1;
+        if arg == "--" {
+            break;
+        }
+        if !arg.starts_with('-') || arg.len() == 1 {
+            index -= 1;
+            break;
+        }
+        for (position, flag) in arg.char_indices().skip(1) {
+            match flag {
+                'r' => options.raw = true,
+                's' => options.silent = true,
+                'p' | 't' | 'n' | 'd' | 'a' => {
+                    // the value is the rest of the word, or the next argument
+                    let value = if position + 1 < arg.len() {
+                        arg[position + 1..].to_owned()
+                    } else if index < args.len() {
+                        index += 1;
+                        args[index - 1].clone()
+                    } else {
+                        return Err(format!("-{}: option requires an argument", flag));
+                    };
+                    match flag {
+                        'p' => options.prompt = Some(value),
+                        't' => match value.parse::<f64>() {
+                            Ok(seconds) if seconds >= 0.0 => {
+                                options.timeout = Some(Duration::new(seconds.trunc() as u64, (seconds.fract() * 1_000_000_000.0) as u32))
+                            }
+                            _ => return Err(format!("{}: invalid timeout specification", value)),
+                        },
+                        'n' => match value.parse::<usize>() {
+                            Ok(count) => options.max_chars = Some(count),
+                            Err(_) => return Err(format!("{}: invalid number", value)),
+                        },
+                        // an empty delimiter reads up to a NUL byte
+                        'd' => options.delimiter = value.chars().next().unwrap_or('\0'),
+                        _ => options.array = Some(value),
+                    }
+                    break;
+                }
+                other => return Err(format!("-{}: invalid option", other)),
+            }
+        }
+    }
+    options.names = args[index..].to_vec();
+    Ok(options)
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with(|c: char| c.is_digit(10)) && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Turns the characters read into (character, escaped) pairs. Without `-r` a backslash is
+/// dropped and the character after it taken literally, so that it cannot split words.
+fn unescape(line: &str, raw: bool) -> Vec<(char, bool)> {
+    let mut result = Vec::new();
+    let mut escaped = false;
+    for c in line.chars() {
+        if escaped {
+            result.push((c, true));
+            escaped = false;
+        } else if c == '\\' && !raw {
+            escaped = true;
+        } else {
+            result.push((c, false));
+        }
+    }
+    result
+}
+
+/// Splits a line into fields on the characters of `ifs`, the way `read` assigns words to
+/// variables: runs of IFS whitespace separate fields and are trimmed at both ends, each other
+/// IFS character ends exactly one field, and with `max` set the last field takes the rest of
+/// the line.
+fn split_fields(line: &[(char, bool)], ifs: &str, max: Option<usize>) -> Vec<String> {
+    let is_ifs = |&(c, escaped): &(char, bool)| !escaped && ifs.contains(c);
+    let is_ifs_whitespace = |item: &(char, bool)| is_ifs(item) && item.0.is_whitespace();
+    let collect = |items: &[(char, bool)]| items.iter().map(|&(c, _)| c).collect::<String>();
+    let mut fields = Vec::new();
+    let mut index = 0;
+    while index < line.len() && is_ifs_whitespace(&line[index]) {
+        index += 1;
+    }
+    while index < line.len() {
+        if max.map(|max| fields.len() + 1 >= max).unwrap_or(false) {
+            let mut end = line.len();
+            while end > index && is_ifs_whitespace(&line[end - 1]) {
+                end -= 1;
+            }
+            fields.push(collect(&line[index..end]));
+            break;
+        }
+        let start = index;
+        while index < line.len() && !is_ifs(&line[index]) {
+            index += 1;
+        }
+        fields.push(collect(&line[start..index]));
+        while index < line.len() && is_ifs_whitespace(&line[index]) {
+            index += 1;
+        }
+        if index < line.len() && is_ifs(&line[index]) {
+            index += 1;
+            while index < line.len() && is_ifs_whitespace(&line[index]) {
+                index += 1;
+            }
+        }
+    }
+    fields
+}
+
+/// Reads from stdin one byte at a time, so that nothing after the delimiter is consumed and the
+/// next command reading the same file continues where `read` stopped.
+fn read_stdin(options: &ReadOptions, deadline: Option<Instant>) -> (String, ReadlineEvent) {
+    let mut bytes = Vec::new();
+    let mut escaped = false;
+    let event = loop {
+        if let Some(deadline) = deadline {
+            let now = Instant::now();
+            let remaining = if deadline > now { deadline - now } else { Duration::from_secs(0) };
+            let mut fds = [nix::poll::PollFd::new(STDIN, nix::poll::POLLIN)];
+            match nix::poll::poll(&mut fds, super::poll_timeout(Some(remaining))) {
+                Ok(0) => break ReadlineEvent::Timeout,
+                Err(nix::Error::Sys(nix::Errno::EINTR)) => continue,
+                _ => {}
+            }
+        }
+        let mut byte = [0u8];
+        match nix::unistd::read(STDIN, &mut byte) {
+            Ok(0) => break ReadlineEvent::Eof,
+            Ok(_) => {}
+            Err(nix::Error::Sys(nix::Errno::EINTR)) => continue,
+            Err(_) => break ReadlineEvent::Eof,
+        }
+        let c = byte[0];
+        if !escaped && c as char == options.delimiter && options.delimiter.len_utf8() == 1 {
+            break ReadlineEvent::Done;
+        }
+        if c == 0 {
+            // like bash, NUL bytes are dropped since variables cannot hold them
+            continue;
+        }
+        if escaped && c == b'\n' {
+            // a backslash before a newline continues the line
+            bytes.pop();
+            escaped = false;
+            continue;
+        }
+        escaped = !escaped && c == b'\\' && !options.raw;
+        bytes.push(c);
+        if let Some(max) = options.max_chars {
+            if let Ok(line) = String::from_utf8(bytes.clone()) {
+                if line.chars().count() >= max {
+                    break ReadlineEvent::Done;
+                }
+            }
+        }
+    };
+    (String::from_utf8_lossy(&bytes).into_owned(), event)
+}
+
+/// Reads with the line editor, asking for more lines while one ends in a backslash.
+fn read_terminal(options: &ReadOptions, deadline: Option<Instant>) -> (String, ReadlineEvent) {
+    let mut readline = Readline::new();
+    let line_options = LineOptions {
+        max_chars: options.max_chars,
+        delimiter: if options.delimiter == '\n' { None } else { Some(options.delimiter) },
+        deadline: deadline,
+    };
+    let mut prompt = options.prompt.clone().unwrap_or(String::new());
+    let mut result = String::new();
+    loop {
+        let (line, event) = readline.read_line(&prompt, options.silent, &line_options);
+        result.push_str(&line);
+        let trailing_backslashes = line.chars().rev().take_while(|c| *c == '\\').count();
+        match event {
+            ReadlineEvent::Done if !options.raw && trailing_backslashes % 2 == 1 && options.max_chars.is_none() => {
+                result.pop();
+                prompt = String::from("> ");
+            }
+            event => return (result, event),
+        }
+    }
+}
+
+/// Turns off echo on the terminal for `read -s` when the line editor is not used, returning
+/// the settings to restore.
+fn disable_echo() -> Option<nix::sys::termios::Termios> {
+    let original = nix::sys::termios::tcgetattr(STDIN).ok()?;
+    let mut silent = original.clone();
+    silent.local_flags.remove(nix::sys::termios::ECHO);
+    nix::sys::termios::tcsetattr(STDIN, nix::sys::termios::SetArg::TCSADRAIN, &silent).ok()?;
+    Some(original)
+}
+
+impl JobManager {
+    pub fn read_builtin(&mut self, args: &[String]) -> i8 {
+        let options = match parse_options(args) {
+            Ok(options) => options,
+            Err(e) => {
+                eprintln!("read: {}", e);
+                eprintln!("read: usage: read [-rs] [-a array] [-d delim] [-n nchars] [-p prompt] [-t timeout] [name ...]");
+                return 2;
+            }
+        };
+        for name in options.names.iter().chain(options.array.iter()) {
+            if !valid_name(name) {
+                eprintln!("read: `{}': not a valid identifier", name);
+                return 1;
+            }
+        }
+        let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+        let stdin_tty = nix::unistd::isatty(STDIN).unwrap_or(false);
+        // like bash, `-n 0` does not read anything; the editor draws on stdout, so it is only
+        // used when both ends are the terminal
+        let (line, event) = if options.max_chars == Some(0) {
+            (String::new(), ReadlineEvent::Done)
+        } else if stdin_tty && nix::unistd::isatty(1).unwrap_or(false) {
+            read_terminal(&options, deadline)
+        } else {
+            if let (true, &Some(ref prompt)) = (stdin_tty, &options.prompt) {
+                eprint!("{}", prompt);
+            }
+            let saved = if stdin_tty && options.silent { disable_echo() } else { None };
+            let result = read_stdin(&options, deadline);
+            if let Some(saved) = saved {
+                let _ = nix::sys::termios::tcsetattr(STDIN, nix::sys::termios::SetArg::TCSADRAIN, &saved);
+                eprintln!("");
+            }
+            result
+        };
+        let status = match event {
+            ReadlineEvent::Done => 0,
+            ReadlineEvent::Eof => 1,
+            ReadlineEvent::Timeout => TIMEOUT_STATUS,
+            ReadlineEvent::Hangup => return (128 + nix::libc::SIGHUP) as i8,
+            _ => return (128 + nix::libc::SIGINT) as i8,
+        };
+        let ifs = env::var("IFS").unwrap_or(String::from(" \t\n"));
+        let line = unescape(&line, options.raw);
+        if let Some(ref array) = options.array {
+            self.variables.set_array(array, split_fields(&line, &ifs, None));
+        }
+        if options.names.is_empty() {
+            if options.array.is_none() {
+                env::set_var("REPLY", line.iter().map(|&(c, _)| c).collect::<String>());
+            }
+            return status;
+        }
+        let mut fields = split_fields(&line, &ifs, Some(options.names.len())).into_iter();
+        for name in &options.names {
+            self.variables.remove_array(name);
+            env::set_var(name, fields.next().unwrap_or(String::new()));
+        }
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| String::from(*word)).collect()
+    }
+
+    fn split(line: &str, ifs: &str, max: Option<usize>) -> Vec<String> {
+        split_fields(&unescape(line, false), ifs, max)
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = parse_options(&args(&["-r", "-p", "name? ", "first", "second"])).unwrap();
+        assert!(options.raw);
+        assert_eq!(options.prompt, Some(String::from("name? ")));
+        assert_eq!(options.names, args(&["first", "second"]));
+
+        let options = parse_options(&args(&["-rsn3", "-t", "1.5", "-a", "words"])).unwrap();
+        assert!(options.raw && options.silent);
+        assert_eq!(options.max_chars, Some(3));
+        assert_eq!(options.timeout, Some(Duration::from_millis(1500)));
+        assert_eq!(options.array, Some(String::from("words")));
+        assert!(options.names.is_empty());
+
+        let options = parse_options(&args(&["-d", "", "--", "-name"])).unwrap();
+        assert_eq!(options.delimiter, '\0');
+        assert_eq!(options.names, args(&["-name"]));
+
+        assert_eq!(parse_options(&args(&["-", "x"])).unwrap().names, args(&["-", "x"]));
+    }
+
+    #[test]
+    fn test_parse_options_errors() {
+        assert_eq!(parse_options(&args(&["-x"])).err(), Some(String::from("-x: invalid option")));
+        assert_eq!(parse_options(&args(&["-n"])).err(), Some(String::from("-n: option requires an argument")));
+        assert_eq!(parse_options(&args(&["-n", "many"])).err(), Some(String::from("many: invalid number")));
+        assert_eq!(parse_options(&args(&["-t", "-1"])).err(), Some(String::from("-1: invalid timeout specification")));
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape("a\\ b", false), vec![('a', false), (' ', true), ('b', false)]);
+        assert_eq!(unescape("a\\\\", false), vec![('a', false), ('\\', true)]);
+        // a trailing backslash has nothing to escape
+        assert_eq!(unescape("a\\", false), vec![('a', false)]);
+        assert_eq!(unescape("a\\ b", true), vec![('a', false), ('\\', false), (' ', false), ('b', false)]);
+    }
+
+    #[test]
+    fn test_split_whitespace() {
+        assert_eq!(split("  one  two\tthree  ", " \t\n", None), args(&["one", "two", "three"]));
+        assert_eq!(split("", " \t\n", None), Vec::<String>::new());
+        assert_eq!(split("   ", " \t\n", Some(2)), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_split_last_takes_rest() {
+        assert_eq!(split("  one  two  three  ", " \t\n", Some(2)), args(&["one", "two  three"]));
+        assert_eq!(split("one two", " \t\n", Some(1)), args(&["one two"]));
+        assert_eq!(split("a:b:c", ":", Some(2)), args(&["a", "b:c"]));
+    }
+
+    #[test]
+    fn test_split_other_separators() {
+        assert_eq!(split("a:b::c", ":", None), args(&["a", "b", "", "c"]));
+        assert_eq!(split(":a", ":", None), args(&["", "a"]));
+        assert_eq!(split("a:b:", ":", None), args(&["a", "b"]));
+        // whitespace around another separator belongs to it
+        assert_eq!(split(" a : b ", ": ", None), args(&["a", "b"]));
+        assert_eq!(split("a  b", ": ", None), args(&["a", "b"]));
+    }
+
+    #[test]
+    fn test_split_escaped_separators() {
+        assert_eq!(split("a\\ b c", " \t\n", None), args(&["a b", "c"]));
+        assert_eq!(split("a\\:b:c", ":", None), args(&["a:b", "c"]));
+        assert_eq!(split_fields(&unescape("a\\ b", true), " ", None), args(&["a\\", "b"]));
+    }
+}
@@ -0,0 +1,211 @@
+use super::job::{self, move_out_of_user_range};
+use super::jobspec::{self, Table};
+use super::{control, traps, Job, Notifications};
+use nix;
+use std::ops::DerefMut;
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicIsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Write end of the pipe that the SIGCHLD handler writes to, or -1 before it is installed.
+static SIGCHLD_PIPE: AtomicIsize = AtomicIsize::new(-1);
+
+extern "C" fn handle_sigchld(_: nix::libc::c_int) {
+    wake();
+}
+
+/// Wakes up whoever is blocked in `Reaper::wait_for_sigchld`, as SIGCHLD does. It only writes
+/// to a pipe, so it can be called from other signal handlers too.
+pub fn wake() {
+    let fd = SIGCHLD_PIPE.load(Ordering::Relaxed);
+    if fd >= 0 {
+        let byte = [0u8];
+        // the pipe is non-blocking; if it is full a wakeup is already pending
+        unsafe {
+            nix::libc::write(fd as RawFd, byte.as_ptr() as *const nix::libc::c_void, 1);
+        }
+    }
+}
+
+/// Reaps background jobs when SIGCHLD arrives.
+///
+/// SIGCHLD only wakes up whoever is polling `fd()`; the `waitpid` calls themselves all happen on
+/// the main thread, between commands or while the line editor waits for input, so no exit
+/// status is ever collected by someone who was not expecting it.
+#[derive(Clone)]
+pub struct Reaper {
+    background_jobs: Arc<RwLock<Vec<Job>>>,
+    stopped_jobs: Arc<RwLock<Vec<Job>>>,
+    notifications: Arc<RwLock<Notifications>>,
+    fd: RawFd,
+}
+
+impl Reaper {
+    pub fn new(background_jobs: Arc<RwLock<Vec<Job>>>, stopped_jobs: Arc<RwLock<Vec<Job>>>, notifications: Arc<RwLock<Notifications>>) -> nix::Result<Reaper> {
+        let (read_fd, write_fd) = nix::unistd::pipe2(nix::fcntl::O_CLOEXEC | nix::fcntl::O_NONBLOCK)?;
+        let read_fd = move_out_of_user_range(read_fd)?;
+        let write_fd = move_out_of_user_range(write_fd)?;
+        SIGCHLD_PIPE.store(write_fd as isize, Ordering::Relaxed);
+        let sigaction = nix::sys::signal::SigAction::new(
+            nix::sys::signal::SigHandler::Handler(handle_sigchld),
+            nix::sys::signal::SA_RESTART,
+            nix::sys::signal::SigSet::empty(),
+        );
+        traps::changed_disposition(nix::sys::signal::Signal::SIGCHLD);
+        unsafe {
+            nix::sys::signal::sigaction(nix::sys::signal::Signal::SIGCHLD, &sigaction)?;
+        }
+        Ok(Reaper {
+            background_jobs: background_jobs,
+            stopped_jobs: stopped_jobs,
+            notifications: notifications,
+            fd: read_fd,
+        })
+    }
+
+    /// File descriptor that becomes readable when a child process changes state.
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    pub fn notifications(&self) -> &RwLock<Notifications> {
+        &self.notifications
+    }
+
+    /// Blocks until a child process changes state or `timeout` has passed, for builtins such as
+    /// `wait -n` that wait on several jobs at once and for jobs with a `timeout`.
+    pub fn wait_for_sigchld(&self, timeout: Option<Duration>) {
+        let mut fds = [nix::poll::PollFd::new(self.fd, nix::poll::POLLIN)];
+        loop {
+            match nix::poll::poll(&mut fds, poll_timeout(timeout)) {
+                Err(nix::Error::Sys(nix::Errno::EINTR)) => {}
+                _ => break,
+            }
+        }
+        self.clear();
+    }
+
+    fn clear(&self) {
+        let mut buf = [0u8; 64];
+        while let Ok(count) = nix::unistd::read(self.fd, &mut buf) {
+            if count == 0 {
+                break;
+            }
+        }
+    }
+
+    /// Clears pending wakeups and reaps every background job that has changed state.
+    pub fn reap(&self) {
+        self.clear();
+        reap_jobs(&self.background_jobs, &self.stopped_jobs, &self.notifications);
+    }
+
+    pub fn next_timeout(&self) -> Option<Duration> {
+        next_timeout(&self.background_jobs, &self.stopped_jobs)
+    }
+
+    pub fn check_timeouts(&self) {
+        check_timeouts(&self.background_jobs, &self.stopped_jobs);
+    }
+}
+
+/// Converts a timeout to milliseconds for `poll`, rounding up so that it does not wake up just
+/// before a deadline; `None` waits forever.
+pub fn poll_timeout(timeout: Option<Duration>) -> nix::libc::c_int {
+    match timeout {
+        Some(timeout) => {
+            let millis = timeout.as_secs() * 1000 + (u64::from(timeout.subsec_nanos()) + 999_999) / 1_000_000;
+            millis.min(nix::libc::c_int::max_value() as u64) as nix::libc::c_int
+        }
+        None => -1,
+    }
+}
+
+/// Time until the `timeout` of a background or stopped job sends its next signal.
+pub fn next_timeout(background_jobs: &RwLock<Vec<Job>>, stopped_jobs: &RwLock<Vec<Job>>) -> Option<Duration> {
+    let bg_jobs = background_jobs.read().unwrap();
+    let st_jobs = stopped_jobs.read().unwrap();
+    bg_jobs.iter().chain(st_jobs.iter()).filter_map(|job| job.timeout_remaining()).min()
+}
+
+/// Signals the background and stopped jobs whose `timeout` has expired. Stopped jobs are
+/// continued when they get SIGTERM, so they move to the background table to be reaped.
+pub fn check_timeouts(background_jobs: &RwLock<Vec<Job>>, stopped_jobs: &RwLock<Vec<Job>>) {
+    let mut bg_jobs = background_jobs.write().unwrap();
+    let mut st_jobs = stopped_jobs.write().unwrap();
+    for job in bg_jobs.iter_mut() {
+        job.check_timeout();
+    }
+    let mut still_stopped = Vec::new();
+    for mut job in st_jobs.drain(..) {
+        job.check_timeout();
+        if job.is_running() {
+            bg_jobs.push(job);
+        } else {
+            still_stopped.push(job);
+        }
+    }
+    *st_jobs.deref_mut() = still_stopped;
+}
+
+/// Waits on every background job without blocking, moving stopped jobs to the stopped table
+/// and dropping finished ones. Each change is queued as a notification.
+pub fn reap_jobs(background_jobs: &RwLock<Vec<Job>>, stopped_jobs: &RwLock<Vec<Job>>, notifications: &RwLock<Notifications>) {
+    let mut bg_jobs = background_jobs.write().unwrap();
+    let mut st_jobs = stopped_jobs.write().unwrap();
+    // the +/- markers are worked out before any job leaves the table, so they match what `jobs` showed
+    let markers: Vec<char> = (0..bg_jobs.len())
+        .map(|index| {
+            jobspec::marker(
+                jobspec::JobRef {
+                    table: Table::Background,
+                    index: index,
+                },
+                &bg_jobs,
+                &st_jobs,
+            )
+        })
+        .collect();
+    let mut new_bg_jobs = Vec::new();
+    let mut messages = Vec::new();
+    for (mut job, marker) in bg_jobs.drain(..).zip(markers) {
+        match job.get_status() {
+            job::Status::NotStarted => {
+                panic!("found job in bg queue that has not been started");
+            }
+            job::Status::Started(_, _, _) => {
+                if job.any_stage_running() {
+                    if let Err(e) = job.wait(Some(nix::sys::wait::WUNTRACED | nix::sys::wait::WNOHANG)) {
+                        messages.push(format!("radish: lost track of job {} ({}): {}", job.id.unwrap_or(0), job.command, e));
+                        continue;
+                    }
+                }
+                match job.wait_status() {
+                    nix::sys::wait::WaitStatus::StillAlive | nix::sys::wait::WaitStatus::Continued(_) => {
+                        new_bg_jobs.push(job);
+                    }
+                    nix::sys::wait::WaitStatus::Stopped(_, _) => {
+                        messages.push(control::format_job(&job, marker, false));
+                        job.touch();
+                        st_jobs.push(job);
+                    }
+                    // the last stage of a pipeline can finish before the others, which stay in
+                    // the table until they have been reaped too
+                    _ if job.any_stage_running() => {
+                        new_bg_jobs.push(job);
+                    }
+                    _ => {
+                        // remove from queue
+                        messages.push(control::format_job(&job, marker, false));
+                    }
+                }
+            }
+        }
+    }
+    *bg_jobs.deref_mut() = new_bg_jobs;
+    let mut notifications = notifications.write().unwrap();
+    for message in messages {
+        notifications.push(message);
+    }
+}
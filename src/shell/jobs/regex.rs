@@ -0,0 +1,121 @@
+extern crate libc;
+use std::ffi::CString;
+use std::mem;
+
+/// A compiled POSIX extended regular expression, as used by `=~` in `[[ ]]`.
+pub struct Regex {
+    compiled: Box<libc::regex_t>,
+    groups: usize,
+}
+
+/// Counts the parenthesised groups in an extended regular expression, skipping escaped
+/// parentheses and those inside bracket expressions.
+fn count_groups(pattern: &str) -> usize {
+    let mut groups = 0;
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '(' => groups += 1,
+            '[' => {
+                // a `]` right after `[` or `[^` is part of the set
+                if chars.peek() == Some(&'^') {
+                    chars.next();
+                }
+                if chars.peek() == Some(&']') {
+                    chars.next();
+                }
+                while let Some(c) = chars.next() {
+                    if c == ']' {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    groups
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, String> {
+        let c_pattern = CString::new(pattern).map_err(|_| String::from("pattern contains a NUL byte"))?;
+        let mut compiled: Box<libc::regex_t> = Box::new(unsafe { mem::zeroed() });
+        let code = unsafe { libc::regcomp(&mut *compiled, c_pattern.as_ptr(), libc::REG_EXTENDED) };
+        if code != 0 {
+            let mut buffer = [0u8; 256];
+            let length = unsafe { libc::regerror(code, &*compiled, buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) };
+            let end = buffer.iter().position(|&b| b == 0).unwrap_or(length.min(buffer.len()));
+            return Err(String::from_utf8_lossy(&buffer[..end]).into_owned());
+        }
+        Ok(Regex {
+            compiled: compiled,
+            groups: count_groups(pattern),
+        })
+    }
+
+    /// Matches `text` against the expression, returning the whole match followed by each group,
+    /// with groups that took no part in the match left empty.
+    pub fn captures(&self, text: &str) -> Option<Vec<String>> {
+        let c_text = CString::new(text).ok()?;
+        let mut matches = vec![libc::regmatch_t { rm_so: -1, rm_eo: -1 }; self.groups + 1];
+        let code = unsafe { libc::regexec(&*self.compiled, c_text.as_ptr(), matches.len(), matches.as_mut_ptr(), 0) };
+        if code != 0 {
+            return None;
+        }
+        let bytes = text.as_bytes();
+        Some(
+            matches
+                .iter()
+                .map(|m| if m.rm_so < 0 || m.rm_eo < m.rm_so {
+                    String::new()
+                } else {
+                    String::from_utf8_lossy(&bytes[m.rm_so as usize..m.rm_eo as usize]).into_owned()
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Drop for Regex {
+    fn drop(&mut self) {
+        unsafe { libc::regfree(&mut *self.compiled) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| String::from(*word)).collect()
+    }
+
+    #[test]
+    fn test_count_groups() {
+        assert_eq!(count_groups("abc"), 0);
+        assert_eq!(count_groups("a(b)(c)"), 2);
+        assert_eq!(count_groups("((a)|b)"), 2);
+        assert_eq!(count_groups("\\(a\\)"), 0);
+        assert_eq!(count_groups("[(]x"), 0);
+        assert_eq!(count_groups("[]()](a)"), 1);
+        assert_eq!(count_groups("[^]()](a)"), 1);
+    }
+
+    #[test]
+    fn test_captures() {
+        let regex = Regex::new("^([0-9]+)-([0-9]+)").unwrap();
+        assert_eq!(regex.captures("2026-10-19"), Some(strings(&["2026-10", "2026", "10"])));
+        assert_eq!(regex.captures("October"), None);
+        // a group that took no part in the match is empty
+        assert_eq!(Regex::new("a(x)?b").unwrap().captures("ab"), Some(strings(&["ab", ""])));
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        assert!(Regex::new("(").is_err());
+        assert!(Regex::new("a\0b").is_err());
+    }
+}
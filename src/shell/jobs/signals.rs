@@ -0,0 +1,24 @@
+use nix::sys::signal::Signal;
+
+/// The name of a signal without the `SIG` prefix, e.g. `TERM`.
+pub fn name(signal: Signal) -> String {
+    let name = format!("{:?}", signal);
+    name.trim_start_matches("SIG").to_owned()
+}
+
+/// Parses a signal given by number, or by name with or without the `SIG` prefix in any case.
+pub fn parse(spec: &str) -> Option<Signal> {
+    if let Ok(number) = spec.parse::<i32>() {
+        return Signal::from_c_int(number).ok();
+    }
+    let spec = spec.to_uppercase();
+    let spec = spec.trim_start_matches("SIG");
+    Signal::iterator().find(|signal| name(*signal) == spec)
+}
+
+/// Every signal as `kill -l` lists it, e.g. ` 1) HUP`.
+pub fn list() -> Vec<String> {
+    Signal::iterator()
+        .map(|signal| format!("{:2}) {}", signal as i32, name(signal)))
+        .collect()
+}
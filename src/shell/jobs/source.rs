@@ -0,0 +1,142 @@
+use super::JobManager;
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::PathBuf;
+
+/// A script read by `source` or at startup, to be run line by line in the current shell.
+pub struct Script {
+    pub contents: String,
+    /// Positional parameters for the duration of the script; without any the script sees
+    /// those of the caller.
+    pub args: Option<Vec<String>>,
+}
+
+impl Script {
+    pub fn read(path: &str) -> io::Result<Script> {
+        let mut contents = String::new();
+        File::open(path)?.read_to_string(&mut contents)?;
+        Ok(Script {
+            contents: contents,
+            args: None,
+        })
+    }
+}
+
+/// Finds the file named by `source`: a name without a slash is looked up in `PATH` first and
+/// then in the current directory, as in bash.
+fn find_script(name: &str) -> PathBuf {
+    if !name.contains('/') {
+        if let Some(paths) = env::var_os("PATH") {
+            if let Some(found) = env::split_paths(&paths).map(|dir| dir.join(name)).find(|path| path.is_file()) {
+                return found;
+            }
+        }
+    }
+    PathBuf::from(name)
+}
+
+/// Runs one line of a script the way the shell runs a typed one, traps included. The job
+/// manager gets it from the shell, which owns the parser.
+pub type LineRunner = fn(&mut JobManager, &str);
+
+/// The state the shell saves while it runs a sourced script.
+struct SourceFrame {
+    positional: Option<Vec<String>>,
+}
+
+impl JobManager {
+    pub fn source_builtin(&mut self, name: &str, args: &[String]) -> i8 {
+        let file = match args.first() {
+            Some(file) => file,
+            None => {
+                eprintln!("{}: filename argument required", name);
+                eprintln!("{}: usage: {} filename [arguments]", name, name);
+                return 2;
+            }
+        };
+        let path = find_script(file);
+        match Script::read(&path.to_string_lossy()) {
+            Ok(mut script) => {
+                if args.len() > 1 {
+                    script.args = Some(args[1..].to_vec());
+                }
+                // the commands of the script must not be timed as part of the `source` around them
+                let timed = self.timed;
+                self.timed = false;
+                let status = self.run_source(script);
+                self.timed = timed;
+                status
+            }
+            Err(e) => {
+                eprintln!("{}: {}: {}", name, file, e);
+                1
+            }
+        }
+    }
+
+    pub fn return_builtin(&mut self, args: &[String]) -> i8 {
+        if self.source_depth == 0 {
+            eprintln!("return: can only `return' from a sourced script");
+            return 1;
+        }
+        let status = match args.first().map(|a| a.trim().parse::<i64>()) {
+            None => self.variables.last_status,
+            // only the low byte is kept, so `return 300` is 44 and `return -1` is 255
+            Some(Ok(status)) => (status & 0xff) as u8 as i8,
+            Some(Err(_)) => {
+                eprintln!("return: {}: numeric argument required", args[0]);
+                2
+            }
+        };
+        self.returning = Some(status);
+        status
+    }
+
+    pub fn set_line_runner(&mut self, run_line: LineRunner) {
+        self.line_runner = Some(run_line);
+    }
+
+    /// Runs a script in the current shell, until its last line, `return`, `exit` or `exec`. The
+    /// status is that of the last command, or the one given to `return`.
+    pub fn run_source(&mut self, script: Script) -> i8 {
+        let run_line = self.line_runner.expect("the shell sets the line runner before running commands");
+        let frame = self.enter_source(script.args);
+        let mut status = 0;
+        for line in script.contents.lines() {
+            let line = line.trim();
+            if line.len() == 0 || line.starts_with('#') {
+                continue;
+            }
+            run_line(self, line);
+            status = self.variables.last_status;
+            if self.source_finished() {
+                break;
+            }
+        }
+        self.leave_source(frame).unwrap_or(status)
+    }
+
+    /// Sets up the positional parameters of a script about to be sourced.
+    fn enter_source(&mut self, args: Option<Vec<String>>) -> SourceFrame {
+        self.source_depth += 1;
+        SourceFrame {
+            positional: args.map(|args| self.variables.replace_positional(args)),
+        }
+    }
+
+    /// Undoes `enter_source`, returning the status given to `return` if that ended the script.
+    fn leave_source(&mut self, frame: SourceFrame) -> Option<i8> {
+        self.source_depth -= 1;
+        if let Some(positional) = frame.positional {
+            self.variables.replace_positional(positional);
+        }
+        self.returning.take()
+    }
+
+    /// Whether the sourced script being run should stop, because of `return`, `exit` or `exec`.
+    fn source_finished(&self) -> bool {
+        self.returning.is_some() || self.exit_status.is_some() || self.exec_command.is_some()
+    }
+}
@@ -0,0 +1,182 @@
+use nix::sys::signal::Signal;
+use std::time::{Duration, Instant};
+
+/// How long a job gets between SIGTERM and SIGKILL unless `timeout -k` says otherwise.
+const DEFAULT_GRACE: Duration = Duration::from_secs(5);
+
+/// A time limit set with the `timeout` prefix. When it expires the job gets SIGTERM, and
+/// SIGKILL if it is still around after the grace period. Like in coreutils, a duration of 0
+/// means no limit, and a grace period of 0 that SIGKILL is never sent.
+#[derive(Clone, Copy, Debug)]
+pub struct Timeout {
+    duration: Duration,
+    grace: Duration,
+    /// When the next signal is due, once the job has started.
+    deadline: Option<Instant>,
+    /// How many of SIGTERM and SIGKILL have been sent.
+    signals_sent: usize,
+}
+
+impl Timeout {
+    pub fn start(&mut self) {
+        if self.duration > Duration::from_secs(0) {
+            self.deadline = Some(Instant::now() + self.duration);
+        }
+    }
+
+    /// Whether the job ran out of time, in which case its exit status is 124.
+    pub fn expired(&self) -> bool {
+        self.signals_sent > 0
+    }
+
+    /// Time left until the next signal is due, or `None` if there is nothing left to send.
+    pub fn remaining(&self) -> Option<Duration> {
+        if self.signals_sent >= 2 {
+            return None;
+        }
+        let now = Instant::now();
+        self.deadline.map(|deadline| if deadline > now { deadline - now } else { Duration::from_secs(0) })
+    }
+
+    /// Returns whichever signal is due for the job, SIGTERM first and then SIGKILL, and moves on
+    /// to the next one.
+    pub fn due(&mut self) -> Option<Signal> {
+        let deadline = match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => deadline,
+            _ => return None,
+        };
+        match self.signals_sent {
+            0 => {
+                self.deadline = if self.grace > Duration::from_secs(0) { Some(deadline + self.grace) } else { None };
+                self.signals_sent = 1;
+                Some(Signal::SIGTERM)
+            }
+            1 => {
+                self.signals_sent = 2;
+                Some(Signal::SIGKILL)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Parses a duration such as `30`, `1.5s`, `2m`, `1h` or `1d`.
+fn parse_duration(spec: &str) -> Option<Duration> {
+    let split = spec.find(|c: char| !c.is_digit(10) && c != '.').unwrap_or(spec.len());
+    let number = spec[..split].parse::<f64>().ok()?;
+    let multiplier = match &spec[split..] {
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 60.0 * 60.0,
+        "d" => 24.0 * 60.0 * 60.0,
+        _ => return None,
+    };
+    let seconds = number * multiplier;
+    Some(Duration::new(seconds.trunc() as u64, (seconds.fract() * 1_000_000_000.0) as u32))
+}
+
+/// Splits the arguments of `timeout [-k grace] duration command args` into the timeout and the
+/// command line it applies to.
+pub fn split_prefix(args: &[String]) -> Result<(Timeout, &[String]), String> {
+    let mut grace = DEFAULT_GRACE;
+    let mut index = 0;
+    if args.get(index).map(|a| a == "-k").unwrap_or(false) {
+        match args.get(index + 1).map(|a| (a, parse_duration(a))) {
+            Some((_, Some(duration))) => grace = duration,
+            Some((spec, None)) => return Err(format!("{}: invalid duration", spec)),
+            None => return Err(String::from("-k: option requires an argument")),
+        }
+        index += 2;
+    }
+    if args.get(index).map(|a| a == "--").unwrap_or(false) {
+        index += 1;
+    }
+    let duration = match args.get(index) {
+        Some(spec) => parse_duration(spec).ok_or(format!("{}: invalid duration", spec))?,
+        None => return Err(String::from("missing duration")),
+    };
+    Ok((
+        Timeout {
+            duration: duration,
+            grace: grace,
+            deadline: None,
+            signals_sent: 0,
+        },
+        &args[index + 1..],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|&arg| arg.to_owned()).collect()
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("1.5s"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("2m"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_duration("1h"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_duration("1d"), Some(Duration::from_secs(86400)));
+        assert_eq!(parse_duration("0"), Some(Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn test_parse_invalid_duration() {
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("s"), None);
+        assert_eq!(parse_duration("5x"), None);
+        assert_eq!(parse_duration("5ms"), None);
+        assert_eq!(parse_duration("-1"), None);
+        assert_eq!(parse_duration("1.2.3"), None);
+    }
+
+    #[test]
+    fn test_split_prefix() {
+        let args = strings(&["-k", "2", "5s", "make", "-j4"]);
+        let (timeout, rest) = split_prefix(&args).unwrap();
+        assert_eq!(rest, &args[3..]);
+        assert_eq!(timeout.duration, Duration::from_secs(5));
+        assert_eq!(timeout.grace, Duration::from_secs(2));
+
+        let args = strings(&["--", "1m", "--", "ls"]);
+        let (timeout, rest) = split_prefix(&args).unwrap();
+        assert_eq!(rest, &args[2..]);
+        assert_eq!(timeout.duration, Duration::from_secs(60));
+        assert_eq!(timeout.grace, DEFAULT_GRACE);
+    }
+
+    #[test]
+    fn test_split_prefix_errors() {
+        assert_eq!(split_prefix(&strings(&[])).err(), Some(String::from("missing duration")));
+        assert_eq!(split_prefix(&strings(&["--"])).err(), Some(String::from("missing duration")));
+        assert_eq!(split_prefix(&strings(&["5y", "ls"])).err(), Some(String::from("5y: invalid duration")));
+        assert_eq!(split_prefix(&strings(&["-k", "soon", "5", "ls"])).err(), Some(String::from("soon: invalid duration")));
+        assert_eq!(split_prefix(&strings(&["-k"])).err(), Some(String::from("-k: option requires an argument")));
+    }
+
+    #[test]
+    fn test_zero_means_no_limit() {
+        let args = strings(&["0", "ls"]);
+        let (mut timeout, _) = split_prefix(&args).unwrap();
+        timeout.start();
+        assert_eq!(timeout.due(), None);
+        assert_eq!(timeout.remaining(), None);
+        assert!(!timeout.expired());
+    }
+
+    #[test]
+    fn test_zero_grace_never_kills() {
+        let args = strings(&["-k", "0", "0.001", "ls"]);
+        let (mut timeout, _) = split_prefix(&args).unwrap();
+        timeout.start();
+        ::std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(timeout.due(), Some(Signal::SIGTERM));
+        assert_eq!(timeout.due(), None);
+        assert_eq!(timeout.remaining(), None);
+        assert!(timeout.expired());
+    }
+}
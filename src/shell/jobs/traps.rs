@@ -0,0 +1,280 @@
+use super::{signals, JobManager};
+use nix;
+use nix::sys::signal::Signal;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::{mem, ptr};
+
+/// Bit `n` is set when signal `n` arrived and its trap has not run yet.
+static PENDING: AtomicUsize = AtomicUsize::new(0);
+/// Bit `n` is set when signal `n` is ignored with `trap ''`, which child processes inherit.
+static IGNORED: AtomicUsize = AtomicUsize::new(0);
+/// Bit `n` is set when the shell changed the disposition of signal `n` at some point.
+static CHANGED: AtomicUsize = AtomicUsize::new(0);
+/// Bit `n` is set when signal `n` was already ignored when the shell started, as under `nohup`.
+static IGNORED_ON_ENTRY: AtomicUsize = AtomicUsize::new(0);
+
+/// Set when an interactive shell receives SIGHUP without a trap for it.
+static HANGUP: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_trapped_signal(signal: nix::libc::c_int) {
+    PENDING.fetch_or(1 << signal, Ordering::SeqCst);
+}
+
+extern "C" fn handle_hangup(_: nix::libc::c_int) {
+    HANGUP.store(true, Ordering::SeqCst);
+}
+
+/// Whether the terminal went away, in which case an interactive shell hangs up its jobs and
+/// exits.
+pub fn hangup_received() -> bool {
+    HANGUP.load(Ordering::SeqCst)
+}
+
+/// Something a trap can be set on: a signal or one of the shell's pseudo-signals.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Condition {
+    Signal(Signal),
+    /// Runs when the shell exits.
+    Exit,
+    /// Runs after a command line whose status is not zero.
+    Err,
+    /// Runs before every command line.
+    Debug,
+    /// Runs instead of the error for a command that was not found, with the command name and
+    /// its arguments as `${1}`, `${2}` and so on.
+    CommandNotFound,
+}
+
+impl Condition {
+    pub fn parse(spec: &str) -> Option<Condition> {
+        match spec.to_uppercase().as_ref() {
+            "0" | "EXIT" | "SIGEXIT" => Some(Condition::Exit),
+            "ERR" => Some(Condition::Err),
+            "DEBUG" => Some(Condition::Debug),
+            "COMMAND_NOT_FOUND" => Some(Condition::CommandNotFound),
+            _ => signals::parse(spec).map(Condition::Signal),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Condition::Signal(signal) => write!(f, "{:?}", signal),
+            &Condition::Exit => write!(f, "EXIT"),
+            &Condition::Err => write!(f, "ERR"),
+            &Condition::Debug => write!(f, "DEBUG"),
+            &Condition::CommandNotFound => write!(f, "COMMAND_NOT_FOUND"),
+        }
+    }
+}
+
+/// The commands set with `trap`, and the signal dispositions that go with them.
+pub struct Traps {
+    commands: Vec<(Condition, String)>,
+    interactive: bool,
+}
+
+impl Traps {
+    pub fn new() -> Self {
+        record_inherited_dispositions();
+        Traps {
+            commands: Vec::new(),
+            interactive: false,
+        }
+    }
+
+    /// Sets up the shell's own signal dispositions. An interactive shell ignores the keyboard
+    /// signals so that Ctrl-C and Ctrl-Z only reach the foreground job, and catches SIGHUP to
+    /// pass it on to its jobs; a script keeps the defaults, so it can be interrupted like any
+    /// other program.
+    pub fn set_interactive(&mut self, interactive: bool) -> nix::Result<()> {
+        self.interactive = interactive;
+        for signal in &[Signal::SIGINT, Signal::SIGTSTP, Signal::SIGQUIT, Signal::SIGHUP] {
+            if self.command(Condition::Signal(*signal)).is_none() {
+                self.reset_disposition(*signal)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn interactive(&self) -> bool {
+        self.interactive
+    }
+
+    pub fn command(&self, condition: Condition) -> Option<&str> {
+        self.commands
+            .iter()
+            .find(|&&(c, _)| c == condition)
+            .map(|&(_, ref command)| command.as_ref())
+    }
+
+    /// Sets the trap for `condition`; an empty command ignores the signal. `None` goes back to
+    /// the shell's default behavior.
+    pub fn set(&mut self, condition: Condition, command: Option<String>) -> nix::Result<()> {
+        if let Condition::Signal(signal) = condition {
+            match command {
+                Some(ref command) if command.len() == 0 => {
+                    set_handler(signal, nix::sys::signal::SigHandler::SigIgn)?;
+                    IGNORED.fetch_or(1 << signal as i32, Ordering::SeqCst);
+                }
+                Some(_) => {
+                    set_handler(signal, nix::sys::signal::SigHandler::Handler(handle_trapped_signal))?;
+                    IGNORED.fetch_and(!(1 << signal as i32), Ordering::SeqCst);
+                }
+                None => self.reset_disposition(signal)?,
+            }
+        }
+        self.commands.retain(|&(c, _)| c != condition);
+        if let Some(command) = command {
+            self.commands.push((condition, command));
+        }
+        Ok(())
+    }
+
+    fn reset_disposition(&self, signal: Signal) -> nix::Result<()> {
+        IGNORED.fetch_and(!(1 << signal as i32), Ordering::SeqCst);
+        match signal {
+            Signal::SIGINT | Signal::SIGTSTP | Signal::SIGQUIT if self.interactive => set_handler(signal, nix::sys::signal::SigHandler::SigIgn),
+            Signal::SIGHUP if self.interactive => set_handler(signal, nix::sys::signal::SigHandler::Handler(handle_hangup)),
+            // the shell itself reports failed writes instead of dying
+            Signal::SIGPIPE => set_handler(signal, nix::sys::signal::SigHandler::SigIgn),
+            _ => set_handler(signal, inherited_handler(signal)),
+        }
+    }
+
+    /// Takes the commands of the traps whose signals arrived since the last call, in signal
+    /// number order.
+    pub fn take_pending(&self) -> Vec<String> {
+        let pending = PENDING.swap(0, Ordering::SeqCst);
+        if pending == 0 {
+            return Vec::new();
+        }
+        Signal::iterator()
+            .filter(|signal| pending & (1 << *signal as i32) != 0)
+            .filter_map(|signal| self.command(Condition::Signal(signal)).map(String::from))
+            .filter(|command| command.len() > 0)
+            .collect()
+    }
+
+    /// Prints traps the way they are set, e.g. `trap -- 'echo bye' EXIT`.
+    pub fn print(&self, conditions: &[Condition]) {
+        for &(condition, ref command) in &self.commands {
+            if conditions.len() == 0 || conditions.contains(&condition) {
+                println!("trap -- '{}' {}", command.replace("'", "'\\''"), condition);
+            }
+        }
+    }
+}
+
+fn set_handler(signal: Signal, handler: nix::sys::signal::SigHandler) -> nix::Result<()> {
+    changed_disposition(signal);
+    let sigaction = nix::sys::signal::SigAction::new(handler, nix::sys::signal::SA_RESTART, nix::sys::signal::SigSet::empty());
+    unsafe { nix::sys::signal::sigaction(signal, &sigaction).map(|_| ()) }
+}
+
+/// Notes that the shell installed its own disposition for `signal`, which child processes
+/// must not keep.
+pub fn changed_disposition(signal: Signal) {
+    CHANGED.fetch_or(1 << signal as i32, Ordering::SeqCst);
+}
+
+fn record_inherited_dispositions() {
+    // the Rust runtime ignores SIGPIPE before `main`, so what the shell inherited is lost;
+    // commands get the default, which lets `yes | head -1` end quietly
+    changed_disposition(Signal::SIGPIPE);
+    let mut ignored = 0;
+    for signal in Signal::iterator().filter(|&signal| signal != Signal::SIGPIPE) {
+        let mut current: nix::libc::sigaction = unsafe { mem::zeroed() };
+        // a null action only reads the current one
+        if unsafe { nix::libc::sigaction(signal as nix::libc::c_int, ptr::null(), &mut current) } == 0 && current.sa_sigaction == nix::libc::SIG_IGN {
+            ignored |= 1 << signal as i32;
+        }
+    }
+    IGNORED_ON_ENTRY.store(ignored, Ordering::SeqCst);
+}
+
+/// The disposition `signal` had when the shell started: ignored, or the default.
+fn inherited_handler(signal: Signal) -> nix::sys::signal::SigHandler {
+    if IGNORED_ON_ENTRY.load(Ordering::SeqCst) & (1 << signal as i32) != 0 {
+        nix::sys::signal::SigHandler::SigIgn
+    } else {
+        nix::sys::signal::SigHandler::SigDfl
+    }
+}
+
+/// Gives a freshly forked child back the dispositions the shell inherited for the signals it
+/// changed, except the ones ignored with `trap ''`, which stay ignored as POSIX requires. Signals
+/// the shell never touched are left alone, so that those ignored by `nohup` stay ignored.
+pub fn reset_for_child() {
+    let changed = CHANGED.load(Ordering::SeqCst) & !IGNORED.load(Ordering::SeqCst);
+    for signal in Signal::iterator() {
+        if changed & (1 << signal as i32) != 0 {
+            let _ = set_handler(signal, inherited_handler(signal));
+        }
+    }
+}
+
+impl JobManager {
+    pub fn trap_builtin(&mut self, args: &[String]) -> i8 {
+        let (command, specs) = match args.first().map(|a| a.as_ref()) {
+            None => {
+                self.traps.print(&[]);
+                return 0;
+            }
+            Some("-l") => {
+                for line in signals::list() {
+                    println!("{}", line);
+                }
+                return 0;
+            }
+            Some("-p") => {
+                let mut conditions = Vec::new();
+                for spec in &args[1..] {
+                    match Condition::parse(spec) {
+                        Some(condition) => conditions.push(condition),
+                        None => {
+                            eprintln!("trap: {}: invalid signal specification", spec);
+                            return 1;
+                        }
+                    }
+                }
+                if conditions.len() > 0 || args.len() == 1 {
+                    self.traps.print(&conditions);
+                }
+                return 0;
+            }
+            Some("-") => (None, &args[1..]),
+            Some("--") if args.len() > 1 => (Some(args[1].clone()), &args[2..]),
+            // a lone signal, or a number first, resets instead of setting a command
+            Some(first) if args.len() == 1 || first.parse::<u32>().is_ok() => (None, &args[..]),
+            Some(first) => (Some(first.to_owned()), &args[1..]),
+        };
+        if specs.len() == 0 {
+            eprintln!("trap: usage: trap [-lp] [[command] signal_spec ...]");
+            return 2;
+        }
+        let mut result = 0;
+        for spec in specs {
+            let condition = match Condition::parse(spec) {
+                Some(Condition::Signal(nix::sys::signal::Signal::SIGCHLD)) => {
+                    eprintln!("trap: {}: used by the shell to track jobs", spec);
+                    result = 1;
+                    continue;
+                }
+                Some(condition) => condition,
+                None => {
+                    eprintln!("trap: {}: invalid signal specification", spec);
+                    result = 1;
+                    continue;
+                }
+            };
+            if let Err(e) = self.traps.set(condition, command.clone()) {
+                eprintln!("trap: {}: {}", spec, e);
+                result = 1;
+            }
+        }
+        result
+    }
+}
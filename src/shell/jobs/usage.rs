@@ -0,0 +1,178 @@
+use nix;
+use nix::libc;
+use nix::sys::signal::Signal;
+use nix::sys::wait::{WaitPidFlag, WaitStatus};
+use nix::unistd::Pid;
+use std::mem;
+use std::ops::Add;
+use std::time::Duration;
+
+/// What the `time` keyword prints when `TIMEFORMAT` is not set.
+pub const DEFAULT_FORMAT: &str = "\nreal\t%3lR\nuser\t%3lU\nsys\t%3lS\nmaxrss\t%MKB";
+
+/// CPU time and peak memory of the processes of a job.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Usage {
+    pub user: Duration,
+    pub system: Duration,
+    /// Largest resident set size of any of the processes, in kilobytes.
+    pub max_rss: u64,
+}
+
+impl Usage {
+    fn from_rusage(rusage: &libc::rusage) -> Usage {
+        let duration = |time: &libc::timeval| Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000);
+        // macOS reports bytes where everyone else reports kilobytes
+        let max_rss = if cfg!(target_os = "macos") {
+            rusage.ru_maxrss as u64 / 1024
+        } else {
+            rusage.ru_maxrss as u64
+        };
+        Usage {
+            user: duration(&rusage.ru_utime),
+            system: duration(&rusage.ru_stime),
+            max_rss: max_rss,
+        }
+    }
+}
+
+impl Add for Usage {
+    type Output = Usage;
+
+    /// Combines the usage of the stages of a pipeline: CPU times add up, while the peak memory
+    /// is that of the largest process.
+    fn add(self, other: Usage) -> Usage {
+        Usage {
+            user: self.user + other.user,
+            system: self.system + other.system,
+            max_rss: self.max_rss.max(other.max_rss),
+        }
+    }
+}
+
+/// Like `waitpid`, but also returns the resource usage of the process once it has terminated.
+pub fn wait4(pid: Pid, flags: Option<WaitPidFlag>) -> nix::Result<(WaitStatus, Usage)> {
+    let mut status: libc::c_int = 0;
+    let mut rusage: libc::rusage = unsafe { mem::zeroed() };
+    let options = flags.map(|f| f.bits()).unwrap_or(0);
+    let result = unsafe { libc::wait4(libc::pid_t::from(pid), &mut status, options, &mut rusage) };
+    match nix::Errno::result(result)? {
+        0 => Ok((WaitStatus::StillAlive, Usage::default())),
+        child => {
+            let status = decode(Pid::from_raw(child), status);
+            match status {
+                WaitStatus::Exited(_, _) | WaitStatus::Signaled(_, _, _) => Ok((status, Usage::from_rusage(&rusage))),
+                _ => Ok((status, Usage::default())),
+            }
+        }
+    }
+}
+
+/// Decodes a raw wait status the way the `W*` macros of `<sys/wait.h>` do. `Signal` has no
+/// room for the realtime signals, so a death by one of them is reported as the status the
+/// shell gives it anyway, 128 plus the signal number.
+fn decode(pid: Pid, status: libc::c_int) -> WaitStatus {
+    let signal = status & 0x7f;
+    if signal == 0 {
+        WaitStatus::Exited(pid, ((status >> 8) & 0xff) as i8)
+    } else if status == 0xffff {
+        WaitStatus::Continued(pid)
+    } else if signal == 0x7f {
+        // only the stop signals stop a process that is not being traced
+        WaitStatus::Stopped(pid, Signal::from_c_int((status >> 8) & 0xff).unwrap_or(Signal::SIGSTOP))
+    } else {
+        match Signal::from_c_int(signal) {
+            Ok(known) => WaitStatus::Signaled(pid, known, status & 0x80 != 0),
+            Err(_) => WaitStatus::Exited(pid, (128 + signal) as i8),
+        }
+    }
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1_000_000_000.0
+}
+
+/// Formats a duration in seconds with `precision` decimals, or as minutes and seconds such
+/// as `1m2.345s` if `long` is set.
+fn format_duration(duration: Duration, precision: usize, long: bool) -> String {
+    let total = seconds(duration);
+    if long {
+        let minutes = (total / 60.0).floor();
+        format!("{}m{:.*}s", minutes, precision, total - minutes * 60.0)
+    } else {
+        format!("{:.*}", precision, total)
+    }
+}
+
+/// Expands a `TIMEFORMAT` string. `%[p][l]R`, `%[p][l]U` and `%[p][l]S` are the elapsed, user
+/// and system time with `p` decimals (3 by default), in minutes and seconds with `l`; `%P` is
+/// the CPU percentage, `%M` the peak resident set size in kilobytes and `%%` a percent sign.
+pub fn format(format: &str, wall: Duration, usage: &Usage) -> String {
+    let mut result = String::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
+            continue;
+        }
+        let mut precision = 3;
+        let mut long = false;
+        if let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+            precision = digit as usize;
+            chars.next();
+        }
+        if chars.peek() == Some(&'l') {
+            long = true;
+            chars.next();
+        }
+        match chars.next() {
+            Some('R') => result.push_str(&format_duration(wall, precision, long)),
+            Some('U') => result.push_str(&format_duration(usage.user, precision, long)),
+            Some('S') => result.push_str(&format_duration(usage.system, precision, long)),
+            Some('P') => {
+                let cpu = seconds(usage.user + usage.system);
+                let percent = if seconds(wall) > 0.0 { cpu * 100.0 / seconds(wall) } else { 0.0 };
+                result.push_str(&format!("{:.2}", percent));
+            }
+            Some('M') => result.push_str(&usage.max_rss.to_string()),
+            Some('%') => result.push('%'),
+            Some(other) => {
+                result.push('%');
+                result.push(other);
+            }
+            None => result.push('%'),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_exit() {
+        let pid = Pid::from_raw(42);
+        assert_eq!(decode(pid, 0), WaitStatus::Exited(pid, 0));
+        assert_eq!(decode(pid, 3 << 8), WaitStatus::Exited(pid, 3));
+        assert_eq!(decode(pid, 255 << 8), WaitStatus::Exited(pid, -1));
+    }
+
+    #[test]
+    fn test_decode_signals() {
+        let pid = Pid::from_raw(42);
+        assert_eq!(decode(pid, libc::SIGTERM), WaitStatus::Signaled(pid, Signal::SIGTERM, false));
+        assert_eq!(decode(pid, libc::SIGSEGV | 0x80), WaitStatus::Signaled(pid, Signal::SIGSEGV, true));
+        assert_eq!(decode(pid, (libc::SIGTSTP << 8) | 0x7f), WaitStatus::Stopped(pid, Signal::SIGTSTP));
+        assert_eq!(decode(pid, 0xffff), WaitStatus::Continued(pid));
+    }
+
+    #[test]
+    fn test_decode_realtime_signals() {
+        let pid = Pid::from_raw(42);
+        // SIGRTMIN + 2 on Linux
+        let realtime = 36;
+        assert_eq!(decode(pid, realtime), WaitStatus::Exited(pid, (128 + realtime) as i8));
+        assert_eq!(decode(pid, (realtime << 8) | 0x7f), WaitStatus::Stopped(pid, Signal::SIGSTOP));
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+use std::mem;
+use std::time::Duration;
+
+/// Shell variables that cannot live in the environment: the exit status of the last
+/// foreground job, the positional parameters and arrays such as `PIPESTATUS`.
+pub struct Variables {
+    arrays: HashMap<String, Vec<String>>,
+    /// `${1}`, `${2}` and so on, set by `source file args`.
+    positional: Vec<String>,
+    pub last_status: i8,
+    /// How long the last foreground job ran, shown as `CMD_DURATION` in milliseconds.
+    pub last_duration: Option<Duration>,
+}
+
+impl Variables {
+    pub fn new() -> Self {
+        Variables {
+            arrays: HashMap::new(),
+            positional: Vec::new(),
+            last_status: 0,
+            last_duration: None,
+        }
+    }
+
+    pub fn set_array(&mut self, name: &str, values: Vec<String>) {
+        self.arrays.insert(name.to_owned(), values);
+    }
+
+    /// Drops an array, for when its name is given a plain value that it would otherwise hide.
+    pub fn remove_array(&mut self, name: &str) {
+        self.arrays.remove(name);
+    }
+
+    /// Sets new positional parameters, returning the old ones.
+    pub fn replace_positional(&mut self, values: Vec<String>) -> Vec<String> {
+        mem::replace(&mut self.positional, values)
+    }
+
+    /// Looks up `?`, `CMD_DURATION`, the positional parameters with `#`, `@` and `*`, `NAME[index]`, `NAME[@]`, `NAME[*]` or `#NAME[@]` (the number of
+    /// elements). A bare array name stands for its first element, as in bash.
+    pub fn get(&self, name: &str) -> Option<String> {
+        if name == "?" {
+            return Some((self.last_status as u8).to_string());
+        }
+        if name == "#" {
+            return Some(self.positional.len().to_string());
+        }
+        if name == "@" || name == "*" {
+            return Some(self.positional.join(" "));
+        }
+        if let Ok(index) = name.parse::<usize>() {
+            return match index {
+                0 => Some(String::from("radish")),
+                index => Some(self.positional.get(index - 1).cloned().unwrap_or(String::new())),
+            };
+        }
+        if name == "CMD_DURATION" {
+            return self.last_duration
+                .map(|d| (d.as_secs() * 1000 + u64::from(d.subsec_nanos()) / 1_000_000).to_string());
+        }
+        let (count, name) = if name.starts_with('#') { (true, &name[1..]) } else { (false, name) };
+        let (base, subscript) = match name.find('[') {
+            Some(index) if name.ends_with(']') => (&name[..index], Some(&name[index + 1..name.len() - 1])),
+            _ => (name, None),
+        };
+        let values = self.arrays.get(base)?;
+        match subscript {
+            Some("@") | Some("*") => if count {
+                Some(values.len().to_string())
+            } else {
+                Some(values.join(" "))
+            },
+            Some(index) => match index.parse::<usize>() {
+                Ok(index) => Some(values.get(index).cloned().unwrap_or(String::new())),
+                Err(_) => None,
+            },
+            None => Some(values.get(0).cloned().unwrap_or(String::new())),
+        }
+    }
+}
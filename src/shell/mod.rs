@@ -2,13 +2,24 @@ mod readline;
 use self::readline::Readline;
 mod syntax;
 mod jobs;
-use self::jobs::JobManager;
+use self::jobs::{Condition, JobManager};
 mod completion;
 use self::completion::Completer;
 mod history;
 use self::history::History;
+mod check;
+mod options;
+use self::options::{Mode, Options};
 use nom;
 use nix;
+use std::env;
+use std::ffi::CString;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
 use std::io::stdin;
 use std::os::unix::io::AsRawFd;
 extern crate rlua;
@@ -18,6 +29,7 @@ pub struct Shell {}
 
 struct TerminalFgGroupManager {
     stdin_group: nix::unistd::Pid,
+    modes: Option<nix::sys::termios::Termios>,
 }
 
 impl TerminalFgGroupManager {
@@ -26,6 +38,7 @@ impl TerminalFgGroupManager {
             Ok(stdin_group) => {
                 let t = TerminalFgGroupManager {
                     stdin_group: stdin_group,
+                    modes: nix::sys::termios::tcgetattr(stdin().as_raw_fd()).ok(),
                 };
                 nix::unistd::tcsetpgrp(stdin().as_raw_fd(), group).expect("failed to tcsetpgrp stdin");
                 Some(t)
@@ -33,10 +46,21 @@ impl TerminalFgGroupManager {
             Err(_) => None,
         }
     }
+
+    /// Puts the terminal back into the modes it was in when the shell started, in case a job
+    /// changed them and did not clean up.
+    pub fn restore_modes(&self) {
+        if let Some(ref modes) = self.modes {
+            let _ = nix::sys::termios::tcsetattr(stdin().as_raw_fd(), nix::sys::termios::SetArg::TCSADRAIN, modes);
+        }
+    }
 }
+
 impl Drop for TerminalFgGroupManager {
     fn drop(&mut self) {
-        nix::unistd::tcsetpgrp(stdin().as_raw_fd(), self.stdin_group).expect("failed to reset tcsetpgrp");
+        self.restore_modes();
+        // this fails if the terminal hung up, and then there is nothing to give back
+        let _ = nix::unistd::tcsetpgrp(stdin().as_raw_fd(), self.stdin_group);
     }
 }
 
@@ -67,89 +91,290 @@ impl Drop for ProcessGroupManager {
 
 impl Shell {
     pub fn run() -> i8 {
+        let options = match Options::parse(env::args().skip(1)) {
+            Ok(options) => options,
+            Err(error) => {
+                eprintln!("radish: {}", error);
+                return 2;
+            }
+        };
+        match options.mode {
+            Mode::Check(ref files, format) => return check::run(files, format),
+            Mode::Script(ref path) => return Shell::run_script(path, &mut JobManager::new()),
+            Mode::Interactive => {}
+        }
         // Set up subsystems
-        let mut history = History::new("history.sqlite").expect("failed to open history file");
-        let mut lua = Lua::new();
+        let mut history = {
+            let _reservation = jobs::UserFdReservation::new();
+            History::new("history.sqlite").expect("failed to open history file")
+        };
+        let lua = Rc::new(Lua::new());
+        if let Some(path) = options.lua_config() {
+            Shell::load_lua_config(&lua, &path);
+        }
+        let mut job_manager = JobManager::new();
+        job_manager.set_line_runner(Shell::run_line);
+        job_manager.set_lua(lua.clone());
+        job_manager.set_abbreviations(history.abbreviations());
         let mut completion_engines: Vec<Box<completion::engines::Engine>> = vec![
             Box::new(completion::engines::PathCompletion::new()),
             Box::new(completion::engines::UserCompletion::new(&lua, "name")),
+            Box::new(completion::engines::AliasCompletion::new(job_manager.aliases())),
         ];
         let mut completer = Completer::new(completion_engines);
-        let mut job_manager = JobManager::new();
         let mut readline = Readline::new();
         Shell::run_interactive(
             &mut history,
             &mut completer,
             &mut job_manager,
             &mut readline,
+            &options.startup_files(),
+            options.rcfile.is_some(),
         )
     }
 
-    fn run_interactive<'a, 'b: 'a>(mut history: &'a mut History, mut completer: &'a mut Completer<'b>, mut job_manager: &mut JobManager, mut readline: &mut Readline) -> i8 {
+    fn run_interactive<'a, 'b: 'a>(mut history: &'a mut History, mut completer: &'a mut Completer<'b>, mut job_manager: &mut JobManager, mut readline: &mut Readline, startup_files: &[PathBuf], rcfile: bool) -> i8 {
         let pid = nix::unistd::getpid();
-        let _process_group_manager = ProcessGroupManager::new(pid).expect("failed to set process group");
-        let _terminal_group_manager = TerminalFgGroupManager::new(pid).expect("failed to set terminal process group");
-        let block_sigaction = nix::sys::signal::SigAction::new(
-            nix::sys::signal::SigHandler::SigIgn,
-            nix::sys::signal::SaFlags::empty(),
-            nix::sys::signal::SigSet::empty(),
-        );
-        unsafe {
-            nix::sys::signal::sigaction(nix::sys::signal::Signal::SIGINT, &block_sigaction).expect("failed to ignore SIGINT");
-            nix::sys::signal::sigaction(nix::sys::signal::Signal::SIGTSTP, &block_sigaction).expect("failed to ignore SIGSTP");
-            nix::sys::signal::sigaction(nix::sys::signal::Signal::SIGQUIT, &block_sigaction).expect("failed to ignore SIGQUIT");
-        }
-        job_manager.start_background_reaper();
+        let process_group_manager = ProcessGroupManager::new(pid).expect("failed to set process group");
+        let terminal_group_manager = TerminalFgGroupManager::new(pid).expect("failed to set terminal process group");
+        job_manager.set_interactive(true).expect("failed to ignore keyboard signals");
+        readline.set_reaper(job_manager.start_reaper().expect("failed to install SIGCHLD handler"));
+        readline.set_directories(job_manager.directories());
+        for path in startup_files {
+            Shell::run_startup_file(&mut job_manager, path, rcfile);
+        }
         let result;
+        let mut exec_command = None;
         loop {
+            // checked before reading a line, so that `exit` in a startup file is honoured too
+            if let Some(status) = job_manager.exit_status() {
+                result = status;
+                break;
+            }
+            if let Some(command) = job_manager.take_exec_command() {
+                exec_command = Some(command);
+                result = 0;
+                break;
+            }
+            if jobs::hangup_received() {
+                result = (128 + nix::libc::SIGHUP) as i8;
+                break;
+            }
+            Shell::run_pending_traps(&mut job_manager);
+            job_manager.report_notifications();
             let input = readline.read(&mut completer, &history);
             match input {
                 Some(command) => {
-                    let trimmed = command.trim();
-                    history
-                        .add_command(&trimmed)
-                        .expect("failed to add command to history");
-                    match syntax::lexer::lex(&trimmed) {
-                        nom::IResult::Done(remaining, tokens) => {
-                            if remaining.len() == 0 {
-                                //println!("lexed: {:?}", tokens);
-                                match syntax::parser::parse(&tokens) {
-                                    Ok(expr) => match job_manager.enqueue_job_from_expr(&expr) {
-                                        Ok(()) => {
-                                            job_manager
-                                                .run_foreground_jobs()
-                                                .expect("failed to run foreground jobs");
-                                        }
-                                        Err(error) => {
-                                            println!("error when constructing job: {:?}", error);
-                                        }
-                                    },
-                                    Err(error) => {
-                                        println!("syntax error: {:?}", error);
-                                    }
-                                }
-                            } else {
-                                println!("syntax error: extraneous characters `{}`", remaining);
+                    let trimmed = match history::expansion::expand_history(command.trim(), history) {
+                        Ok(expanded) => {
+                            if expanded != command.trim() {
+                                println!("{}", expanded);
                             }
+                            expanded
                         }
-                        nom::IResult::Error(error) => {
-                            println!("lex error: {:?}", error);
-                        }
-                        nom::IResult::Incomplete(nom::Needed::Unknown) => {
-                            println!("lex error: incomplete input");
+                        Err(error) => {
+                            eprintln!("radish: {}", error);
+                            continue;
                         }
-                        nom::IResult::Incomplete(nom::Needed::Size(remaining)) => {
-                            println!("lex error: incomplete input, remaining: {}", remaining);
-                        }
-                    }
+                    };
+                    history
+                        .add_command(&trimmed)
+                        .expect("failed to add command to history");
+                    job_manager.count_command_line();
+                    Shell::run_line(&mut job_manager, &trimmed);
+                    terminal_group_manager.restore_modes();
                 }
                 None => {
-                    result = 0;
+                    result = if jobs::hangup_received() { (128 + nix::libc::SIGHUP) as i8 } else { 0 };
                     break;
                 }
             }
         }
+        if let Some((path, args)) = exec_command {
+            // give the terminal and process group back the way they were before the shell started
+            drop(terminal_group_manager);
+            drop(process_group_manager);
+            return Shell::exec(&path, &args);
+        }
+        Shell::run_trap(&mut job_manager, Condition::Exit);
+        job_manager.hang_up_jobs();
         // we don't need to stop the reaper because it's not owned by the job manager, and we're exiting anyway
         result
     }
+
+    /// Runs a command line together with the `DEBUG` and `ERR` traps, then the traps of any
+    /// signals that arrived in the meantime.
+    fn run_line(job_manager: &mut JobManager, line: &str) {
+        Shell::run_trap(job_manager, Condition::Debug);
+        Shell::execute(job_manager, line);
+        if job_manager.last_status() != 0 {
+            Shell::run_trap(job_manager, Condition::Err);
+        }
+        Shell::run_pending_traps(job_manager);
+    }
+
+    fn run_trap(job_manager: &mut JobManager, condition: Condition) {
+        if let Some(command) = job_manager.trap_command(condition) {
+            Shell::run_trap_command(job_manager, &command);
+        }
+    }
+
+    fn run_pending_traps(job_manager: &mut JobManager) {
+        for command in job_manager.pending_traps() {
+            Shell::run_trap_command(job_manager, &command);
+        }
+    }
+
+    /// Runs the command of a trap without letting it change `$?`.
+    fn run_trap_command(job_manager: &mut JobManager, command: &str) {
+        let status = job_manager.last_status();
+        Shell::execute(job_manager, command);
+        job_manager.set_last_status(status);
+    }
+
+    /// Expands aliases in a command line, parses it and runs the jobs it describes.
+    fn execute(job_manager: &mut JobManager, line: &str) {
+        let expanded = syntax::alias::expand_aliases(line, &job_manager.aliases().read().unwrap());
+        match syntax::lexer::lex(&expanded) {
+            nom::IResult::Done(remaining, tokens) => {
+                if remaining.len() == 0 {
+                    //println!("lexed: {:?}", tokens);
+                    let (timed, tokens) = syntax::parser::split_time_keyword(&tokens);
+                    if timed && tokens.len() == 0 {
+                        job_manager.print_times(Duration::from_secs(0), &Default::default());
+                        return;
+                    }
+                    if let Some(condition) = syntax::parser::split_conditional(tokens) {
+                        let status = job_manager.conditional_command(condition);
+                        job_manager.set_last_status(status);
+                        return;
+                    }
+                    match syntax::parser::parse(tokens) {
+                        Ok(expr) => match job_manager.enqueue_job_from_expr(&expr, line) {
+                            Ok(()) => {
+                                job_manager.set_timed(timed);
+                                job_manager
+                                    .run_foreground_jobs()
+                                    .expect("failed to run foreground jobs");
+                                job_manager.set_timed(false);
+                            }
+                            Err(error) => match error.missing_command() {
+                                Some((name, args)) => Shell::command_not_found(job_manager, &name.to_string_lossy(), args),
+                                None => println!("error when constructing job: {:?}", error),
+                            },
+                        },
+                        Err(error) => {
+                            println!("syntax error: {:?}", error);
+                        }
+                    }
+                } else {
+                    println!("syntax error: extraneous characters `{}`", remaining);
+                }
+            }
+            nom::IResult::Error(error) => {
+                println!("lex error: {:?}", error);
+            }
+            nom::IResult::Incomplete(nom::Needed::Unknown) => {
+                println!("lex error: incomplete input");
+            }
+            nom::IResult::Incomplete(nom::Needed::Size(remaining)) => {
+                println!("lex error: incomplete input, remaining: {}", remaining);
+            }
+        }
+    }
+
+    /// Lets the `COMMAND_NOT_FOUND` trap or the Lua hook deal with a command that was not
+    /// found; without either the job manager reports it.
+    fn command_not_found(job_manager: &mut JobManager, name: &str, args: &[String]) {
+        match job_manager.command_not_found(name, args) {
+            jobs::NotFound::Trap(command) => {
+                let mut words = vec![name.to_owned()];
+                words.extend(args.iter().cloned());
+                job_manager.run_not_found_hook(Some(words), |job_manager| Shell::execute(job_manager, &command));
+            }
+            jobs::NotFound::Replace(line) => job_manager.run_not_found_hook(None, |job_manager| Shell::execute(job_manager, &line)),
+            jobs::NotFound::Done => {}
+        }
+    }
+
+    /// Sources a startup file. Missing files are skipped unless given with `--rcfile`.
+    fn run_startup_file(job_manager: &mut JobManager, path: &Path, rcfile: bool) {
+        match jobs::Script::read(&path.to_string_lossy()) {
+            Ok(script) => {
+                let status = job_manager.run_source(script);
+                job_manager.set_last_status(status);
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => if rcfile {
+                eprintln!("radish: {}: No such file or directory", path.display());
+            },
+            Err(e) => eprintln!("radish: {}: {}", path.display(), e),
+        }
+    }
+
+    /// Runs `config.lua` in the shell's Lua state, if there is one.
+    fn load_lua_config(lua: &Lua, path: &Path) {
+        let mut source = String::new();
+        match File::open(path).and_then(|mut f| f.read_to_string(&mut source)) {
+            Ok(_) => if let Err(e) = lua.exec::<()>(&source, path.to_str()) {
+                eprintln!("radish: {}: {}", path.display(), e);
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => eprintln!("radish: {}: {}", path.display(), e),
+        }
+    }
+
+    /// Runs a script line by line with the signal dispositions of a non-interactive shell.
+    fn run_script(path: &str, mut job_manager: &mut JobManager) -> i8 {
+        let mut script = String::new();
+        if let Err(e) = File::open(path).and_then(|mut f| f.read_to_string(&mut script)) {
+            eprintln!("radish: {}: {}", path, e);
+            return 127;
+        }
+        job_manager.set_interactive(false).expect("failed to set signal dispositions");
+        job_manager.set_line_runner(Shell::run_line);
+        for line in script.lines() {
+            let line = line.trim();
+            if line.len() == 0 || line.starts_with('#') {
+                continue;
+            }
+            Shell::run_line(&mut job_manager, line);
+            if job_manager.exit_status().is_some() {
+                break;
+            }
+            if let Some((path, args)) = job_manager.take_exec_command() {
+                return Shell::exec(&path, &args);
+            }
+        }
+        Shell::run_trap(&mut job_manager, Condition::Exit);
+        job_manager.exit_status().unwrap_or(job_manager.last_status())
+    }
+
+    /// Replaces the shell with the command given to `exec`, returning only if that fails.
+    fn exec(path: &Path, args: &[String]) -> i8 {
+        let path_cstring = match path.to_str().and_then(|p| CString::new(p).ok()) {
+            Some(path_cstring) => path_cstring,
+            None => {
+                eprintln!("exec: {}: invalid path", path.display());
+                return 126;
+            }
+        };
+        let mut args_cstring = Vec::new();
+        for arg in args {
+            match CString::new(arg.clone()) {
+                Ok(arg_cstring) => args_cstring.push(arg_cstring),
+                Err(_) => {
+                    eprintln!("exec: {}: invalid argument", arg);
+                    return 126;
+                }
+            }
+        }
+        jobs::reset_for_child();
+        match nix::unistd::execv(&path_cstring, &args_cstring) {
+            Ok(_) => 0,
+            Err(e) => {
+                eprintln!("exec: {}: {}", path.display(), e);
+                126
+            }
+        }
+    }
 }
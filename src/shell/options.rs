@@ -0,0 +1,95 @@
+use super::check;
+use std::env;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Mode {
+    Interactive,
+    Script(String),
+    Check(Vec<String>, check::Format),
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub mode: Mode,
+    /// `--norc`: read no startup files at all.
+    pub norc: bool,
+    /// `--rcfile`: a script to read at startup instead of the usual ones.
+    pub rcfile: Option<String>,
+}
+
+/// `$XDG_CONFIG_HOME/radish`, or `~/.config/radish` when that is not set.
+fn config_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(ref dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("radish")),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("radish")),
+    }
+}
+
+impl Options {
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+        let mut check_mode = false;
+        let mut format = check::Format::Text;
+        let mut norc = false;
+        let mut rcfile = None;
+        let mut files = Vec::new();
+        while let Some(arg) = args.next() {
+            match arg.as_ref() {
+                "--check" => check_mode = true,
+                "--json" => format = check::Format::Json,
+                "--norc" => norc = true,
+                "--rcfile" => match args.next() {
+                    Some(file) => rcfile = Some(file),
+                    None => return Err(String::from("--rcfile requires a file")),
+                },
+                _ => {
+                    if arg.starts_with("-") {
+                        return Err(format!("unknown option `{}`", arg));
+                    }
+                    files.push(arg);
+                }
+            }
+        }
+        let mode = if check_mode {
+            if files.len() == 0 {
+                return Err(String::from("--check requires at least one file"));
+            }
+            Mode::Check(files, format)
+        } else if format == check::Format::Json {
+            return Err(String::from("--json is only valid with --check"));
+        } else if files.len() > 1 {
+            return Err(String::from("passing arguments to scripts is not supported yet"));
+        } else if files.len() == 1 {
+            Mode::Script(files.remove(0))
+        } else {
+            Mode::Interactive
+        };
+        Ok(Options {
+            mode: mode,
+            norc: norc,
+            rcfile: rcfile,
+        })
+    }
+
+    /// The scripts an interactive shell runs before the first prompt: `/etc/radishrc` and then
+    /// `config.rsh` in the config directory, or only the file given to `--rcfile`.
+    pub fn startup_files(&self) -> Vec<PathBuf> {
+        if self.norc {
+            return Vec::new();
+        }
+        if let Some(ref rcfile) = self.rcfile {
+            return vec![PathBuf::from(rcfile)];
+        }
+        let mut files = vec![PathBuf::from("/etc/radishrc")];
+        files.extend(config_dir().map(|dir| dir.join("config.rsh")));
+        files
+    }
+
+    /// The Lua file an interactive shell loads at startup, `config.lua` in the config directory.
+    pub fn lua_config(&self) -> Option<PathBuf> {
+        if self.norc {
+            return None;
+        }
+        config_dir().map(|dir| dir.join("config.lua"))
+    }
+}
@@ -9,16 +9,29 @@ use self::termion::event::Key;
 
 pub struct Editor<'a, 'b: 'a> {
     line_editor: LineEditor<'a, 'b>,
-    history: &'a History,
+    history: Option<&'a History>,
 }
 
 impl<'a, 'b: 'a> Editor<'a, 'b> {
     pub fn new(completer: &'a mut Completer<'b>, history: &'a History) -> Editor<'a, 'b> {
         Editor {
             line_editor: LineEditor::new(DisplayString::from("$ prompt "), completer, history),
-            history: history,
+            history: Some(history),
         }
     }
+
+    /// An editor for a line of input to a command such as `read`, with history and completion
+    /// turned off.
+    pub fn plain(prompt: &'a str, hidden: bool) -> Editor<'a, 'b> {
+        Editor {
+            line_editor: LineEditor::plain(DisplayString::from(prompt), hidden),
+            history: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.line_editor.buffer().len()
+    }
     pub fn handle_input(&mut self, key: Key) -> ReadlineEvent {
         self.line_editor.handle_input(key)
     }
@@ -134,9 +134,47 @@ impl<'a, 'b: 'a> Editor<'a, 'b> {
 
     // XXX: Returning a bool to indicate doneness is a bit awkward, maybe change it
     pub fn handle_newline(&mut self) -> ReadlineEvent {
+        self.expand_abbreviation();
         ReadlineEvent::Done
     }
 
+    /// Expands the abbreviation directly before the cursor, if the cursor is at the end of a word
+    /// in command position (the first word, or the first word after a pipe).
+    ///
+    /// Returns `true` if an abbreviation was expanded.
+    pub fn expand_abbreviation(&mut self) -> bool {
+        let abbreviations = match self.history.history_instance {
+            Some(h) => h.abbreviations(),
+            None => return false,
+        };
+        let (words, pos) = self.get_words_and_cursor_position();
+        let index = match pos {
+            CursorPosition::OnWordRightEdge(i) => i,
+            _ => return false,
+        };
+        let (start, end) = words[index];
+        let previous = if index > 0 {
+            let (prev_start, prev_end) = words[index - 1];
+            Some(cur_buf!(self).range(prev_start, prev_end))
+        } else {
+            None
+        };
+        let word = cur_buf!(self).range(start, end);
+        let expansion: Vec<char> = match abbreviations.read().unwrap().expand(&word, previous.as_ref().map(|p| p.as_str())) {
+            Some(e) => e.chars().collect(),
+            None => return false,
+        };
+        {
+            let buf = cur_buf_mut!(self);
+            buf.start_undo_group();
+            buf.remove(start, end);
+            buf.insert(start, &expansion);
+            buf.end_undo_group();
+        }
+        self.cursor = start + expansion.len();
+        true
+    }
+
     /// Attempts to undo an action on the current buffer.
     ///
     /// Returns `Ok(true)` if an action was undone.
@@ -106,6 +106,10 @@ impl<'a, 'b: 'a> KeyMap<'a, 'b, Emacs<'a, 'b>> for Emacs<'a, 'b> {
         }
 
         match key {
+            Key::Char(' ') => {
+                self.ed.expand_abbreviation();
+                self.ed.insert_after_cursor(' ')
+            }
             Key::Char(c) => self.ed.insert_after_cursor(c),
             Key::Alt(c) => self.handle_alt_key(c),
             Key::Ctrl(c) => self.handle_ctrl_key(c),
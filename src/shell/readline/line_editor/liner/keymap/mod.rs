@@ -28,7 +28,7 @@ pub trait KeyMap<'a, 'b: 'a, T>: From<T> {
             }
             Key::Char('\t') => self.editor_mut().complete(handler),
             Key::Char('\n') => {
-                return ReadlineEvent::Done;
+                return self.editor_mut().handle_newline();
             }
             Key::Ctrl('f') if self.editor().is_currently_showing_autosuggestion() => self.editor_mut().accept_autosuggestion(),
             Key::Right if self.editor().is_currently_showing_autosuggestion() && self.editor().cursor_is_at_end_of_line() => self.editor_mut().accept_autosuggestion(),
@@ -14,6 +14,10 @@ use super::ReadlineEvent;
 pub struct LineEditor<'a, 'b: 'a> {
     prompt: DisplayString<'a>,
     editor: liner::emacs::Emacs<'a, 'b>,
+    /// Set for input that is not a command line, which is shown without highlighting.
+    plain: bool,
+    /// Set when the typed characters must not be shown, as with `read -s`.
+    hidden: bool,
 }
 
 impl<'a, 'b: 'a> LineEditor<'a, 'b> {
@@ -21,6 +25,18 @@ impl<'a, 'b: 'a> LineEditor<'a, 'b> {
         LineEditor {
             prompt: prompt,
             editor: liner::emacs::Emacs::new(liner::Editor::new(Some(history), Some(completer))),
+            plain: false,
+            hidden: false,
+        }
+    }
+
+    /// An editor for input to a command, without history, completion or highlighting.
+    pub fn plain(prompt: DisplayString<'a>, hidden: bool) -> LineEditor<'a, 'b> {
+        LineEditor {
+            prompt: prompt,
+            editor: liner::emacs::Emacs::new(liner::Editor::new(None, None)),
+            plain: true,
+            hidden: hidden,
         }
     }
 
@@ -55,8 +71,13 @@ impl<'a, 'b: 'a> Render for LineEditor<'a, 'b> {
             }
             subs
         }
-        let buffer_string = self.buffer().iter().cloned().collect::<String>();
-        let (cursor_split_left, _) = buffer_string.split_at(self.cursor());
+        let buffer_string = if self.hidden {
+            String::new()
+        } else {
+            self.buffer().iter().cloned().collect::<String>()
+        };
+        let cursor = if self.hidden { 0 } else { self.cursor() };
+        let (cursor_split_left, _) = buffer_string.split_at(cursor);
         let graphemes: Vec<&str> = cursor_split_left.graphemes(true).collect();
         let cursor_position = graphemes.len() + self.prompt.width();
 
@@ -75,17 +96,28 @@ impl<'a, 'b: 'a> Render for LineEditor<'a, 'b> {
         if self.prompt.width() >= cursor_position {
             lines[0].columns[0].left.cursor = Some(cursor_position);
         }
+        // the first word is the command, shown in red when there is nothing to run by that
+        // name; words with quotes or expansions in them are only known once expanded
+        let command_start = buffer_string.len() - buffer_string.trim_start().len();
+        let command_end = buffer_string[command_start..]
+            .find(char::is_whitespace)
+            .map_or(buffer_string.len(), |end| command_start + end);
+        let command = &buffer_string[command_start..command_end];
+        let command_valid = command.is_empty() || command.contains(|c| "$`'\"\\{*?[=".contains(c))
+            || self.editor.editor().completer.as_ref().map_or(true, |completer| completer.is_command(command));
         let mut total_prev_length = 0;
-        for (index, word) in buffer_string.split_word_bounds().enumerate() {
+        for (start, word) in buffer_string.split_word_bound_indices() {
             for word in sub_strings(word, width) {
                 let total_length = lines.last().unwrap().width();
                 let total_line_length = total_prev_length + total_length;
                 let component;
-                if index == 0 {
+                if self.plain {
+                    component = DisplayStringComponent::from(word);
+                } else if start < command_end && !word.trim().is_empty() {
                     component = DisplayStringComponent::new(
                         word,
                         Color::new(
-                            color::Mode::Normal(color::Base::White),
+                            color::Mode::Normal(if command_valid { color::Base::White } else { color::Base::Red }),
                             color::Mode::Normal(color::Base::Reset),
                         ),
                         Style::BOLD,
@@ -12,10 +12,17 @@ use std::fmt;
 use std::cmp::max;
 use super::history::History;
 use super::completion::{Completer, Completions};
-use self::termion::input::TermRead;
+use super::jobs::{hangup_received, poll_timeout, Directories, Reaper};
+use self::termion::event::{Event, Key};
 use self::termion::raw::IntoRawMode;
+use std::os::unix::io::AsRawFd;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
-pub struct Readline {}
+pub struct Readline {
+    reaper: Option<Reaper>,
+    directories: Option<Arc<RwLock<Directories>>>,
+}
 
 #[derive(Debug)]
 pub enum ReadlineEvent {
@@ -23,20 +30,120 @@ pub enum ReadlineEvent {
     Done,
     Eof,
     Interrupted,
+    Hangup,
+    Timeout,
     HistorySearch,
     Continue,
     StartCompletionPager(Completions),
 }
 
+/// When a line of input for a command such as `read` is complete, besides on Enter.
+#[derive(Default)]
+pub struct LineOptions {
+    /// Finish once this many characters have been typed.
+    pub max_chars: Option<usize>,
+    /// Finish when this character is typed; it is not part of the line.
+    pub delimiter: Option<char>,
+    /// Give up waiting for input at this time.
+    pub deadline: Option<Instant>,
+}
+
 impl Readline {
     pub fn new() -> Readline {
-        Readline {}
+        Readline {
+            reaper: None,
+            directories: None,
+        }
+    }
+
+    /// Reaps background jobs while waiting for input, showing their notifications above the
+    /// prompt if `set -b` is on.
+    pub fn set_reaper(&mut self, reaper: Reaper) {
+        self.reaper = Some(reaper);
+    }
+
+    /// Lets Alt-Left and Alt-Right move through the directory history like `prevd` and `nextd`.
+    pub fn set_directories(&mut self, directories: Arc<RwLock<Directories>>) {
+        self.directories = Some(directories);
+    }
+
+    /// Waits for input on stdin for at most `timeout`, reaping background jobs if SIGCHLD
+    /// arrives in the meantime and signaling those whose `timeout` expires. Returns false if it
+    /// stopped waiting before there was any input.
+    fn poll_input(&self, timeout: Option<Duration>) -> bool {
+        if self.reaper.is_none() && timeout.is_none() {
+            return true;
+        }
+        let mut fds = vec![nix::poll::PollFd::new(stdin().as_raw_fd(), nix::poll::POLLIN)];
+        if let Some(ref reaper) = self.reaper {
+            fds.push(nix::poll::PollFd::new(reaper.fd(), nix::poll::POLLIN));
+        }
+        let timeout = match (timeout, self.reaper.as_ref().and_then(|reaper| reaper.next_timeout())) {
+            (Some(own), Some(jobs)) => Some(own.min(jobs)),
+            (own, jobs) => own.or(jobs),
+        };
+        match nix::poll::poll(&mut fds, poll_timeout(timeout)) {
+            Ok(0) => {
+                if let Some(ref reaper) = self.reaper {
+                    reaper.check_timeouts();
+                }
+                false
+            }
+            Ok(_) => {
+                if let Some(ref reaper) = self.reaper {
+                    if fds[1].revents().map(|events| events.contains(nix::poll::POLLIN)).unwrap_or(false) {
+                        reaper.reap();
+                    }
+                }
+                fds[0].revents().map(|events| !events.is_empty()).unwrap_or(false)
+            }
+            Err(nix::Error::Sys(nix::Errno::EINTR)) => false,
+            Err(_) => true,
+        }
+    }
+
+    /// Prints pending job notifications above the editor, returning true if the editor needs to
+    /// be drawn again.
+    fn show_notifications(&self, cursor: &mut CursorManager, term_buffer: &mut TerminalBuffer, stdout: &mut Write) -> bool {
+        let messages = match self.reaper {
+            Some(ref reaper) => {
+                let mut notifications = reaper.notifications().write().unwrap();
+                if !notifications.immediate {
+                    return false;
+                }
+                notifications.take()
+            }
+            None => return false,
+        };
+        print_above(&messages, cursor, term_buffer, stdout)
+    }
+
+    /// Moves through the directory history and shows the new working directory above the
+    /// editor, returning true if the editor needs to be drawn again.
+    fn switch_directory(&self, forward: bool, cursor: &mut CursorManager, term_buffer: &mut TerminalBuffer, stdout: &mut Write) -> bool {
+        let directories = match self.directories {
+            Some(ref directories) => directories,
+            None => return false,
+        };
+        let result = if forward {
+            directories.write().unwrap().next(1)
+        } else {
+            directories.write().unwrap().previous(1)
+        };
+        match result {
+            Ok(directory) => print_above(&[directory.display().to_string()], cursor, term_buffer, stdout),
+            Err(_) => false,
+        }
     }
 
     pub fn read<'a, 'b: 'a>(&mut self, completer: &'a mut Completer<'b>, history: &'a History) -> Option<String> {
         let result;
         loop {
-            let res = self.read_impl(completer, history);
+            let res = self.read_impl(&mut Editor::new(completer, history), &LineOptions::default());
+            if let Err(ReadlineEvent::Hangup) = res {
+                // the terminal is gone, so there is nothing left to write to
+                return None;
+            }
             println!("");
             match res {
                 Ok(string) => {
@@ -90,14 +197,31 @@ impl Readline {
         stdout.flush().expect("failed to flush stdout");
     }
 
-    fn read_impl<'a, 'b: 'a>(&mut self, completer: &'a mut Completer<'b>, history: &'a History) -> Result<String, ReadlineEvent> {
-        let mut editor = Editor::new(completer, history);
+    /// Reads a line of input for a command such as `read`, without history or completion. The
+    /// line is returned along with the event that ended it: `Done`, `Eof`, `Interrupted`,
+    /// `Timeout` or `Hangup`, so that what was typed before a timeout is not lost.
+    pub fn read_line(&mut self, prompt: &str, hidden: bool, options: &LineOptions) -> (String, ReadlineEvent) {
+        let mut editor = Editor::plain(prompt, hidden);
+        loop {
+            let res = self.read_impl(&mut editor, options);
+            if let Err(ReadlineEvent::Hangup) = res {
+                return (editor.buffer(), ReadlineEvent::Hangup);
+            }
+            println!("");
+            match res {
+                Ok(line) => return (line, ReadlineEvent::Done),
+                Err(ReadlineEvent::ClearScreen) => {}
+                Err(event) => return (editor.buffer(), event),
+            }
+        }
+    }
+
+    fn read_impl(&mut self, mut editor: &mut Editor, options: &LineOptions) -> Result<String, ReadlineEvent> {
         let mut term_buffer = TerminalBuffer::new();
         let mut stdout = stdout().into_raw_mode().expect("failed to set raw mode");
         let mut cursor = CursorManager::new();
         let (mut terminal_width, mut terminal_height) = termion::terminal_size().expect("failed to get terminal size");
         let mut result = Err(ReadlineEvent::Interrupted);
-        let stdin = stdin();
         self.render(
             &mut editor,
             &mut cursor,
@@ -105,10 +229,59 @@ impl Readline {
             terminal_width as usize,
             &mut stdout,
         );
-        for key in stdin.keys() {
+        loop {
+            let now = Instant::now();
+            let remaining = options.deadline.map(|deadline| if deadline > now { deadline - now } else { Duration::from_secs(0) });
+            if !self.poll_input(remaining) {
+                if hangup_received() {
+                    return Err(ReadlineEvent::Hangup);
+                }
+                if options.deadline.map(|deadline| Instant::now() >= deadline).unwrap_or(false) {
+                    result = Err(ReadlineEvent::Timeout);
+                    break;
+                }
+                if self.show_notifications(&mut cursor, &mut term_buffer, &mut stdout) {
+                    self.render(
+                        &mut editor,
+                        &mut cursor,
+                        &mut term_buffer,
+                        terminal_width as usize,
+                        &mut stdout,
+                    );
+                }
+                continue;
+            }
+            let key = match read_input() {
+                Some(Ok(Input::Key(key))) => key,
+                Some(Ok(input)) => {
+                    let forward = match input {
+                        Input::NextDirectory => true,
+                        _ => false,
+                    };
+                    if self.switch_directory(forward, &mut cursor, &mut term_buffer, &mut stdout) {
+                        self.render(
+                            &mut editor,
+                            &mut cursor,
+                            &mut term_buffer,
+                            terminal_width as usize,
+                            &mut stdout,
+                        );
+                    }
+                    continue;
+                }
+                _ => break,
+            };
+            if options.delimiter.map(|delimiter| key == Key::Char(delimiter)).unwrap_or(false) {
+                result = Ok(editor.buffer());
+                break;
+            }
             // input
-            if let Ok(key) = key {
+            {
                 match editor.handle_input(key) {
+                    ReadlineEvent::Continue if options.max_chars.map(|max| editor.len() >= max).unwrap_or(false) => {
+                        result = Ok(editor.buffer());
+                        break;
+                    }
                     ReadlineEvent::Continue => {}
                     ReadlineEvent::Done => {
                         result = Ok(editor.buffer());
@@ -124,8 +297,6 @@ impl Readline {
                     terminal_width = new_terminal_width;
                     terminal_height = new_terminal_height;
                 }
-            } else {
-                break;
             }
             // render
             self.render(
@@ -149,6 +320,111 @@ impl Readline {
     }
 }
 
+fn stdin_ready() -> bool {
+    let mut fds = [nix::poll::PollFd::new(stdin().as_raw_fd(), nix::poll::POLLIN)];
+    match nix::poll::poll(&mut fds, 0) {
+        Ok(0) | Err(nix::Error::Sys(nix::Errno::EINTR)) => false,
+        _ => true,
+    }
+}
+
+fn read_stdin(buf: &mut [u8]) -> io::Result<usize> {
+    loop {
+        match nix::unistd::read(stdin().as_raw_fd(), buf) {
+            Ok(count) => return Ok(count),
+            Err(nix::Error::Sys(nix::Errno::EINTR)) => {}
+            Err(nix::Error::Sys(errno)) => return Err(io::Error::from_raw_os_error(errno as i32)),
+            Err(_) => return Err(io::Error::new(io::ErrorKind::Other, "failed to read stdin")),
+        }
+    }
+}
+
+/// Prints lines above the editor, returning true if the editor needs to be drawn again.
+fn print_above(lines: &[String], cursor: &mut CursorManager, term_buffer: &mut TerminalBuffer, mut stdout: &mut Write) -> bool {
+    if lines.len() == 0 {
+        return false;
+    }
+    cursor.move_to(&mut stdout, 0, 0);
+    write!(stdout, "{}", termion::clear::AfterCursor).expect("failed to write to stdout");
+    for line in lines {
+        write!(stdout, "{}\r\n", line).expect("failed to write to stdout");
+    }
+    term_buffer.invalidate();
+    true
+}
+
+/// A key for the editor, or one of the directory history shortcuts the line editor handles
+/// itself.
+enum Input {
+    Key(Key),
+    PreviousDirectory,
+    NextDirectory,
+}
+
+/// Whether `sequence`, which starts with Esc, is a complete escape sequence. Alt-modified
+/// sequences may carry a second Esc in front.
+fn escape_sequence_complete(sequence: &[u8]) -> bool {
+    let sequence = if sequence.starts_with(b"\x1b\x1b") { &sequence[1..] } else { sequence };
+    match sequence.len() {
+        0 | 1 => false,
+        2 => sequence[1] != b'[' && sequence[1] != b'O',
+        _ => sequence[1] == b'O' || (sequence[sequence.len() - 1] >= 0x40 && sequence[sequence.len() - 1] <= 0x7E),
+    }
+}
+
+/// Reads the next key from stdin. termion does not know Alt-Left and Alt-Right, which
+/// terminals send as `ESC [1;3D` or `ESC ESC [D` and so on, so escape sequences are read in
+/// full and those two picked out before termion parses the rest. Events the editor cannot use
+/// are skipped rather than ending the line.
+fn read_input() -> Option<io::Result<Input>> {
+    loop {
+        let mut byte = [0u8];
+        match read_stdin(&mut byte) {
+            Ok(0) => return None,
+            Ok(_) => {}
+            Err(e) => return Some(Err(e)),
+        }
+        let mut sequence = vec![byte[0]];
+        if byte[0] == 0x1B {
+            while !escape_sequence_complete(&sequence) && stdin_ready() {
+                match read_stdin(&mut byte) {
+                    Ok(0) => break,
+                    Ok(_) => sequence.push(byte[0]),
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+            match &sequence[..] {
+                b"\x1b" => return Some(Ok(Input::Key(Key::Esc))),
+                b"\x1b[1;3D" | b"\x1b\x1b[D" => return Some(Ok(Input::PreviousDirectory)),
+                b"\x1b[1;3C" | b"\x1b\x1b[C" => return Some(Ok(Input::NextDirectory)),
+                _ => {}
+            }
+        }
+        // multibyte characters are read from stdin as termion needs them
+        let mut rest = sequence[1..].to_vec().into_iter().map(Ok).chain(UnbufferedStdin {});
+        if let Ok(Event::Key(key)) = termion::event::parse_event(sequence[0], &mut rest) {
+            return Some(Ok(Input::Key(key)));
+        }
+    }
+}
+
+/// Reads stdin one byte at a time without buffering, so that polling the file descriptor
+/// reliably tells whether another key is waiting.
+struct UnbufferedStdin {}
+
+impl Iterator for UnbufferedStdin {
+    type Item = io::Result<u8>;
+
+    fn next(&mut self) -> Option<io::Result<u8>> {
+        let mut byte = [0u8];
+        match read_stdin(&mut byte) {
+            Ok(0) => None,
+            Ok(_) => Some(Ok(byte[0])),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
 struct CursorManager {
     xpos: u16,
     ypos: u16,
@@ -219,6 +495,11 @@ impl TerminalBuffer {
         self.terminal_width = width;
     }
 
+    /// Forgets what is on screen so that the next render redraws every line.
+    pub fn invalidate(&mut self) {
+        self.last_rendered.clear();
+    }
+
     pub fn render(&mut self, writer: &mut Write) -> io::Result<()> {
         // precondition: cursor is at (0,0)
         let num_lines = self.buffer.len();
@@ -0,0 +1,151 @@
+use super::lexer;
+use super::tokens::*;
+use nom;
+use std::collections::HashMap;
+
+/// Expands aliases in command position (the first word of every simple command).
+///
+/// An alias is never expanded inside its own expansion, so `alias ls='ls -F'` works and
+/// chains like `a -> b -> a` stop instead of looping. If an expansion ends in a blank, the
+/// word following it is also checked for an alias.
+pub fn expand_aliases(line: &str, aliases: &HashMap<String, String>) -> String {
+    let mut active = Vec::new();
+    expand(line, aliases, &mut active)
+}
+
+fn expand(line: &str, aliases: &HashMap<String, String>, active: &mut Vec<String>) -> String {
+    let tokens = match lexer::lex(line) {
+        nom::IResult::Done(remaining, tokens) => {
+            if remaining.len() == 0 {
+                tokens
+            } else {
+                return line.to_owned();
+            }
+        }
+        _ => return line.to_owned(),
+    };
+    let mut result = String::new();
+    let mut last = 0;
+    let mut command_position = true;
+    let mut in_subshell = false;
+    for token in &tokens {
+        match token {
+            &Token::StringLiteral(ref components) => {
+                if command_position {
+                    if let Some((start, end)) = unquoted_word(line, components) {
+                        let word = &line[start..end];
+                        if word == "time" {
+                            // the keyword leaves the command it times in command position
+                            continue;
+                        }
+                        if let Some(value) = aliases.get(word) {
+                            if !active.iter().any(|a| a == word) {
+                                active.push(word.to_owned());
+                                let expanded = expand(value, aliases, active);
+                                active.pop();
+                                result.push_str(&line[last..start]);
+                                result.push_str(&expanded);
+                                last = end;
+                                command_position = expanded.ends_with(' ') || expanded.ends_with('\t');
+                                continue;
+                            }
+                        }
+                    }
+                }
+                command_position = false;
+            }
+            &Token::Pipe => command_position = true,
+            &Token::Subshell => {
+                in_subshell = !in_subshell;
+                command_position = in_subshell;
+            }
+            _ => command_position = false,
+        }
+    }
+    result.push_str(&line[last..]);
+    result
+}
+
+/// Returns the byte range of a word if it consists only of literal text that was written
+/// without quotes or escapes, since quoting any part of a word suppresses alias expansion.
+fn unquoted_word(line: &str, components: &[StringLiteralComponent]) -> Option<(usize, usize)> {
+    let base = line.as_ptr() as usize;
+    let mut range: Option<(usize, usize)> = None;
+    for component in components {
+        match component {
+            &StringLiteralComponent::Literal(s) => {
+                let start = (s.as_ptr() as usize).wrapping_sub(base);
+                if start > line.len() {
+                    return None;
+                }
+                range = match range {
+                    None => Some((start, start + s.len())),
+                    Some((first, end)) if end == start => Some((first, start + s.len())),
+                    _ => return None,
+                };
+            }
+            _ => return None,
+        }
+    }
+    match range {
+        Some((start, end)) => {
+            if start > 0 && (line[..start].ends_with('"') || line[..start].ends_with('\\')) {
+                None
+            } else {
+                Some((start, end))
+            }
+        }
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_with(line: &str, aliases: &[(&str, &str)]) -> String {
+        let aliases = aliases.iter().map(|&(name, value)| (name.to_owned(), value.to_owned())).collect();
+        expand_aliases(line, &aliases)
+    }
+
+    #[test]
+    fn test_command_position() {
+        let aliases = [("ll", "ls -l")];
+        assert_eq!(expand_with("ll /tmp", &aliases), "ls -l /tmp");
+        assert_eq!(expand_with("echo ll", &aliases), "echo ll");
+        assert_eq!(expand_with("cat x | ll", &aliases), "cat x | ls -l");
+        assert_eq!(expand_with("time ll", &aliases), "time ls -l");
+        assert_eq!(expand_with("nothing here", &aliases), "nothing here");
+    }
+
+    #[test]
+    fn test_quoted_words_are_not_expanded() {
+        let aliases = [("ll", "ls -l")];
+        // quoting only part of the word is enough
+        assert_eq!(expand_with("l\"l\" /tmp", &aliases), "l\"l\" /tmp");
+        assert_eq!(expand_with("\"ll\" /tmp", &aliases), "\"ll\" /tmp");
+        assert_eq!(expand_with("\\ll /tmp", &aliases), "\\ll /tmp");
+    }
+
+    #[test]
+    fn test_recursive_expansion() {
+        let aliases = [("l", "ll -a"), ("ll", "ls -l")];
+        assert_eq!(expand_with("l x", &aliases), "ls -l -a x");
+    }
+
+    #[test]
+    fn test_alias_not_expanded_in_itself() {
+        assert_eq!(expand_with("ls x", &[("ls", "ls -F")]), "ls -F x");
+        // a chain back to the first alias stops there
+        assert_eq!(expand_with("a", &[("a", "b"), ("b", "a")]), "a");
+    }
+
+    #[test]
+    fn test_trailing_blank_expands_next_word() {
+        let aliases = [("sudo", "sudo "), ("ll", "ls -l")];
+        assert_eq!(expand_with("sudo ll", &aliases), "sudo  ls -l");
+        assert_eq!(expand_with("ll ll", &aliases), "ls -l ll");
+        let aliases = [("nice", "nice -n5\t"), ("ll", "ls -l")];
+        assert_eq!(expand_with("nice ll", &aliases), "nice -n5\t ls -l");
+    }
+}
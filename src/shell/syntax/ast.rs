@@ -12,6 +12,7 @@ pub enum Expr<'a> {
 pub enum Argument<'a> {
     Redirect(RawFd, &'a [StringLiteralComponent<'a>]),
     RedirectFD(RawFd, RawFd),
+    Close(RawFd),
     Append(RawFd, &'a [StringLiteralComponent<'a>]),
     Input(RawFd, &'a [StringLiteralComponent<'a>]),
     Background,
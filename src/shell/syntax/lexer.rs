@@ -21,6 +21,27 @@ named!(redirect_fd_operator<&str, Token>,
        )
 );
 
+named!(input_fd_operator<&str, Token>,
+       do_parse!(
+           i: opt_res!(map_res!(digit, FromStr::from_str)) >>
+               tag!("<&") >>
+               j: map_res!(digit, FromStr::from_str) >>(Token::RedirectFD(i.unwrap_or(0), j))
+       )
+);
+
+named!(close_operator<&str, Token>,
+       alt_complete!(
+           do_parse!(
+               i: opt_res!(map_res!(digit, FromStr::from_str)) >>
+                   tag!(">&-") >> (Token::Close(i.unwrap_or(1)))
+           ) |
+           do_parse!(
+               i: opt_res!(map_res!(digit, FromStr::from_str)) >>
+                   tag!("<&-") >> (Token::Close(i.unwrap_or(0)))
+           )
+       )
+);
+
 named!(append_operator<&str, Token>,
        do_parse!(
            i: opt_res!(map_res!(digit, FromStr::from_str)) >>
@@ -63,8 +84,10 @@ named!(operator<&str, Token>,
        alt_complete!(
            ws!(pipe_operator) |
            ws!(append_operator) |
+           ws!(close_operator) |
            ws!(redirect_fd_operator) |
            ws!(redirect_operator) |
+           ws!(input_fd_operator) |
            ws!(input_operator) |
            ws!(appendall_operator) |
            ws!(redirectall_operator) |
@@ -82,6 +105,14 @@ named!(quoted_string<&str, Token>,
        )
 );
 
+// match_string rejects empty input, so `""` gets a parser of its own
+named!(empty_quoted_string<&str, Token>,
+       do_parse!(
+           tag!("\"\"") >>
+               (Token::StringLiteral(Vec::new()))
+       )
+);
+
 #[derive(Debug, Clone)]
 enum State {
     Base,
@@ -89,13 +120,12 @@ enum State {
     VarOpen,
     Escaped,
     InVar,
-    HomeExpansion,
     BraceOpen,
     InBrace,
     BraceSplit,
 }
 
-fn match_string<F>(input: &str, filter: F) -> IResult<&str, Token>
+fn match_string<F>(input: &str, quoted: bool, filter: F) -> IResult<&str, Token>
 where
     F: Fn(char) -> bool,
 {
@@ -113,7 +143,9 @@ where
                     end_position = i;
                     break;
                 }
-                if c == '\\' {
+                // inside double quotes a backslash only escapes the characters special there, so
+                // that escapes such as `\n` reach `printf` and `echo -e`
+                if c == '\\' && (!quoted || input[i + 1..].starts_with(|n: char| "\"\\$`".contains(n))) {
                     if last_base < i {
                         new_string_components.push(StringLiteralComponent::Literal(&input[last_base..i]));
                     }
@@ -125,24 +157,22 @@ where
                     }
                     state = State::ExpectVarOpen;
                 }
-                if c == '~' {
+                // as in other shells, only a leading unquoted `~` is the home directory, and
+                // braces are not expanded inside quotes
+                if c == '~' && i == 0 && !quoted {
                     if last_base < i {
                         new_string_components.push(StringLiteralComponent::Literal(&input[last_base..i]));
                     }
                     new_string_components.push(StringLiteralComponent::EnvVar("HOME"));
-                    state = State::HomeExpansion;
+                    last_base = i + 1;
                 }
-                if c == '{' {
+                if c == '{' && !quoted {
                     if last_base < i {
                         new_string_components.push(StringLiteralComponent::Literal(&input[last_base..i]));
                     }
                     state = State::BraceOpen;
                 }
             }
-            State::HomeExpansion => {
-                last_base = i;
-                state = State::Base;
-            }
             State::Escaped => {
                 last_base = i;
                 state = State::Base;
@@ -196,7 +226,6 @@ where
                 ));
             }
         }
-        State::HomeExpansion => {}
         _ => return IResult::Error(ErrorKind::IsNot),
     }
 
@@ -209,13 +238,13 @@ where
 }
 
 fn bare_string(input: &str) -> IResult<&str, Token> {
-    match_string(input, |c| {
+    match_string(input, false, |c| {
         char::is_whitespace(c) || c == '>' || c == '<' || c == '|' || c == '&' || c == '`'
     })
 }
 
 fn quoted_string_inner(input: &str) -> IResult<&str, Token> {
-    match_string(input, |c| c == '\"')
+    match_string(input, true, |c| c == '\"')
 }
 
 /*
@@ -240,6 +269,7 @@ named!(bare_string_consume<&str, Token>,
 named!(lex_one<&str, Token>,
        alt_complete!(
            ws!(operator) |
+           ws!(empty_quoted_string) |
            ws!(quoted_string) |
            ws!(bare_string)
        )
@@ -252,3 +282,52 @@ named!(lex_all<&str, Vec<Token>>,
 pub fn lex<'a>(s: &'a str) -> IResult<&str, Vec<Token<'a>>> {
     lex_all(s)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lex_words(line: &str) -> Vec<Token> {
+        match lex(line) {
+            IResult::Done(rest, tokens) => {
+                assert_eq!(rest, "");
+                tokens
+            }
+            other => panic!("failed to lex {:?}: {:?}", line, other),
+        }
+    }
+
+    fn word(components: Vec<StringLiteralComponent>) -> Token {
+        Token::StringLiteral(components)
+    }
+
+    #[test]
+    fn test_leading_tilde_is_home() {
+        assert_eq!(lex_words("~"), vec![word(vec![StringLiteralComponent::EnvVar("HOME")])]);
+        assert_eq!(
+            lex_words("cd ~/src"),
+            vec![
+                word(vec![StringLiteralComponent::Literal("cd")]),
+                word(vec![StringLiteralComponent::EnvVar("HOME"), StringLiteralComponent::Literal("/src")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tilde_inside_word_is_literal() {
+        assert_eq!(lex_words("a~b"), vec![word(vec![StringLiteralComponent::Literal("a~b")])]);
+        assert_eq!(lex_words("~/a~b"), vec![word(vec![StringLiteralComponent::EnvVar("HOME"), StringLiteralComponent::Literal("/a~b")])]);
+        // the regex operator of `[[`
+        assert_eq!(lex_words("=~"), vec![word(vec![StringLiteralComponent::Literal("=~")])]);
+    }
+
+    #[test]
+    fn test_quoted_tilde_and_braces_are_literal() {
+        assert_eq!(lex_words("\"~\""), vec![word(vec![StringLiteralComponent::Literal("~")])]);
+        assert_eq!(lex_words("\"a{b,c}\""), vec![word(vec![StringLiteralComponent::Literal("a{b,c}")])]);
+        assert_eq!(
+            lex_words("a{b,c}"),
+            vec![word(vec![StringLiteralComponent::Literal("a"), StringLiteralComponent::Brace(vec!["b", "c"])])]
+        );
+    }
+}
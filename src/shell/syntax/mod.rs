@@ -2,3 +2,4 @@ pub mod tokens;
 pub mod lexer;
 pub mod ast;
 pub mod parser;
+pub mod alias;
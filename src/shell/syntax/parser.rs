@@ -102,6 +102,9 @@ fn parse_one<'a>(t: &'a [Token]) -> Result<Expr<'a>, Error> {
             &Token::RedirectFD(fd1, fd2) => {
                 arguments.push(Argument::RedirectFD(fd1, fd2));
             }
+            &Token::Close(fd) => {
+                arguments.push(Argument::Close(fd));
+            }
             &Token::Background => {
                 arguments.push(Argument::Background);
             }
@@ -117,3 +120,25 @@ fn parse_one<'a>(t: &'a [Token]) -> Result<Expr<'a>, Error> {
 pub fn parse<'a>(t: &'a [Token]) -> Result<Expr<'a>, Error> {
     parse_one(t)
 }
+
+/// Splits off a leading `time` keyword, which applies to the whole pipeline after it.
+pub fn split_time_keyword<'a, 'b>(t: &'b [Token<'a>]) -> (bool, &'b [Token<'a>]) {
+    match t.first() {
+        Some(&Token::StringLiteral(ref components)) if components.len() == 1 && components[0] == StringLiteralComponent::Literal("time") => (true, &t[1..]),
+        _ => (false, t),
+    }
+}
+
+/// Returns the tokens between `[[` and `]]` if the line is a conditional expression, which is
+/// evaluated by the shell instead of being parsed as a command.
+pub fn split_conditional<'a, 'b>(t: &'b [Token<'a>]) -> Option<&'b [Token<'a>]> {
+    let is_word = |token: Option<&Token>, word: &str| match token {
+        Some(&Token::StringLiteral(ref components)) => components.len() == 1 && components[0] == StringLiteralComponent::Literal(word),
+        _ => false,
+    };
+    if t.len() >= 2 && is_word(t.first(), "[[") && is_word(t.last(), "]]") {
+        Some(&t[1..t.len() - 1])
+    } else {
+        None
+    }
+}
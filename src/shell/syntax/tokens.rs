@@ -6,6 +6,7 @@ pub enum Token<'a> {
     Pipe,
     Redirect(RawFd),
     RedirectFD(RawFd, RawFd),
+    Close(RawFd),
     Append(RawFd),
     RedirectAll,
     AppendAll,